@@ -0,0 +1,57 @@
+use async_trait::async_trait;
+use std::error::Error;
+
+use crate::Kuzzle;
+
+/// Source of fresh credentials, asked for a new token when a query is
+/// rejected because the stored one is invalid or expired. The query is then
+/// sent again once with the new token, and only fails if it is rejected
+/// again.
+///
+/// Queries rejected at the same time share a single call to `authenticate`,
+/// the other queries being sent with the rejected token until it returns.
+///
+/// # Example
+///
+/// ```
+/// use async_trait::async_trait;
+/// use kuzzle::auth::Authenticator;
+/// use kuzzle::protocols::InMemory;
+/// use kuzzle::{request, Kuzzle};
+/// use std::error::Error;
+///
+/// /// Log in again as an API user
+/// struct ApiUser {
+///     username: String,
+///     password: String,
+/// }
+///
+/// #[async_trait]
+/// impl Authenticator for ApiUser {
+///     async fn authenticate(&self, kuzzle: &Kuzzle) -> Result<String, Box<dyn Error>> {
+///         let login = request!({
+///             "controller": "auth",
+///             "action": "login",
+///             "body": {"username": self.username, "password": self.password}
+///         })?;
+///         let response = kuzzle.query(&login).await?;
+///
+///         match response.result.as_ref().and_then(|result| result["jwt"].as_str()) {
+///             Some(jwt) => Ok(jwt.to_string()),
+///             None => Err("Login failed".into()),
+///         }
+///     }
+/// }
+///
+/// let kuzzle = Kuzzle::new(InMemory::new());
+/// kuzzle.set_authenticator(ApiUser {
+///     username: "ingester".to_string(),
+///     password: "s3cr3t".to_string(),
+/// });
+/// ```
+#[async_trait]
+pub trait Authenticator: Send + Sync {
+    /// Obtain a new authentication token, e.g. by logging in again. Returning
+    /// an error makes the rejected query fail with it.
+    async fn authenticate(&self, kuzzle: &Kuzzle) -> Result<String, Box<dyn Error>>;
+}
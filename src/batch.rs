@@ -0,0 +1,103 @@
+use std::error::Error;
+
+use crate::types::Response;
+
+/// Outcome of `Kuzzle::query_batch`: the result of every request, in the
+/// order they were given. Some requests may fail while others succeed.
+///
+/// # Example
+///
+/// ```no_run
+/// use kuzzle::protocols::WebSocket;
+/// use kuzzle::{request, Kuzzle};
+///
+/// # async_std::task::block_on(async {
+/// let kuzzle = Kuzzle::new(WebSocket::new("localhost", None));
+/// kuzzle.connect().await.unwrap();
+///
+/// let requests = vec![
+///     request!({"controller": "server", "action": "now"}).unwrap(),
+///     request!({"controller": "server", "action": "info"}).unwrap(),
+/// ];
+///
+/// let batch = kuzzle.query_batch(&requests).await;
+/// for index in batch.failures() {
+///     println!("Request #{} failed", index);
+/// }
+/// # })
+/// ```
+#[derive(Debug)]
+pub struct BatchResponse {
+    pub results: Vec<Result<Response, Box<dyn Error>>>,
+}
+
+impl BatchResponse {
+    /// Whether every request was answered without error
+    pub fn is_success(&self) -> bool {
+        self.failures().is_empty()
+    }
+
+    /// Indexes of the requests which could not be sent, or were answered
+    /// with an error
+    pub fn failures(&self) -> Vec<usize> {
+        self.results
+            .iter()
+            .enumerate()
+            .filter(|(_, result)| match result {
+                Ok(response) => response.error.is_some(),
+                Err(_) => true,
+            })
+            .map(|(index, _)| index)
+            .collect()
+    }
+
+    /// Responses without error, along with the index of their request
+    pub fn responses(&self) -> impl Iterator<Item = (usize, &Response)> {
+        self.results
+            .iter()
+            .enumerate()
+            .filter_map(|(index, result)| match result {
+                Ok(response) if response.error.is_none() => Some((index, response)),
+                _ => None,
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocols::ProtocolError;
+    use serde_json::json;
+    use std::time::Duration;
+
+    fn response(status: u16) -> Result<Response, Box<dyn Error>> {
+        Ok(serde_json::from_value(json!({
+            "requestId": "foo",
+            "action": "get",
+            "controller": "document",
+            "status": status,
+            "error": match status {
+                200 => serde_json::Value::Null,
+                _ => json!({"status": status}),
+            }
+        }))?)
+    }
+
+    #[test]
+    fn should_report_partial_failures() {
+        let timeout: Box<dyn Error> = Box::new(ProtocolError::Timeout(Duration::from_secs(1)));
+        let batch = BatchResponse {
+            results: vec![response(200), response(404), Err(timeout), response(200)],
+        };
+
+        assert!(!batch.is_success());
+        assert_eq!(batch.failures(), vec![1, 2]);
+        assert_eq!(
+            batch
+                .responses()
+                .map(|(index, _)| index)
+                .collect::<Vec<_>>(),
+            vec![0, 3]
+        );
+    }
+}
@@ -0,0 +1,144 @@
+//! Synchronous client, for command-line tools and scripts which are not
+//! async. Each call blocks the current thread until it completes, the
+//! connection being driven in the background meanwhile.
+//!
+//! The blocking client must not be used from within an async runtime, which
+//! it would stall.
+
+use serde::de::DeserializeOwned;
+use std::error::Error;
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::batch::BatchResponse;
+use crate::protocols::{Protocol, State};
+use crate::runtime::Blocker;
+use crate::types::{Request, Response};
+use crate::QueryOptions;
+
+/// Blocking counterpart of `kuzzle::Kuzzle`. Its settings, which don't need
+/// to block, are available through `as_async`.
+///
+/// # Panics
+///
+/// Creating a client panics if the runtime driving it can't be started.
+///
+/// # Example
+///
+/// ```no_run
+/// use kuzzle::blocking::Kuzzle;
+/// use kuzzle::protocols::WebSocket;
+/// use kuzzle::request;
+///
+/// let kuzzle = Kuzzle::new(WebSocket::new("localhost", None));
+/// kuzzle.connect().unwrap();
+///
+/// let request = request!({"controller": "server", "action": "now"}).unwrap();
+/// let response = kuzzle.query(&request).unwrap();
+///
+/// println!("{:?}", response.result);
+/// ```
+#[derive(Clone)]
+pub struct Kuzzle {
+    inner: crate::Kuzzle,
+    runtime: Arc<Blocker>,
+}
+
+impl Kuzzle {
+    pub fn new<P>(protocol: P) -> Kuzzle
+    where
+        P: 'static + Protocol,
+    {
+        Kuzzle::from(crate::Kuzzle::new(protocol))
+    }
+
+    /// Async client wrapped, sharing the connection and settings
+    pub fn as_async(&self) -> &crate::Kuzzle {
+        &self.inner
+    }
+
+    pub fn connect(&self) -> Result<(), Box<dyn Error>> {
+        self.runtime.block_on(self.inner.connect())
+    }
+
+    pub fn disconnect(&self) -> Result<(), Box<dyn Error>> {
+        self.runtime.block_on(self.inner.disconnect())
+    }
+
+    /// See `kuzzle::Kuzzle::disconnect_graceful`
+    pub fn disconnect_graceful(&self, timeout: Duration) -> Result<(), Box<dyn Error>> {
+        self.runtime
+            .block_on(self.inner.disconnect_graceful(timeout))
+    }
+
+    pub fn state(&self) -> State {
+        self.inner.state()
+    }
+
+    /// See `kuzzle::Kuzzle::query`
+    pub fn query(&self, request: &Request) -> Result<Response, Box<dyn Error>> {
+        self.runtime.block_on(self.inner.query(request))
+    }
+
+    /// See `kuzzle::Kuzzle::query_with_options`
+    pub fn query_with_options(
+        &self,
+        request: &Request,
+        options: QueryOptions,
+    ) -> Result<Response, Box<dyn Error>> {
+        self.runtime
+            .block_on(self.inner.query_with_options(request, options))
+    }
+
+    /// See `kuzzle::Kuzzle::query_checked`
+    pub fn query_checked(&self, request: &Request) -> Result<Response, Box<dyn Error>> {
+        self.runtime.block_on(self.inner.query_checked(request))
+    }
+
+    /// See `kuzzle::Kuzzle::query_as`
+    pub fn query_as<T>(&self, request: &Request) -> Result<T, Box<dyn Error>>
+    where
+        T: DeserializeOwned,
+    {
+        self.runtime.block_on(self.inner.query_as(request))
+    }
+
+    /// See `kuzzle::Kuzzle::query_batch`
+    pub fn query_batch(&self, requests: &[Request]) -> BatchResponse {
+        self.runtime.block_on(self.inner.query_batch(requests))
+    }
+}
+
+impl From<crate::Kuzzle> for Kuzzle {
+    fn from(kuzzle: crate::Kuzzle) -> Self {
+        Kuzzle {
+            inner: kuzzle,
+            runtime: Arc::new(Blocker::new().expect("Failed to start the blocking runtime")),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocols::InMemory;
+    use crate::request;
+    use serde_json::json;
+
+    #[test]
+    fn should_query_synchronously() -> Result<(), Box<dyn Error>> {
+        let protocol = InMemory::new();
+        protocol.respond(json!({"result": {"now": 1234}}));
+
+        let kuzzle = Kuzzle::new(protocol.clone());
+        kuzzle.connect()?;
+        assert_eq!(kuzzle.state(), State::Connected);
+
+        let request = request!({"controller": "server", "action": "now"})?;
+        let response = kuzzle.query(&request)?;
+
+        assert_eq!(response.result, Some(json!({"now": 1234})));
+        assert_eq!(protocol.requests().len(), 1);
+        Ok(())
+    }
+}
@@ -0,0 +1,259 @@
+use serde_json::Value;
+use std::error::Error;
+use std::time::Duration;
+
+use crate::events::EventCallback;
+use crate::protocols::{Hosts, Protocol, ProtocolError, WebSocket, WebSocketOptions};
+#[cfg(not(feature = "wasm"))]
+use crate::protocols::{Http, HttpOptions, Mqtt, MqttOptions, ReconnectPolicy};
+use crate::Kuzzle;
+
+/// Transport used to reach the Kuzzle hosts
+enum Transport {
+    WebSocket(WebSocketOptions),
+    #[cfg(not(feature = "wasm"))]
+    Http(HttpOptions),
+    #[cfg(not(feature = "wasm"))]
+    Mqtt(MqttOptions),
+    Custom(Box<dyn Protocol>),
+}
+
+/// Configuration of a Kuzzle client, gathered in one place
+///
+/// # Example
+///
+/// ```
+/// use kuzzle::events::Event;
+/// use kuzzle::protocols::{ReconnectPolicy, WebSocketOptions};
+/// use kuzzle::Kuzzle;
+/// use serde_json::json;
+///
+/// let kuzzle = Kuzzle::builder()
+///     .host("localhost")
+///     .websocket(WebSocketOptions::new().ssl(true))
+///     .reconnect(ReconnectPolicy::new().max_retries(10))
+///     .default_index("nyc-open-data")
+///     .volatile(json!({"appVersion": "1.4.2"}))
+///     .on_event(Box::new(|event: &Event| println!("{:?}", event)))
+///     .build()
+///     .unwrap();
+///
+/// assert_eq!(kuzzle.default_index(), Some("nyc-open-data".to_string()));
+/// ```
+pub struct KuzzleBuilder {
+    hosts: Option<Hosts>,
+    transport: Transport,
+    #[cfg(not(feature = "wasm"))]
+    reconnect: Option<ReconnectPolicy>,
+    default_index: Option<String>,
+    default_collection: Option<String>,
+    jwt: Option<String>,
+    api_key: Option<String>,
+    volatile: Value,
+    request_timeout: Option<Duration>,
+    on_event: Vec<EventCallback>,
+}
+
+impl Default for KuzzleBuilder {
+    fn default() -> Self {
+        Self {
+            hosts: None,
+            transport: Transport::WebSocket(WebSocketOptions::new()),
+            #[cfg(not(feature = "wasm"))]
+            reconnect: None,
+            default_index: None,
+            default_collection: None,
+            jwt: None,
+            api_key: None,
+            volatile: Value::Null,
+            request_timeout: None,
+            on_event: Vec::new(),
+        }
+    }
+}
+
+impl KuzzleBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Kuzzle node, or nodes, to connect to
+    pub fn host<H: Into<Hosts>>(mut self, hosts: H) -> Self {
+        self.hosts = Some(hosts.into());
+        self
+    }
+
+    /// Connect using WebSocket, which is the default
+    pub fn websocket(mut self, options: WebSocketOptions) -> Self {
+        self.transport = Transport::WebSocket(options);
+        self
+    }
+
+    /// Connect using HTTP
+    #[cfg(not(feature = "wasm"))]
+    pub fn http(mut self, options: HttpOptions) -> Self {
+        self.transport = Transport::Http(options);
+        self
+    }
+
+    /// Connect using MQTT
+    #[cfg(not(feature = "wasm"))]
+    pub fn mqtt(mut self, options: MqttOptions) -> Self {
+        self.transport = Transport::Mqtt(options);
+        self
+    }
+
+    /// Use an already configured protocol, the hosts being ignored
+    pub fn protocol<P>(mut self, protocol: P) -> Self
+    where
+        P: 'static + Protocol,
+    {
+        self.transport = Transport::Custom(Box::new(protocol));
+        self
+    }
+
+    /// Re-establish lost WebSocket connections following the given policy
+    #[cfg(not(feature = "wasm"))]
+    pub fn reconnect(mut self, policy: ReconnectPolicy) -> Self {
+        self.reconnect = Some(policy);
+        self
+    }
+
+    /// Index of the queries giving a collection but no index
+    pub fn default_index(mut self, index: &str) -> Self {
+        self.default_index = Some(index.to_string());
+        self
+    }
+
+    /// Collection of the queries about documents or subscriptions giving
+    /// neither an index nor a collection
+    pub fn default_collection(mut self, collection: &str) -> Self {
+        self.default_collection = Some(collection.to_string());
+        self
+    }
+
+    /// Authentication token to send with every query not providing its own
+    pub fn jwt(mut self, jwt: &str) -> Self {
+        self.jwt = Some(jwt.to_string());
+        self
+    }
+
+    /// API key to send as token with every query not providing its own,
+    /// checked with `auth:checkToken` upon connection
+    pub fn api_key(mut self, api_key: &str) -> Self {
+        self.api_key = Some(api_key.to_string());
+        self
+    }
+
+    /// Volatile data attached to every query
+    pub fn volatile(mut self, volatile: Value) -> Self {
+        self.volatile = volatile;
+        self
+    }
+
+    /// Time after which the queries not giving their own timeout fail with
+    /// `ProtocolError::Timeout`
+    pub fn request_timeout(mut self, timeout: Duration) -> Self {
+        self.request_timeout = Some(timeout);
+        self
+    }
+
+    /// Register a callback invoked with every client event
+    pub fn on_event(mut self, callback: EventCallback) -> Self {
+        self.on_event.push(callback);
+        self
+    }
+
+    /// Create the client, ready to connect. Fails with `ProtocolError::NoHost`
+    /// if no host was given, unless a protocol was.
+    pub fn build(self) -> Result<Kuzzle, Box<dyn Error>> {
+        let protocol: Box<dyn Protocol> = match (self.transport, self.hosts) {
+            (Transport::Custom(protocol), _) => protocol,
+            (_, None) => return Err(Box::new(ProtocolError::NoHost)),
+            #[cfg(not(feature = "wasm"))]
+            (Transport::WebSocket(mut options), Some(hosts)) => {
+                if self.reconnect.is_some() {
+                    options.reconnect = self.reconnect;
+                }
+                Box::new(WebSocket::new(hosts, Some(options)))
+            }
+            #[cfg(feature = "wasm")]
+            (Transport::WebSocket(options), Some(hosts)) => {
+                Box::new(WebSocket::new(hosts, Some(options)))
+            }
+            #[cfg(not(feature = "wasm"))]
+            (Transport::Http(options), Some(hosts)) => Box::new(Http::new(hosts, Some(options))),
+            #[cfg(not(feature = "wasm"))]
+            (Transport::Mqtt(options), Some(hosts)) => Box::new(Mqtt::new(hosts, Some(options))),
+        };
+
+        let kuzzle = Kuzzle::with_protocol(protocol);
+        kuzzle.set_default_index(self.default_index);
+        kuzzle.set_default_collection(self.default_collection);
+        kuzzle.set_jwt(self.jwt);
+        if let Some(api_key) = self.api_key {
+            kuzzle.set_api_key(&api_key);
+        }
+        kuzzle.set_volatile(self.volatile);
+        kuzzle.set_request_timeout(self.request_timeout);
+        for callback in self.on_event {
+            kuzzle.on_event(callback);
+        }
+
+        Ok(kuzzle)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocols::{InMemory, State};
+    use serde_json::json;
+
+    #[test]
+    fn should_require_a_host() {
+        assert!(KuzzleBuilder::new().build().is_err());
+        assert!(KuzzleBuilder::new().host("localhost").build().is_ok());
+    }
+
+    #[async_std::test]
+    async fn should_check_the_api_key_upon_connection() -> Result<(), Box<dyn Error>> {
+        let protocol = InMemory::new();
+        protocol.respond(json!({"result": {"valid": true}}));
+
+        let kuzzle = KuzzleBuilder::new()
+            .protocol(protocol.clone())
+            .api_key("eyJhbGciOi")
+            .build()?;
+        kuzzle.connect().await?;
+
+        assert_eq!(kuzzle.jwt(), Some("eyJhbGciOi".to_string()));
+        assert_eq!(protocol.requests()[0]["action"], "checkToken");
+        assert_eq!(protocol.requests()[0]["body"]["token"], "eyJhbGciOi");
+
+        protocol.respond(json!({"result": {"valid": false}}));
+        assert!(kuzzle.login_with_api_key("invalid").await.is_err());
+        assert_eq!(kuzzle.jwt(), Some("eyJhbGciOi".to_string()));
+        Ok(())
+    }
+
+    #[test]
+    fn should_configure_the_client() -> Result<(), Box<dyn Error>> {
+        let kuzzle = KuzzleBuilder::new()
+            .protocol(InMemory::new())
+            .default_index("nyc-open-data")
+            .default_collection("yellow-taxi")
+            .jwt("eyJhbGciOi")
+            .volatile(json!({"appVersion": "1.4.2"}))
+            .request_timeout(Duration::from_secs(5))
+            .build()?;
+
+        assert_eq!(kuzzle.state(), State::Offline);
+        assert_eq!(kuzzle.default_index(), Some("nyc-open-data".to_string()));
+        assert_eq!(kuzzle.default_collection(), Some("yellow-taxi".to_string()));
+        assert_eq!(kuzzle.jwt(), Some("eyJhbGciOi".to_string()));
+        assert_eq!(kuzzle.volatile(), json!({"appVersion": "1.4.2"}));
+        assert_eq!(kuzzle.request_timeout(), Some(Duration::from_secs(5)));
+        Ok(())
+    }
+}
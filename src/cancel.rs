@@ -0,0 +1,113 @@
+use futures_channel::oneshot;
+use futures_util::future::{FutureExt, Shared};
+use std::fmt;
+use std::sync::{Arc, Mutex};
+
+/// Aborts the queries it is given to through `QueryOptions::cancel`, e.g. a
+/// long search the user is not interested in anymore. A cancelled query fails
+/// with `ProtocolError::Cancelled` and stops waiting for its response, freeing
+/// its slot among the requests in flight. Kuzzle has no way to interrupt a
+/// request it already received: the server still processes it.
+///
+/// Clones share the same state, so that the query can be cancelled from
+/// another task.
+///
+/// # Example
+///
+/// ```no_run
+/// use kuzzle::cancel::CancellationToken;
+/// use kuzzle::protocols::WebSocket;
+/// use kuzzle::{request, Kuzzle, QueryOptions};
+///
+/// # async_std::task::block_on(async {
+/// let kuzzle = Kuzzle::new(WebSocket::new("localhost", None));
+/// kuzzle.connect().await.unwrap();
+///
+/// let token = CancellationToken::new();
+/// let search = request!({
+///     "controller": "document",
+///     "action": "search",
+///     "index": "nyc-open-data",
+///     "collection": "yellow-taxi"
+/// })
+/// .unwrap();
+///
+/// let canceller = token.clone();
+/// async_std::task::spawn(async move {
+///     async_std::task::sleep(std::time::Duration::from_secs(1)).await;
+///     canceller.cancel();
+/// });
+///
+/// let options = QueryOptions::new().cancel(token);
+/// assert!(kuzzle.query_with_options(&search, options).await.is_err());
+/// # })
+/// ```
+#[derive(Clone)]
+pub struct CancellationToken {
+    trigger: Arc<Mutex<Option<oneshot::Sender<()>>>>,
+    cancelled: Shared<oneshot::Receiver<()>>,
+}
+
+impl Default for CancellationToken {
+    fn default() -> Self {
+        let (trigger, cancelled) = oneshot::channel();
+
+        Self {
+            trigger: Arc::new(Mutex::new(Some(trigger))),
+            cancelled: cancelled.shared(),
+        }
+    }
+}
+
+impl CancellationToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Abort the queries using this token, including the ones started later
+    pub fn cancel(&self) {
+        if let Some(trigger) = self.trigger.lock().unwrap().take() {
+            let _ = trigger.send(());
+        }
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.trigger.lock().unwrap().is_none()
+    }
+
+    /// Resolve once cancelled
+    pub(crate) fn cancelled(&self) -> Shared<oneshot::Receiver<()>> {
+        self.cancelled.clone()
+    }
+}
+
+impl PartialEq for CancellationToken {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.trigger, &other.trigger)
+    }
+}
+
+impl fmt::Debug for CancellationToken {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("CancellationToken")
+            .field("cancelled", &self.is_cancelled())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[async_std::test]
+    async fn should_resolve_once_cancelled() {
+        let token = CancellationToken::new();
+        let clone = token.clone();
+        assert!(!token.is_cancelled());
+
+        clone.cancel();
+
+        assert!(token.is_cancelled());
+        assert_eq!(token.cancelled().await, Ok(()));
+    }
+}
@@ -0,0 +1,448 @@
+use serde::de::{self, Deserializer};
+use serde::ser::Serializer;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::error::Error;
+
+use crate::controllers;
+use crate::request;
+use crate::{Kuzzle, QueryOptions};
+
+/// Kind of collection: stored ones hold documents while real-time ones only
+/// exist for subscriptions
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum CollectionType {
+    /// Either kind, only meaningful to filter a listing
+    All,
+    Stored,
+    Realtime,
+}
+
+/// Collection of an index, as listed
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct CollectionInfo {
+    pub name: String,
+    #[serde(rename = "type")]
+    pub kind: CollectionType,
+}
+
+/// Mapping of the fields of the documents of a collection
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct Mapping {
+    /// How fields missing from the properties are handled
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dynamic: Option<Dynamic>,
+    /// Metadata of the collection, free of form
+    #[serde(rename = "_meta", default, skip_serializing_if = "Option::is_none")]
+    pub meta: Option<Value>,
+    /// Definition of each field, e.g. `{"type": "keyword"}`
+    #[serde(default)]
+    pub properties: BTreeMap<String, Value>,
+}
+
+/// Policy applied to the fields of a document missing from the mapping
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Dynamic {
+    /// The fields are added to the mapping
+    True,
+    /// The fields are stored but not indexed
+    False,
+    /// The document is rejected
+    Strict,
+}
+
+impl Serialize for Dynamic {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(match self {
+            Dynamic::True => "true",
+            Dynamic::False => "false",
+            Dynamic::Strict => "strict",
+        })
+    }
+}
+
+impl<'de> Deserialize<'de> for Dynamic {
+    // Kuzzle sends either booleans or strings
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        match Value::deserialize(deserializer)? {
+            Value::Bool(true) => Ok(Dynamic::True),
+            Value::Bool(false) => Ok(Dynamic::False),
+            Value::String(policy) => match policy.as_str() {
+                "true" => Ok(Dynamic::True),
+                "false" => Ok(Dynamic::False),
+                "strict" => Ok(Dynamic::Strict),
+                _ => Err(de::Error::custom(format!(
+                    "Unknown dynamic policy {}",
+                    policy
+                ))),
+            },
+            value => Err(de::Error::custom(format!(
+                "Unknown dynamic policy {}",
+                value
+            ))),
+        }
+    }
+}
+
+/// Filter and pagination of `CollectionController::list`
+#[derive(Debug, Clone, PartialEq)]
+pub struct ListOptions {
+    pub kind: CollectionType,
+    pub from: Option<u64>,
+    pub size: Option<u64>,
+}
+
+impl Default for ListOptions {
+    fn default() -> Self {
+        Self {
+            kind: CollectionType::All,
+            from: None,
+            size: None,
+        }
+    }
+}
+
+impl ListOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Only list the collections of the given kind
+    pub fn kind(mut self, kind: CollectionType) -> Self {
+        self.kind = kind;
+        self
+    }
+
+    /// Offset of the first collection listed
+    pub fn from(mut self, from: u64) -> Self {
+        self.from = Some(from);
+        self
+    }
+
+    /// Maximum number of collections listed
+    pub fn size(mut self, size: u64) -> Self {
+        self.size = Some(size);
+        self
+    }
+}
+
+/// Methods of the `collection` controller, obtained with
+/// `Kuzzle::collection`. They fail with the `KuzzleError` Kuzzle answered
+/// with, if any.
+///
+/// # Example
+///
+/// ```no_run
+/// use kuzzle::protocols::WebSocket;
+/// use kuzzle::Kuzzle;
+///
+/// # async_std::task::block_on(async {
+/// let kuzzle = Kuzzle::new(WebSocket::new("localhost", None));
+/// kuzzle.connect().await.unwrap();
+///
+/// let collection = kuzzle.collection();
+/// if !collection.exists("nyc-open-data", "yellow-taxi").await.unwrap() {
+///     collection.create("nyc-open-data", "yellow-taxi", None).await.unwrap();
+/// }
+/// # })
+/// ```
+pub struct CollectionController<'a> {
+    kuzzle: &'a Kuzzle,
+}
+
+impl<'a> CollectionController<'a> {
+    pub(crate) fn new(kuzzle: &'a Kuzzle) -> Self {
+        Self { kuzzle }
+    }
+
+    /// Create a collection, with the given mappings if any, or update the
+    /// mappings of an existing one
+    pub async fn create(
+        &self,
+        index: &str,
+        collection: &str,
+        mappings: Option<Value>,
+    ) -> Result<(), Box<dyn Error>> {
+        let request = request!({
+            "controller": "collection",
+            "action": "create",
+            "index": index,
+            "collection": collection,
+            "body": mappings
+        })?;
+
+        controllers::query::<Value>(self.kuzzle, request, QueryOptions::new()).await?;
+        Ok(())
+    }
+
+    /// Delete a collection along with its documents
+    pub async fn delete(&self, index: &str, collection: &str) -> Result<(), Box<dyn Error>> {
+        let request = request!({
+            "controller": "collection",
+            "action": "delete",
+            "index": index,
+            "collection": collection
+        })?;
+
+        controllers::query::<Value>(self.kuzzle, request, QueryOptions::new()).await?;
+        Ok(())
+    }
+
+    /// Delete every document of a collection, keeping its mapping. Use the
+    /// `refresh` option for the collection to be seen empty right away.
+    pub async fn truncate(
+        &self,
+        index: &str,
+        collection: &str,
+        options: QueryOptions,
+    ) -> Result<(), Box<dyn Error>> {
+        let request = request!({
+            "controller": "collection",
+            "action": "truncate",
+            "index": index,
+            "collection": collection
+        })?;
+
+        controllers::query::<Value>(self.kuzzle, request, options).await?;
+        Ok(())
+    }
+
+    /// Make the changes made to a collection searchable right away, instead
+    /// of waiting for the next automatic refresh
+    pub async fn refresh(&self, index: &str, collection: &str) -> Result<(), Box<dyn Error>> {
+        let request = request!({
+            "controller": "collection",
+            "action": "refresh",
+            "index": index,
+            "collection": collection
+        })?;
+
+        controllers::query::<Value>(self.kuzzle, request, QueryOptions::new()).await?;
+        Ok(())
+    }
+
+    pub async fn exists(&self, index: &str, collection: &str) -> Result<bool, Box<dyn Error>> {
+        let request = request!({
+            "controller": "collection",
+            "action": "exists",
+            "index": index,
+            "collection": collection
+        })?;
+
+        controllers::query(self.kuzzle, request, QueryOptions::new()).await
+    }
+
+    pub async fn get_mapping(
+        &self,
+        index: &str,
+        collection: &str,
+    ) -> Result<Mapping, Box<dyn Error>> {
+        let request = request!({
+            "controller": "collection",
+            "action": "getMapping",
+            "index": index,
+            "collection": collection
+        })?;
+
+        controllers::query(self.kuzzle, request, QueryOptions::new()).await
+    }
+
+    /// Add fields to the mapping of a collection, or change its policy and
+    /// metadata, returning the updated mapping. Existing fields can't be
+    /// changed.
+    pub async fn update_mapping(
+        &self,
+        index: &str,
+        collection: &str,
+        mapping: &Mapping,
+    ) -> Result<Mapping, Box<dyn Error>> {
+        let request = request!({
+            "controller": "collection",
+            "action": "updateMapping",
+            "index": index,
+            "collection": collection,
+            "body": mapping
+        })?;
+
+        controllers::query(self.kuzzle, request, QueryOptions::new()).await
+    }
+
+    /// Collections of an index, sorted by name
+    pub async fn list(
+        &self,
+        index: &str,
+        options: ListOptions,
+    ) -> Result<Vec<CollectionInfo>, Box<dyn Error>> {
+        let request = request!({
+            "controller": "collection",
+            "action": "list",
+            "index": index,
+            "type": options.kind,
+            "from": options.from,
+            "size": options.size
+        })?;
+
+        let listed: Listed = controllers::query(self.kuzzle, request, QueryOptions::new()).await?;
+        Ok(listed.collections)
+    }
+}
+
+#[derive(Deserialize)]
+struct Listed {
+    collections: Vec<CollectionInfo>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocols::InMemory;
+    use crate::Refresh;
+    use serde_json::json;
+
+    #[async_std::test]
+    async fn should_manage_collections() -> Result<(), Box<dyn Error>> {
+        let protocol = InMemory::new();
+        protocol.respond(json!({"result": false}));
+        protocol.respond(json!({"result": {"acknowledged": true}}));
+        protocol.respond(json!({"result": {"acknowledged": true}}));
+
+        let kuzzle = Kuzzle::new(protocol.clone());
+        kuzzle.connect().await?;
+        let collection = kuzzle.collection();
+
+        assert!(!collection.exists("index", "yellow-taxi").await?);
+        let mappings = json!({"properties": {"licence": {"type": "keyword"}}});
+        collection
+            .create("index", "yellow-taxi", Some(mappings.clone()))
+            .await?;
+        collection.delete("index", "green-taxi").await?;
+
+        let requests = protocol.requests();
+        assert_eq!(requests[0]["action"], "exists");
+        assert_eq!(requests[1]["action"], "create");
+        assert_eq!(requests[1]["body"], mappings);
+        assert_eq!(requests[2]["action"], "delete");
+        assert_eq!(requests[2]["collection"], "green-taxi");
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn should_get_and_update_mappings() -> Result<(), Box<dyn Error>> {
+        let protocol = InMemory::new();
+        protocol.respond(json!({
+            "result": {
+                "dynamic": "strict",
+                "_meta": {"owner": "fleet"},
+                "properties": {"licence": {"type": "keyword"}}
+            }
+        }));
+        protocol.respond(json!({
+            "result": {
+                "dynamic": false,
+                "properties": {
+                    "licence": {"type": "keyword"},
+                    "rides": {"type": "integer"}
+                }
+            }
+        }));
+
+        let kuzzle = Kuzzle::new(protocol.clone());
+        kuzzle.connect().await?;
+        let collection = kuzzle.collection();
+
+        let mapping = collection.get_mapping("index", "yellow-taxi").await?;
+        assert_eq!(mapping.dynamic, Some(Dynamic::Strict));
+        assert_eq!(mapping.meta, Some(json!({"owner": "fleet"})));
+        assert_eq!(mapping.properties["licence"], json!({"type": "keyword"}));
+
+        let mut properties = BTreeMap::new();
+        properties.insert("rides".to_string(), json!({"type": "integer"}));
+        let changes = Mapping {
+            dynamic: Some(Dynamic::False),
+            meta: None,
+            properties,
+        };
+        let updated = collection
+            .update_mapping("index", "yellow-taxi", &changes)
+            .await?;
+
+        assert_eq!(updated.dynamic, Some(Dynamic::False));
+        assert_eq!(updated.properties.len(), 2);
+        assert_eq!(
+            protocol.requests()[1]["body"],
+            json!({"dynamic": "false", "properties": {"rides": {"type": "integer"}}})
+        );
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn should_truncate_collections() -> Result<(), Box<dyn Error>> {
+        let protocol = InMemory::new();
+        protocol.respond(json!({"result": {"acknowledged": true}}));
+
+        let kuzzle = Kuzzle::new(protocol.clone());
+        kuzzle.connect().await?;
+
+        let options = QueryOptions::new().refresh(Refresh::WaitFor);
+        kuzzle
+            .collection()
+            .truncate("index", "yellow-taxi", options)
+            .await?;
+
+        let request = &protocol.requests()[0];
+        assert_eq!(request["action"], "truncate");
+        assert_eq!(request["refresh"], "wait_for");
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn should_refresh_collections() -> Result<(), Box<dyn Error>> {
+        let protocol = InMemory::new();
+        protocol.respond(json!({"result": null}));
+
+        let kuzzle = Kuzzle::new(protocol.clone());
+        kuzzle.connect().await?;
+
+        kuzzle.collection().refresh("index", "yellow-taxi").await?;
+
+        let request = &protocol.requests()[0];
+        assert_eq!(request["controller"], "collection");
+        assert_eq!(request["action"], "refresh");
+        assert_eq!(request["collection"], "yellow-taxi");
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn should_list_collections() -> Result<(), Box<dyn Error>> {
+        let protocol = InMemory::new();
+        protocol.respond(json!({
+            "result": {
+                "collections": [
+                    {"name": "green-taxi", "type": "stored"},
+                    {"name": "yellow-taxi", "type": "stored"}
+                ],
+                "type": "stored",
+                "from": 0,
+                "size": 2
+            }
+        }));
+
+        let kuzzle = Kuzzle::new(protocol.clone());
+        kuzzle.connect().await?;
+
+        let options = ListOptions::new().kind(CollectionType::Stored).size(2);
+        let collections = kuzzle.collection().list("index", options).await?;
+
+        assert_eq!(collections.len(), 2);
+        assert_eq!(collections[1].name, "yellow-taxi");
+        assert_eq!(collections[1].kind, CollectionType::Stored);
+
+        let request = &protocol.requests()[0];
+        assert_eq!(request["type"], "stored");
+        assert_eq!(request["size"], 2);
+        assert!(request.get("from").is_none());
+        Ok(())
+    }
+}
@@ -0,0 +1,1612 @@
+use futures_util::stream::{self, Stream, StreamExt};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::convert::TryFrom;
+use std::error::Error;
+
+#[cfg(not(feature = "wasm"))]
+use crate::controllers::ExportFormat;
+use crate::controllers::{
+    self, BulkDocument, DeletionFailure, Documents, PartialResult, SearchOptions, SearchResult,
+};
+use crate::request;
+#[cfg(not(feature = "wasm"))]
+use crate::runtime::AsyncWriteExt;
+use crate::types::Request;
+use crate::{Kuzzle, QueryOptions};
+
+/// Documents fetched per page by `DocumentController::export`
+#[cfg(not(feature = "wasm"))]
+const EXPORT_PAGE_SIZE: u64 = 1000;
+
+/// How long the cursor of a search stream is kept alive between two pages,
+/// unless the search is sorted or given its own `scroll`
+const STREAM_SCROLL: &str = "1m";
+
+/// Number of documents per page of a search stream, unless the search is
+/// given its own `size`
+const STREAM_PAGE_SIZE: u64 = 100;
+
+/// Document as returned by Kuzzle, its content being deserialized into `T`,
+/// e.g. a struct of the application. When only some fields are read, `T`
+/// must tolerate the missing ones, e.g. with `Option` fields.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(try_from = "RawDocument", bound(deserialize = "T: DeserializeOwned"))]
+pub struct Document<T = Value> {
+    #[serde(rename = "_id")]
+    pub id: String,
+    #[serde(rename = "_version")]
+    pub version: u64,
+    /// Content of the document, only the updated fields on update unless
+    /// the `source` option is given
+    #[serde(rename = "_source")]
+    pub source: T,
+    /// Metadata Kuzzle keeps along with the content, if part of the response
+    #[serde(rename = "_kuzzle_info", skip_serializing_if = "Option::is_none")]
+    pub kuzzle_info: Option<KuzzleInfo>,
+}
+
+/// Document as sent by Kuzzle, its metadata mixed with its content
+#[derive(Deserialize)]
+struct RawDocument {
+    #[serde(rename = "_id")]
+    id: String,
+    #[serde(rename = "_version", default)]
+    version: u64,
+    #[serde(rename = "_source", default)]
+    source: Value,
+}
+
+impl<T: DeserializeOwned> TryFrom<RawDocument> for Document<T> {
+    type Error = serde_json::Error;
+
+    fn try_from(raw: RawDocument) -> Result<Self, Self::Error> {
+        // No content at all is read as an empty one
+        let mut source = match raw.source {
+            Value::Null => Value::Object(Map::new()),
+            source => source,
+        };
+        let kuzzle_info = match source.as_object_mut() {
+            Some(source) => source.remove("_kuzzle_info"),
+            None => None,
+        };
+
+        Ok(Document {
+            id: raw.id,
+            version: raw.version,
+            source: serde_json::from_value(source)?,
+            kuzzle_info: match kuzzle_info {
+                Some(info) => serde_json::from_value(info)?,
+                None => None,
+            },
+        })
+    }
+}
+
+/// Who created and last updated a document, and when
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct KuzzleInfo {
+    pub author: Option<String>,
+    /// Timestamp in milliseconds
+    pub created_at: Option<u64>,
+    pub updater: Option<String>,
+    /// Timestamp in milliseconds
+    pub updated_at: Option<u64>,
+}
+
+/// Options of an update, along with the options of the query itself
+#[derive(Debug, Clone, PartialEq)]
+pub struct UpdateOptions {
+    pub retry_on_conflict: Option<u32>,
+    pub query: QueryOptions,
+}
+
+impl Default for UpdateOptions {
+    fn default() -> Self {
+        Self {
+            retry_on_conflict: None,
+            query: QueryOptions::default(),
+        }
+    }
+}
+
+impl UpdateOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Retry updating a document modified meanwhile up to the given number
+    /// of times, instead of reporting it as failed right away
+    pub fn retry_on_conflict(mut self, retries: u32) -> Self {
+        self.retry_on_conflict = Some(retries);
+        self
+    }
+
+    pub fn query(mut self, options: QueryOptions) -> Self {
+        self.query = options;
+        self
+    }
+}
+
+/// Documents deleted by a query. Their content is only known if the query
+/// was given the `source` option.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(bound(deserialize = "T: DeserializeOwned"))]
+pub struct DeletedDocuments<T = Value> {
+    pub count: u64,
+    pub documents: Vec<Document<T>>,
+}
+
+impl<T> DeletedDocuments<T> {
+    pub fn ids(&self) -> Vec<&str> {
+        self.documents
+            .iter()
+            .map(|document| document.id.as_str())
+            .collect()
+    }
+}
+
+/// Verdict of the validation rules of a collection on a document
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Validation {
+    pub valid: bool,
+    /// What is wrong with each invalid field
+    #[serde(rename = "details", default)]
+    pub errors: Vec<String>,
+    /// Summary of the errors
+    pub description: Option<String>,
+}
+
+/// Methods of the `document` controller, obtained with `Kuzzle::document`.
+/// They fail with the `KuzzleError` Kuzzle answered with, if any.
+///
+/// # Example
+///
+/// ```no_run
+/// use kuzzle::protocols::WebSocket;
+/// use kuzzle::{Kuzzle, QueryOptions};
+/// use serde::{Deserialize, Serialize};
+///
+/// #[derive(Serialize, Deserialize, Debug)]
+/// struct Taxi {
+///     licence: String,
+/// }
+///
+/// # async_std::task::block_on(async {
+/// let kuzzle = Kuzzle::new(WebSocket::new("localhost", None));
+/// kuzzle.connect().await.unwrap();
+///
+/// let taxi = Taxi {
+///     licence: "B".to_string(),
+/// };
+/// let document = kuzzle
+///     .document()
+///     .create("nyc-open-data", "yellow-taxi", None, &taxi, QueryOptions::new())
+///     .await
+///     .unwrap();
+///
+/// println!("Created {} ({:?})", document.id, document.source);
+/// # })
+/// ```
+pub struct DocumentController<'a> {
+    kuzzle: &'a Kuzzle,
+}
+
+impl<'a> DocumentController<'a> {
+    pub(crate) fn new(kuzzle: &'a Kuzzle) -> Self {
+        Self { kuzzle }
+    }
+
+    /// Create a document, its id being generated by Kuzzle if none is given
+    pub async fn create<T>(
+        &self,
+        index: &str,
+        collection: &str,
+        id: Option<&str>,
+        body: &T,
+        options: QueryOptions,
+    ) -> Result<Document<T>, Box<dyn Error>>
+    where
+        T: Serialize + DeserializeOwned,
+    {
+        let body = serde_json::to_value(body)?;
+        let request = request!({
+            "controller": "document",
+            "action": "create",
+            "index": index,
+            "collection": collection,
+            "_id": id,
+            "body": body
+        })?;
+
+        self.query(request, options).await
+    }
+
+    pub async fn get<T>(
+        &self,
+        index: &str,
+        collection: &str,
+        id: &str,
+        options: QueryOptions,
+    ) -> Result<Document<T>, Box<dyn Error>>
+    where
+        T: DeserializeOwned,
+    {
+        let request = request!({
+            "controller": "document",
+            "action": "get",
+            "index": index,
+            "collection": collection,
+            "_id": id
+        })?;
+
+        self.query(request, options).await
+    }
+
+    /// Whether a document with the given id exists
+    pub async fn exists(
+        &self,
+        index: &str,
+        collection: &str,
+        id: &str,
+    ) -> Result<bool, Box<dyn Error>> {
+        let request = request!({
+            "controller": "document",
+            "action": "exists",
+            "index": index,
+            "collection": collection,
+            "_id": id
+        })?;
+
+        self.query(request, QueryOptions::new()).await
+    }
+
+    /// Apply a partial update to a document
+    pub async fn update<T>(
+        &self,
+        index: &str,
+        collection: &str,
+        id: &str,
+        body: Value,
+        options: UpdateOptions,
+    ) -> Result<Document<T>, Box<dyn Error>>
+    where
+        T: DeserializeOwned,
+    {
+        let request = request!({
+            "controller": "document",
+            "action": "update",
+            "index": index,
+            "collection": collection,
+            "_id": id,
+            "body": body,
+            "retryOnConflict": options.retry_on_conflict
+        })?;
+
+        self.query(request, options.query).await
+    }
+
+    /// Apply a partial update to a document, creating it from the changes
+    /// and the given default content if it doesn't exist
+    pub async fn upsert<T>(
+        &self,
+        index: &str,
+        collection: &str,
+        id: &str,
+        changes: Value,
+        default: Option<Value>,
+        options: UpdateOptions,
+    ) -> Result<Document<T>, Box<dyn Error>>
+    where
+        T: DeserializeOwned,
+    {
+        let request = request!({
+            "controller": "document",
+            "action": "upsert",
+            "index": index,
+            "collection": collection,
+            "_id": id,
+            "body": {"changes": changes, "default": default},
+            "retryOnConflict": options.retry_on_conflict
+        })?;
+
+        self.query(request, options.query).await
+    }
+
+    /// Check a document against the validation rules of a collection,
+    /// without writing it
+    pub async fn validate(
+        &self,
+        index: &str,
+        collection: &str,
+        body: Value,
+    ) -> Result<Validation, Box<dyn Error>> {
+        let request = request!({
+            "controller": "document",
+            "action": "validate",
+            "index": index,
+            "collection": collection,
+            "body": body
+        })?;
+
+        self.query(request, QueryOptions::new()).await
+    }
+
+    /// Replace the content of an existing document
+    pub async fn replace<T>(
+        &self,
+        index: &str,
+        collection: &str,
+        id: &str,
+        body: &T,
+        options: QueryOptions,
+    ) -> Result<Document<T>, Box<dyn Error>>
+    where
+        T: Serialize + DeserializeOwned,
+    {
+        let body = serde_json::to_value(body)?;
+        let request = request!({
+            "controller": "document",
+            "action": "replace",
+            "index": index,
+            "collection": collection,
+            "_id": id,
+            "body": body
+        })?;
+
+        self.query(request, options).await
+    }
+
+    /// Replace the content of a document, creating it if it doesn't exist
+    pub async fn create_or_replace<T>(
+        &self,
+        index: &str,
+        collection: &str,
+        id: &str,
+        body: &T,
+        options: QueryOptions,
+    ) -> Result<Document<T>, Box<dyn Error>>
+    where
+        T: Serialize + DeserializeOwned,
+    {
+        let body = serde_json::to_value(body)?;
+        let request = request!({
+            "controller": "document",
+            "action": "createOrReplace",
+            "index": index,
+            "collection": collection,
+            "_id": id,
+            "body": body
+        })?;
+
+        self.query(request, options).await
+    }
+
+    /// Remove the given fields from a document, nested ones being given as
+    /// paths such as `driver.licence`
+    pub async fn delete_fields<T>(
+        &self,
+        index: &str,
+        collection: &str,
+        id: &str,
+        fields: &[&str],
+        options: QueryOptions,
+    ) -> Result<Document<T>, Box<dyn Error>>
+    where
+        T: DeserializeOwned,
+    {
+        let request = request!({
+            "controller": "document",
+            "action": "deleteFields",
+            "index": index,
+            "collection": collection,
+            "_id": id,
+            "body": {"fields": fields}
+        })?;
+
+        self.query(request, options).await
+    }
+
+    /// Delete a document, returning its id
+    pub async fn delete(
+        &self,
+        index: &str,
+        collection: &str,
+        id: &str,
+        options: QueryOptions,
+    ) -> Result<String, Box<dyn Error>> {
+        let request = request!({
+            "controller": "document",
+            "action": "delete",
+            "index": index,
+            "collection": collection,
+            "_id": id
+        })?;
+
+        let deleted: Deleted = self.query(request, options).await?;
+        Ok(deleted.id)
+    }
+
+    /// Create several documents at once. Documents failing to be created,
+    /// e.g. because their id is taken, are reported without preventing the
+    /// others from being created.
+    pub async fn m_create<T>(
+        &self,
+        index: &str,
+        collection: &str,
+        documents: Vec<BulkDocument<T>>,
+        options: QueryOptions,
+    ) -> Result<PartialResult<Document<T>>, Box<dyn Error>>
+    where
+        T: Serialize + DeserializeOwned,
+    {
+        let documents = serde_json::to_value(documents)?;
+        let request = request!({
+            "controller": "document",
+            "action": "mCreate",
+            "index": index,
+            "collection": collection,
+            "body": {"documents": documents}
+        })?;
+
+        self.query(request, options).await
+    }
+
+    /// Get several documents at once, the ones not found being listed apart
+    pub async fn m_get<T>(
+        &self,
+        index: &str,
+        collection: &str,
+        ids: &[&str],
+        options: QueryOptions,
+    ) -> Result<Documents<T>, Box<dyn Error>>
+    where
+        T: DeserializeOwned,
+    {
+        let request = request!({
+            "controller": "document",
+            "action": "mGet",
+            "index": index,
+            "collection": collection,
+            "body": {"ids": ids}
+        })?;
+
+        self.query(request, options).await
+    }
+
+    /// Apply partial updates to several documents at once. Documents failing
+    /// to be updated are reported without preventing the others from being
+    /// updated.
+    pub async fn m_update<T>(
+        &self,
+        index: &str,
+        collection: &str,
+        documents: Vec<BulkDocument>,
+        options: UpdateOptions,
+    ) -> Result<PartialResult<Document<T>>, Box<dyn Error>>
+    where
+        T: DeserializeOwned,
+    {
+        let request = request!({
+            "controller": "document",
+            "action": "mUpdate",
+            "index": index,
+            "collection": collection,
+            "body": {"documents": documents},
+            "retryOnConflict": options.retry_on_conflict
+        })?;
+
+        self.query(request, options.query).await
+    }
+
+    /// Replace the content of several existing documents at once. Documents
+    /// failing to be replaced, e.g. because they don't exist, are reported
+    /// without preventing the others from being replaced.
+    pub async fn m_replace<T>(
+        &self,
+        index: &str,
+        collection: &str,
+        documents: Vec<BulkDocument<T>>,
+        options: QueryOptions,
+    ) -> Result<PartialResult<Document<T>>, Box<dyn Error>>
+    where
+        T: Serialize + DeserializeOwned,
+    {
+        let documents = serde_json::to_value(documents)?;
+        let request = request!({
+            "controller": "document",
+            "action": "mReplace",
+            "index": index,
+            "collection": collection,
+            "body": {"documents": documents}
+        })?;
+
+        self.query(request, options).await
+    }
+
+    /// Delete several documents at once, returning the ids of the deleted
+    /// ones and why the others could not be deleted
+    pub async fn m_delete(
+        &self,
+        index: &str,
+        collection: &str,
+        ids: &[&str],
+        options: QueryOptions,
+    ) -> Result<PartialResult<String, DeletionFailure>, Box<dyn Error>> {
+        let request = request!({
+            "controller": "document",
+            "action": "mDelete",
+            "index": index,
+            "collection": collection,
+            "body": {"ids": ids}
+        })?;
+
+        self.query(request, options).await
+    }
+
+    /// Count the documents matching the given search body, e.g.
+    /// `{"query": {...}}`, or every document if none is given
+    pub async fn count(
+        &self,
+        index: &str,
+        collection: &str,
+        query: Option<Value>,
+    ) -> Result<u64, Box<dyn Error>> {
+        let request = request!({
+            "controller": "document",
+            "action": "count",
+            "index": index,
+            "collection": collection,
+            "body": query
+        })?;
+
+        let counted: Counted = self.query(request, QueryOptions::new()).await?;
+        Ok(counted.count)
+    }
+
+    /// Delete the documents matching the given search body, e.g.
+    /// `{"query": {...}}`
+    pub async fn delete_by_query<T>(
+        &self,
+        index: &str,
+        collection: &str,
+        query: Value,
+        options: QueryOptions,
+    ) -> Result<DeletedDocuments<T>, Box<dyn Error>>
+    where
+        T: DeserializeOwned,
+    {
+        let request = request!({
+            "controller": "document",
+            "action": "deleteByQuery",
+            "index": index,
+            "collection": collection,
+            "body": query
+        })?;
+
+        let deleted: Matched<T> = self.query(request, options).await?;
+        Ok(DeletedDocuments {
+            count: deleted.documents.len() as u64,
+            documents: deleted.documents,
+        })
+    }
+
+    /// Apply the same partial update to the documents matching the given
+    /// search body, e.g. `{"query": {...}}`. Documents failing to be updated
+    /// are reported without preventing the others from being updated.
+    pub async fn update_by_query<T>(
+        &self,
+        index: &str,
+        collection: &str,
+        query: Value,
+        changes: Value,
+        options: UpdateOptions,
+    ) -> Result<PartialResult<Document<T>>, Box<dyn Error>>
+    where
+        T: DeserializeOwned,
+    {
+        let mut body = match query {
+            Value::Object(body) => body,
+            _ => return Err("The search body must be an object".into()),
+        };
+        body.insert("changes".to_string(), changes);
+
+        let request = request!({
+            "controller": "document",
+            "action": "updateByQuery",
+            "index": index,
+            "collection": collection,
+            "body": body,
+            "retryOnConflict": options.retry_on_conflict
+        })?;
+
+        self.query(request, options.query).await
+    }
+
+    /// Search documents, `query` being the search body, e.g.
+    /// `{"query": {...}, "sort": [...], "aggregations": {...}}`
+    pub async fn search(
+        &self,
+        index: &str,
+        collection: &str,
+        query: Value,
+        options: SearchOptions,
+    ) -> Result<SearchResult, Box<dyn Error>> {
+        let request = request!({
+            "controller": "document",
+            "action": "search",
+            "index": index,
+            "collection": collection,
+            "body": query,
+            "from": options.from,
+            "size": options.size,
+            "scroll": options.scroll
+        })?;
+
+        SearchResult::fetch(self.kuzzle, request, options.query, 0).await
+    }
+
+    /// Every document matching a search, fetched page after page as the
+    /// stream is consumed. Unless the search is sorted or given its own
+    /// `scroll`, it is scrolled with a cursor kept alive for a minute between
+    /// two pages. Pages hold 100 documents unless the search is given its own
+    /// `size`. The stream ends after the first error.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use futures_util::stream::StreamExt;
+    /// use kuzzle::controllers::SearchOptions;
+    /// use kuzzle::protocols::WebSocket;
+    /// use kuzzle::Kuzzle;
+    /// use serde::Deserialize;
+    /// use serde_json::json;
+    ///
+    /// #[derive(Deserialize, Debug)]
+    /// struct Taxi {
+    ///     licence: String,
+    /// }
+    ///
+    /// # async_std::task::block_on(async {
+    /// let kuzzle = Kuzzle::new(WebSocket::new("localhost", None));
+    /// kuzzle.connect().await.unwrap();
+    ///
+    /// let mut documents = Box::pin(kuzzle.document().search_stream::<Taxi>(
+    ///     "nyc-open-data",
+    ///     "yellow-taxi",
+    ///     json!({"query": {"match": {"licence": "B"}}}),
+    ///     SearchOptions::new().size(1000),
+    /// ));
+    ///
+    /// while let Some(document) = documents.next().await {
+    ///     println!("{:?}", document.unwrap().source);
+    /// }
+    /// # })
+    /// ```
+    pub fn search_stream<T>(
+        &self,
+        index: &str,
+        collection: &str,
+        query: Value,
+        mut options: SearchOptions,
+    ) -> impl Stream<Item = Result<Document<T>, Box<dyn Error>>>
+    where
+        T: DeserializeOwned,
+    {
+        if options.scroll.is_none() && query.get("sort").is_none() {
+            options.scroll = Some(STREAM_SCROLL.to_string());
+        }
+        if options.size.is_none() {
+            options.size = Some(STREAM_PAGE_SIZE);
+        }
+        let start = Pages::Search {
+            kuzzle: self.kuzzle.clone(),
+            index: index.to_string(),
+            collection: collection.to_string(),
+            query,
+            options,
+        };
+
+        stream::unfold(start, |pages| async move {
+            let page = match pages {
+                Pages::Search {
+                    kuzzle,
+                    index,
+                    collection,
+                    query,
+                    options,
+                } => {
+                    kuzzle
+                        .document()
+                        .search(&index, &collection, query, options)
+                        .await
+                }
+                Pages::Next(page) => match page.next().await {
+                    Ok(Some(next)) => Ok(next),
+                    Ok(None) => return None,
+                    Err(error) => Err(error),
+                },
+                Pages::Done => return None,
+            };
+
+            match page {
+                Ok(page) => {
+                    let documents: Vec<Result<Document<T>, Box<dyn Error>>> = page
+                        .hits
+                        .iter()
+                        .map(|hit| {
+                            let raw = RawDocument {
+                                id: hit.id.clone(),
+                                version: 0,
+                                source: hit.source.clone(),
+                            };
+                            Document::try_from(raw).map_err(|error| error.into())
+                        })
+                        .collect();
+                    Some((documents, Pages::Next(page)))
+                }
+                Err(error) => Some((vec![Err(error)], Pages::Done)),
+            }
+        })
+        .flat_map(stream::iter)
+    }
+
+    /// Write every document matching a search to the given writer, e.g. a
+    /// file, paging through them as they are written. Returns the number of
+    /// documents exported.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use kuzzle::controllers::ExportFormat;
+    /// use kuzzle::protocols::WebSocket;
+    /// use kuzzle::Kuzzle;
+    /// use serde_json::json;
+    ///
+    /// # async_std::task::block_on(async {
+    /// let kuzzle = Kuzzle::new(WebSocket::new("localhost", None));
+    /// kuzzle.connect().await.unwrap();
+    ///
+    /// let mut file = async_std::fs::File::create("yellow-taxi.jsonl").await.unwrap();
+    /// let exported = kuzzle
+    ///     .document()
+    ///     .export(
+    ///         "nyc-open-data",
+    ///         "yellow-taxi",
+    ///         json!({}),
+    ///         ExportFormat::JsonLines,
+    ///         &mut file,
+    ///     )
+    ///     .await
+    ///     .unwrap();
+    ///
+    /// println!("{} documents exported", exported);
+    /// # })
+    /// ```
+    #[cfg(not(feature = "wasm"))]
+    pub async fn export<W>(
+        &self,
+        index: &str,
+        collection: &str,
+        query: Value,
+        format: ExportFormat,
+        mut writer: W,
+    ) -> Result<u64, Box<dyn Error>>
+    where
+        W: AsyncWriteExt + Unpin,
+    {
+        let options = SearchOptions::new().size(EXPORT_PAGE_SIZE);
+        let documents = self.search_stream::<Value>(index, collection, query, options);
+        futures_util::pin_mut!(documents);
+
+        if let Some(header) = format.header() {
+            writer.write_all(header.as_bytes()).await?;
+        }
+
+        let mut exported = 0;
+        while let Some(document) = documents.next().await {
+            let document = document?;
+            let line = format.line(&document.id, &document.source);
+            writer.write_all(line.as_bytes()).await?;
+            exported += 1;
+        }
+
+        writer.flush().await?;
+        Ok(exported)
+    }
+
+    async fn query<T>(&self, request: Request, options: QueryOptions) -> Result<T, Box<dyn Error>>
+    where
+        T: DeserializeOwned,
+    {
+        controllers::query(self.kuzzle, request, options).await
+    }
+}
+
+/// Progress of a search stream
+enum Pages {
+    Search {
+        kuzzle: Kuzzle,
+        index: String,
+        collection: String,
+        query: Value,
+        options: SearchOptions,
+    },
+    Next(SearchResult),
+    Done,
+}
+
+#[derive(Deserialize)]
+#[serde(bound(deserialize = "T: DeserializeOwned"))]
+struct Matched<T> {
+    documents: Vec<Document<T>>,
+}
+
+#[derive(Deserialize)]
+struct Counted {
+    count: u64,
+}
+
+#[derive(Deserialize)]
+struct Deleted {
+    #[serde(rename = "_id")]
+    id: String,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocols::InMemory;
+    use crate::types::KuzzleError;
+    use crate::Refresh;
+    use serde_json::json;
+
+    #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+    struct Taxi {
+        licence: String,
+    }
+
+    #[async_std::test]
+    async fn should_create_and_get_documents() -> Result<(), Box<dyn Error>> {
+        let protocol = InMemory::new();
+        protocol.respond(json!({
+            "result": {"_id": "foo", "_version": 1, "_source": {"licence": "B"}}
+        }));
+        protocol.respond(json!({
+            "result": {
+                "_id": "foo",
+                "_version": 1,
+                "_source": {"licence": "B", "_kuzzle_info": {"author": "admin"}}
+            }
+        }));
+
+        let kuzzle = Kuzzle::new(protocol.clone());
+        kuzzle.connect().await?;
+        let document = kuzzle.document();
+
+        let taxi = Taxi {
+            licence: "B".to_string(),
+        };
+        let created = document
+            .create(
+                "nyc-open-data",
+                "yellow-taxi",
+                Some("foo"),
+                &taxi,
+                QueryOptions::new(),
+            )
+            .await?;
+        let fetched: Document<Taxi> = document
+            .get("nyc-open-data", "yellow-taxi", "foo", QueryOptions::new())
+            .await?;
+
+        assert_eq!(created.source, taxi);
+        assert_eq!(fetched.source, taxi);
+        assert_eq!(fetched.version, 1);
+        assert_eq!(
+            fetched.kuzzle_info.unwrap().author.as_deref(),
+            Some("admin")
+        );
+
+        let requests = protocol.requests();
+        assert_eq!(requests[0]["action"], "create");
+        assert_eq!(requests[0]["_id"], "foo");
+        assert_eq!(requests[0]["body"], json!({"licence": "B"}));
+        assert_eq!(requests[1]["action"], "get");
+        assert_eq!(requests[1]["index"], "nyc-open-data");
+        assert_eq!(requests[1]["collection"], "yellow-taxi");
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn should_read_partial_documents() -> Result<(), Box<dyn Error>> {
+        #[derive(Deserialize)]
+        struct Driver {
+            name: Option<String>,
+            licence: Option<String>,
+        }
+
+        let protocol = InMemory::new();
+        protocol.respond(json!({
+            "result": {"_id": "foo", "_version": 1, "_source": {"licence": "B"}}
+        }));
+        protocol.respond(json!({"result": {"_id": "foo", "_version": 1}}));
+
+        let kuzzle = Kuzzle::new(protocol.clone());
+        kuzzle.connect().await?;
+        let document = kuzzle.document();
+
+        let options = QueryOptions::new()
+            .source_includes(&["licence"])
+            .source_excludes(&["name"]);
+        let partial: Document<Driver> = document.get("index", "drivers", "foo", options).await?;
+        let options = QueryOptions::new().fetch_source(false);
+        let empty: Document<Driver> = document.get("index", "drivers", "foo", options).await?;
+
+        assert_eq!(partial.source.licence.as_deref(), Some("B"));
+        assert!(partial.source.name.is_none());
+        assert!(empty.source.licence.is_none());
+
+        let requests = protocol.requests();
+        assert_eq!(requests[0]["_source_includes"], json!(["licence"]));
+        assert_eq!(requests[0]["_source_excludes"], json!(["name"]));
+        assert!(requests[0].get("_source").is_none());
+        assert_eq!(requests[1]["_source"], false);
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn should_let_kuzzle_generate_the_id() -> Result<(), Box<dyn Error>> {
+        let protocol = InMemory::new();
+        protocol.respond(json!({"result": {"_id": "generated", "_version": 1}}));
+
+        let kuzzle = Kuzzle::new(protocol.clone());
+        kuzzle.connect().await?;
+
+        let created = kuzzle
+            .document()
+            .create("index", "collection", None, &json!({}), QueryOptions::new())
+            .await?;
+
+        assert_eq!(created.id, "generated");
+        assert!(protocol.requests()[0].get("_id").is_none());
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn should_search_documents() -> Result<(), Box<dyn Error>> {
+        let protocol = InMemory::new();
+        protocol.respond(json!({
+            "result": {
+                "hits": [
+                    {"_id": "foo", "_score": 1.5, "_source": {"licence": "B"}},
+                    {"_id": "bar", "_score": 0.5, "_source": {"licence": "B"}}
+                ],
+                "total": 42,
+                "aggregations": {"licences": {"buckets": []}},
+                "scrollId": "cursor"
+            }
+        }));
+
+        let kuzzle = Kuzzle::new(protocol.clone());
+        kuzzle.connect().await?;
+
+        let options = SearchOptions::new().size(2).scroll("30s");
+        let result = kuzzle
+            .document()
+            .search(
+                "nyc-open-data",
+                "yellow-taxi",
+                json!({"query": {"match": {"licence": "B"}}}),
+                options,
+            )
+            .await?;
+
+        assert_eq!(result.total, 42);
+        assert_eq!(result.hits.len(), 2);
+        assert_eq!(result.hits[0].id, "foo");
+        assert_eq!(result.hits[0].score, Some(1.5));
+        assert_eq!(result.hits[1].source, json!({"licence": "B"}));
+        assert_eq!(
+            result.aggregations,
+            Some(json!({"licences": {"buckets": []}}))
+        );
+        assert_eq!(result.scroll_id.as_deref(), Some("cursor"));
+
+        let request = &protocol.requests()[0];
+        assert_eq!(request["action"], "search");
+        assert_eq!(request["body"]["query"]["match"]["licence"], "B");
+        assert_eq!(request["size"], 2);
+        assert_eq!(request["scroll"], "30s");
+        assert!(request.get("from").is_none());
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn should_report_documents_failing_to_be_created() -> Result<(), Box<dyn Error>> {
+        let protocol = InMemory::new();
+        protocol.respond(json!({
+            "result": {
+                "successes": [{"_id": "foo", "_version": 1, "_source": {"licence": "B"}}],
+                "errors": [{
+                    "document": {"_id": "bar", "body": {"licence": "C"}},
+                    "reason": "document already exists",
+                    "status": 400
+                }]
+            }
+        }));
+
+        let kuzzle = Kuzzle::new(protocol.clone());
+        kuzzle.connect().await?;
+
+        let documents = vec![
+            BulkDocument::new(None, json!({"licence": "B"})),
+            BulkDocument::new(Some("bar"), json!({"licence": "C"})),
+        ];
+        let result = kuzzle
+            .document()
+            .m_create("index", "collection", documents, QueryOptions::new())
+            .await?;
+
+        assert!(!result.is_success());
+        assert_eq!(result.successes[0].id, "foo");
+        assert_eq!(result.errors[0].document["_id"], "bar");
+        assert_eq!(result.errors[0].status, 400);
+        assert_eq!(
+            protocol.requests()[0]["body"]["documents"],
+            json!([{"body": {"licence": "B"}}, {"_id": "bar", "body": {"licence": "C"}}])
+        );
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn should_list_the_missing_documents() -> Result<(), Box<dyn Error>> {
+        let protocol = InMemory::new();
+        protocol.respond(json!({
+            "result": {
+                "successes": [{"_id": "foo", "_version": 2, "_source": {"licence": "B"}}],
+                "errors": ["bar"]
+            }
+        }));
+
+        let kuzzle = Kuzzle::new(protocol.clone());
+        kuzzle.connect().await?;
+
+        let result: Documents = kuzzle
+            .document()
+            .m_get("index", "collection", &["foo", "bar"], QueryOptions::new())
+            .await?;
+
+        assert_eq!(result.documents.len(), 1);
+        assert_eq!(result.documents[0].version, 2);
+        assert_eq!(result.missing, vec!["bar"]);
+        assert_eq!(protocol.requests()[0]["body"]["ids"], json!(["foo", "bar"]));
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn should_update_and_replace_documents_in_bulk() -> Result<(), Box<dyn Error>> {
+        let protocol = InMemory::new();
+        protocol.respond(json!({
+            "result": {
+                "successes": [{"_id": "foo", "_version": 2, "_source": {"licence": "C"}}],
+                "errors": []
+            }
+        }));
+        protocol.respond(json!({
+            "result": {
+                "successes": [],
+                "errors": [{
+                    "document": {"_id": "bar", "body": {"licence": "D"}},
+                    "reason": "document not found",
+                    "status": 404
+                }]
+            }
+        }));
+
+        let kuzzle = Kuzzle::new(protocol.clone());
+        kuzzle.connect().await?;
+        let document = kuzzle.document();
+
+        let updated: PartialResult<Document> = document
+            .m_update(
+                "index",
+                "collection",
+                vec![BulkDocument::new(Some("foo"), json!({"licence": "C"}))],
+                UpdateOptions::new().retry_on_conflict(3),
+            )
+            .await?;
+        let replaced = document
+            .m_replace(
+                "index",
+                "collection",
+                vec![BulkDocument::new(Some("bar"), json!({"licence": "D"}))],
+                QueryOptions::new(),
+            )
+            .await?;
+
+        assert!(updated.is_success());
+        assert_eq!(updated.successes[0].version, 2);
+        assert_eq!(replaced.errors[0].reason, "document not found");
+        assert_eq!(replaced.errors[0].status, 404);
+
+        let requests = protocol.requests();
+        assert_eq!(requests[0]["action"], "mUpdate");
+        assert_eq!(requests[0]["retryOnConflict"], 3);
+        assert_eq!(requests[1]["action"], "mReplace");
+        assert!(requests[1].get("retryOnConflict").is_none());
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn should_report_documents_failing_to_be_deleted() -> Result<(), Box<dyn Error>> {
+        let protocol = InMemory::new();
+        protocol.respond(json!({
+            "result": {
+                "successes": ["foo"],
+                "errors": [{"_id": "bar", "reason": "document not found"}]
+            }
+        }));
+
+        let kuzzle = Kuzzle::new(protocol.clone());
+        kuzzle.connect().await?;
+
+        let result = kuzzle
+            .document()
+            .m_delete("index", "collection", &["foo", "bar"], QueryOptions::new())
+            .await?;
+
+        assert_eq!(result.successes, vec!["foo"]);
+        assert_eq!(result.errors[0].id, "bar");
+        assert_eq!(result.errors[0].reason, "document not found");
+        assert_eq!(protocol.requests()[0]["action"], "mDelete");
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn should_count_documents() -> Result<(), Box<dyn Error>> {
+        let protocol = InMemory::new();
+        protocol.respond(json!({"result": {"count": 42}}));
+        protocol.respond(json!({"result": {"count": 1234}}));
+
+        let kuzzle = Kuzzle::new(protocol.clone());
+        kuzzle.connect().await?;
+        let document = kuzzle.document();
+
+        let query = json!({"query": {"match": {"licence": "B"}}});
+        let matching = document.count("index", "collection", Some(query)).await?;
+        let all = document.count("index", "collection", None).await?;
+
+        assert_eq!(matching, 42);
+        assert_eq!(all, 1234);
+
+        let requests = protocol.requests();
+        assert_eq!(requests[0]["body"]["query"]["match"]["licence"], "B");
+        assert!(requests[1]["body"].is_null());
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn should_check_whether_documents_exist() -> Result<(), Box<dyn Error>> {
+        let protocol = InMemory::new();
+        protocol.respond(json!({"result": true}));
+        protocol.respond(json!({"result": false}));
+
+        let kuzzle = Kuzzle::new(protocol.clone());
+        kuzzle.connect().await?;
+        let document = kuzzle.document();
+
+        assert!(document.exists("index", "collection", "foo").await?);
+        assert!(!document.exists("index", "collection", "bar").await?);
+        assert_eq!(protocol.requests()[1]["_id"], "bar");
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn should_delete_documents_by_query() -> Result<(), Box<dyn Error>> {
+        let protocol = InMemory::new();
+        protocol.respond(json!({
+            "result": {
+                "documents": [
+                    {"_id": "foo", "_source": {"licence": "B"}},
+                    {"_id": "bar", "_source": {"licence": "B"}}
+                ]
+            }
+        }));
+
+        let kuzzle = Kuzzle::new(protocol.clone());
+        kuzzle.connect().await?;
+
+        let deleted: DeletedDocuments = kuzzle
+            .document()
+            .delete_by_query(
+                "index",
+                "collection",
+                json!({"query": {"match": {"licence": "B"}}}),
+                QueryOptions::new().source(true),
+            )
+            .await?;
+
+        assert_eq!(deleted.count, 2);
+        assert_eq!(deleted.ids(), vec!["foo", "bar"]);
+        assert_eq!(deleted.documents[1].source, json!({"licence": "B"}));
+
+        let request = &protocol.requests()[0];
+        assert_eq!(request["action"], "deleteByQuery");
+        assert_eq!(request["source"], true);
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn should_update_documents_by_query() -> Result<(), Box<dyn Error>> {
+        let protocol = InMemory::new();
+        protocol.respond(json!({
+            "result": {
+                "successes": [{"_id": "foo", "_version": 3, "_source": {"licence": "C"}}],
+                "errors": [{
+                    "document": {"_id": "bar", "_source": {"licence": "B"}},
+                    "reason": "version conflict",
+                    "status": 409
+                }]
+            }
+        }));
+
+        let kuzzle = Kuzzle::new(protocol.clone());
+        kuzzle.connect().await?;
+
+        let result: PartialResult<Document> = kuzzle
+            .document()
+            .update_by_query(
+                "index",
+                "collection",
+                json!({"query": {"match": {"licence": "B"}}}),
+                json!({"licence": "C"}),
+                UpdateOptions::new(),
+            )
+            .await?;
+
+        assert_eq!(result.successes[0].version, 3);
+        assert_eq!(result.errors[0].status, 409);
+        assert_eq!(
+            protocol.requests()[0]["body"],
+            json!({"query": {"match": {"licence": "B"}}, "changes": {"licence": "C"}})
+        );
+        assert!(protocol.requests()[0].get("retryOnConflict").is_none());
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn should_replace_documents() -> Result<(), Box<dyn Error>> {
+        let protocol = InMemory::new();
+        protocol.respond(json!({
+            "result": {"_id": "foo", "_version": 4, "_source": {"licence": "C"}}
+        }));
+        protocol.respond(json!({
+            "result": {"_id": "bar", "_version": 1, "_source": {"licence": "D"}, "created": true}
+        }));
+
+        let kuzzle = Kuzzle::new(protocol.clone());
+        kuzzle.connect().await?;
+        let document = kuzzle.document();
+
+        let options = QueryOptions::new().refresh(Refresh::WaitFor);
+        let replaced = document
+            .replace(
+                "index",
+                "collection",
+                "foo",
+                &json!({"licence": "C"}),
+                options,
+            )
+            .await?;
+        let created = document
+            .create_or_replace(
+                "index",
+                "collection",
+                "bar",
+                &json!({"licence": "D"}),
+                QueryOptions::new(),
+            )
+            .await?;
+
+        assert_eq!(replaced.version, 4);
+        assert_eq!(created.version, 1);
+
+        let requests = protocol.requests();
+        assert_eq!(requests[0]["action"], "replace");
+        assert_eq!(requests[0]["refresh"], "wait_for");
+        assert_eq!(requests[1]["action"], "createOrReplace");
+        assert!(requests[1]["refresh"].is_null());
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn should_upsert_documents() -> Result<(), Box<dyn Error>> {
+        let protocol = InMemory::new();
+        protocol.respond(json!({
+            "result": {"_id": "foo", "_version": 1, "_source": {"rides": 1, "licence": "B"}}
+        }));
+
+        let kuzzle = Kuzzle::new(protocol.clone());
+        kuzzle.connect().await?;
+
+        let upserted: Document = kuzzle
+            .document()
+            .upsert(
+                "index",
+                "collection",
+                "foo",
+                json!({"rides": 1}),
+                Some(json!({"licence": "B"})),
+                UpdateOptions::new().retry_on_conflict(5),
+            )
+            .await?;
+
+        assert_eq!(upserted.source, json!({"rides": 1, "licence": "B"}));
+        let request = &protocol.requests()[0];
+        assert_eq!(
+            request["body"],
+            json!({"changes": {"rides": 1}, "default": {"licence": "B"}})
+        );
+        assert_eq!(request["retryOnConflict"], 5);
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn should_validate_documents() -> Result<(), Box<dyn Error>> {
+        let protocol = InMemory::new();
+        protocol.respond(json!({"result": {"valid": true}}));
+        protocol.respond(json!({
+            "result": {
+                "valid": false,
+                "details": ["Field \"licence\": must be a string"],
+                "description": "The document does not match validation rules"
+            }
+        }));
+
+        let kuzzle = Kuzzle::new(protocol.clone());
+        kuzzle.connect().await?;
+        let document = kuzzle.document();
+
+        let valid = document
+            .validate("index", "collection", json!({"licence": "B"}))
+            .await?;
+        let invalid = document
+            .validate("index", "collection", json!({"licence": 42}))
+            .await?;
+
+        assert!(valid.valid);
+        assert!(valid.errors.is_empty());
+        assert!(!invalid.valid);
+        assert_eq!(invalid.errors, vec!["Field \"licence\": must be a string"]);
+        assert_eq!(protocol.requests()[1]["action"], "validate");
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn should_delete_fields() -> Result<(), Box<dyn Error>> {
+        let protocol = InMemory::new();
+        protocol.respond(json!({
+            "result": {
+                "_id": "foo",
+                "_version": 5,
+                "_source": {
+                    "driver": {"name": "Hugo"},
+                    "_kuzzle_info": {
+                        "author": "admin",
+                        "createdAt": 1000,
+                        "updater": "admin",
+                        "updatedAt": 2000
+                    }
+                }
+            }
+        }));
+
+        let kuzzle = Kuzzle::new(protocol.clone());
+        kuzzle.connect().await?;
+
+        let document: Document = kuzzle
+            .document()
+            .delete_fields(
+                "index",
+                "collection",
+                "foo",
+                &["driver.licence"],
+                QueryOptions::new().source(true),
+            )
+            .await?;
+
+        assert_eq!(document.version, 5);
+        assert_eq!(document.source["driver"], json!({"name": "Hugo"}));
+        let info = document.kuzzle_info.unwrap();
+        assert_eq!(info.updater.as_deref(), Some("admin"));
+        assert_eq!(info.updated_at, Some(2000));
+
+        let request = &protocol.requests()[0];
+        assert_eq!(request["action"], "deleteFields");
+        assert_eq!(request["body"]["fields"], json!(["driver.licence"]));
+        assert_eq!(request["source"], true);
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn should_stream_every_matching_document() -> Result<(), Box<dyn Error>> {
+        let protocol = InMemory::new();
+        protocol.respond(json!({
+            "result": {
+                "hits": [{"_id": "a", "_source": {}}, {"_id": "b", "_source": {}}],
+                "total": 3,
+                "scrollId": "cursor"
+            }
+        }));
+        protocol.respond(json!({
+            "result": {"hits": [{"_id": "c", "_source": {}}], "total": 3, "scrollId": "cursor"}
+        }));
+
+        let kuzzle = Kuzzle::new(protocol.clone());
+        kuzzle.connect().await?;
+
+        let documents: Vec<_> = kuzzle
+            .document()
+            .search_stream::<Value>("index", "collection", json!({}), SearchOptions::new())
+            .collect()
+            .await;
+        let ids: Vec<String> = documents
+            .into_iter()
+            .map(|document| document.map(|document| document.id))
+            .collect::<Result<_, _>>()?;
+
+        assert_eq!(ids, vec!["a", "b", "c"]);
+
+        let requests = protocol.requests();
+        assert_eq!(requests.len(), 2);
+        assert_eq!(requests[0]["scroll"], "1m");
+        assert_eq!(requests[1]["action"], "scroll");
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn should_stream_sorted_search_without_size() -> Result<(), Box<dyn Error>> {
+        let protocol = InMemory::new();
+        protocol.respond(json!({
+            "result": {
+                "hits": [{"_id": "a", "_source": {}}, {"_id": "b", "_source": {}}],
+                "total": 3
+            }
+        }));
+        protocol.respond(json!({
+            "result": {"hits": [{"_id": "c", "_source": {}}], "total": 3}
+        }));
+
+        let kuzzle = Kuzzle::new(protocol.clone());
+        kuzzle.connect().await?;
+
+        let documents: Vec<_> = kuzzle
+            .document()
+            .search_stream::<Value>(
+                "index",
+                "collection",
+                json!({"sort": ["_id"]}),
+                SearchOptions::new(),
+            )
+            .collect()
+            .await;
+        let ids: Vec<String> = documents
+            .into_iter()
+            .map(|document| document.map(|document| document.id))
+            .collect::<Result<_, _>>()?;
+
+        assert_eq!(ids, vec!["a", "b", "c"]);
+
+        let requests = protocol.requests();
+        assert_eq!(requests.len(), 2);
+        assert_eq!(requests[0]["size"], 100);
+        assert!(requests[0].get("scroll").is_none());
+        assert_eq!(requests[1]["body"]["search_after"], json!(["b"]));
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn should_end_the_stream_after_an_error() -> Result<(), Box<dyn Error>> {
+        let protocol = InMemory::new();
+        protocol.respond(json!({
+            "result": {"hits": [{"_id": "a", "_source": {}}], "total": 2, "scrollId": "cursor"}
+        }));
+        protocol.respond(json!({
+            "status": 404,
+            "error": {"status": 404, "message": "Scroll not found"}
+        }));
+
+        let kuzzle = Kuzzle::new(protocol.clone());
+        kuzzle.connect().await?;
+
+        let documents: Vec<_> = kuzzle
+            .document()
+            .search_stream::<Value>("index", "collection", json!({}), SearchOptions::new())
+            .collect()
+            .await;
+
+        assert_eq!(documents.len(), 2);
+        assert!(documents[0].is_ok());
+        assert!(documents[1].is_err());
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn should_export_documents_as_json_lines() -> Result<(), Box<dyn Error>> {
+        let protocol = InMemory::new();
+        protocol.respond(json!({
+            "result": {
+                "hits": [{"_id": "a", "_source": {"licence": "B"}}],
+                "total": 2,
+                "scrollId": "cursor"
+            }
+        }));
+        protocol.respond(json!({
+            "result": {
+                "hits": [{"_id": "b", "_source": {"licence": "C"}}],
+                "total": 2,
+                "scrollId": "cursor"
+            }
+        }));
+
+        let kuzzle = Kuzzle::new(protocol.clone());
+        kuzzle.connect().await?;
+
+        let mut output = Vec::new();
+        let exported = kuzzle
+            .document()
+            .export(
+                "index",
+                "collection",
+                json!({}),
+                ExportFormat::JsonLines,
+                &mut output,
+            )
+            .await?;
+
+        assert_eq!(exported, 2);
+        assert_eq!(
+            String::from_utf8(output)?,
+            "{\"_id\":\"a\",\"_source\":{\"licence\":\"B\"}}\n\
+             {\"_id\":\"b\",\"_source\":{\"licence\":\"C\"}}\n"
+        );
+        assert_eq!(protocol.requests()[0]["size"], 1000);
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn should_fail_with_the_kuzzle_error() -> Result<(), Box<dyn Error>> {
+        let protocol = InMemory::new();
+        protocol.respond(json!({"result": {"_id": "foo"}}));
+        protocol.respond(json!({
+            "status": 404,
+            "error": {"status": 404, "message": "Document not found"}
+        }));
+
+        let kuzzle = Kuzzle::new(protocol.clone());
+        kuzzle.connect().await?;
+        let document = kuzzle.document();
+
+        let deleted = document
+            .delete("index", "collection", "foo", QueryOptions::new())
+            .await?;
+        let error = document
+            .update::<Value>(
+                "index",
+                "collection",
+                "foo",
+                json!({"licence": "C"}),
+                UpdateOptions::new().retry_on_conflict(2),
+            )
+            .await
+            .unwrap_err();
+
+        assert_eq!(deleted, "foo");
+        assert_eq!(error.downcast_ref::<KuzzleError>().unwrap().status(), 404);
+        assert_eq!(protocol.requests()[1]["retryOnConflict"], 2);
+        Ok(())
+    }
+}
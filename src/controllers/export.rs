@@ -0,0 +1,71 @@
+use serde_json::{json, Value};
+
+/// Format of the documents exported by `DocumentController::export`
+#[derive(Debug, Clone, PartialEq)]
+pub enum ExportFormat {
+    /// One `{"_id": ..., "_source": ...}` JSON object per line
+    JsonLines,
+    /// The id of each document followed by the given fields, nested ones
+    /// being given as paths such as `driver.licence`. The first line lists
+    /// the columns.
+    Csv(Vec<String>),
+}
+
+impl ExportFormat {
+    /// Line preceding the documents, if any
+    pub(crate) fn header(&self) -> Option<String> {
+        match self {
+            ExportFormat::JsonLines => None,
+            ExportFormat::Csv(fields) => {
+                let mut columns = vec!["_id".to_string()];
+                columns.extend(fields.iter().map(|field| csv_cell(field)));
+                Some(format!("{}\n", columns.join(",")))
+            }
+        }
+    }
+
+    /// Line of a document
+    pub(crate) fn line(&self, id: &str, source: &Value) -> String {
+        match self {
+            ExportFormat::JsonLines => format!("{}\n", json!({"_id": id, "_source": source})),
+            ExportFormat::Csv(fields) => {
+                let mut cells = vec![csv_cell(id)];
+                cells.extend(fields.iter().map(|field| {
+                    let value = field.split('.').fold(source, |value, key| &value[key]);
+                    match value {
+                        Value::Null => String::new(),
+                        Value::String(value) => csv_cell(value),
+                        value => csv_cell(&value.to_string()),
+                    }
+                }));
+                format!("{}\n", cells.join(","))
+            }
+        }
+    }
+}
+
+/// Quote a CSV cell if needed, doubling the quotes it contains
+fn csv_cell(value: &str) -> String {
+    match value.contains(|c| c == ',' || c == '"' || c == '\n' || c == '\r') {
+        true => format!("\"{}\"", value.replace('"', "\"\"")),
+        false => value.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_format_csv_lines() {
+        let format = ExportFormat::Csv(vec!["licence".to_string(), "driver.name".to_string()]);
+        let source = json!({"licence": "B, C", "driver": {"name": "Hugo \"H\""}});
+
+        assert_eq!(format.header().unwrap(), "_id,licence,driver.name\n");
+        assert_eq!(
+            format.line("foo", &source),
+            "foo,\"B, C\",\"Hugo \"\"H\"\"\"\n"
+        );
+        assert_eq!(format.line("bar", &json!({})), "bar,,\n");
+    }
+}
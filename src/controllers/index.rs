@@ -0,0 +1,300 @@
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+
+use crate::controllers;
+use crate::request;
+use crate::types::KuzzleError;
+use crate::{Kuzzle, QueryOptions};
+
+/// Error Kuzzle answers with when creating an index which exists
+const INDEX_ALREADY_EXISTS: &str = "services.storage.index_already_exists";
+
+/// Outcome of `IndexController::m_delete`
+#[derive(Debug, Clone, PartialEq)]
+pub struct DeletedIndexes {
+    pub deleted: Vec<String>,
+    /// Indexes which don't exist or the user is not allowed to delete
+    pub skipped: Vec<String>,
+}
+
+/// Storage used by the indexes, in bytes
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct IndexStats {
+    pub indexes: Vec<IndexSize>,
+    pub size: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct IndexSize {
+    pub name: String,
+    pub size: u64,
+    pub collections: Vec<CollectionSize>,
+}
+
+impl IndexSize {
+    /// Documents of every collection of the index
+    pub fn document_count(&self) -> u64 {
+        self.collections
+            .iter()
+            .map(|collection| collection.document_count)
+            .sum()
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct CollectionSize {
+    pub name: String,
+    pub size: u64,
+    pub document_count: u64,
+}
+
+/// Methods of the `index` controller, obtained with `Kuzzle::index`. They
+/// fail with the `KuzzleError` Kuzzle answered with, if any.
+///
+/// # Example
+///
+/// ```no_run
+/// use kuzzle::protocols::WebSocket;
+/// use kuzzle::Kuzzle;
+///
+/// # async_std::task::block_on(async {
+/// let kuzzle = Kuzzle::new(WebSocket::new("localhost", None));
+/// kuzzle.connect().await.unwrap();
+///
+/// if !kuzzle.index().exists("nyc-open-data").await.unwrap() {
+///     kuzzle.index().create("nyc-open-data").await.unwrap();
+/// }
+/// # })
+/// ```
+pub struct IndexController<'a> {
+    kuzzle: &'a Kuzzle,
+}
+
+impl<'a> IndexController<'a> {
+    pub(crate) fn new(kuzzle: &'a Kuzzle) -> Self {
+        Self { kuzzle }
+    }
+
+    pub async fn create(&self, index: &str) -> Result<(), Box<dyn Error>> {
+        let request = request!({"controller": "index", "action": "create", "index": index})?;
+
+        controllers::query(self.kuzzle, request, QueryOptions::new()).await?;
+        Ok(())
+    }
+
+    /// Create an index unless it exists, returning whether it was created.
+    /// An index created meanwhile by another client is not an error.
+    pub async fn ensure(&self, index: &str) -> Result<bool, Box<dyn Error>> {
+        if self.exists(index).await? {
+            return Ok(false);
+        }
+
+        match self.create(index).await {
+            Ok(()) => Ok(true),
+            Err(error) => match error.downcast_ref::<KuzzleError>() {
+                Some(error) if error.id() == INDEX_ALREADY_EXISTS => Ok(false),
+                _ => Err(error),
+            },
+        }
+    }
+
+    /// Delete an index along with its collections and documents
+    pub async fn delete(&self, index: &str) -> Result<(), Box<dyn Error>> {
+        let request = request!({"controller": "index", "action": "delete", "index": index})?;
+
+        controllers::query(self.kuzzle, request, QueryOptions::new()).await?;
+        Ok(())
+    }
+
+    /// Delete several indexes at once, skipping the ones which can't be
+    /// deleted
+    pub async fn m_delete(&self, indexes: &[&str]) -> Result<DeletedIndexes, Box<dyn Error>> {
+        let request = request!({
+            "controller": "index",
+            "action": "mDelete",
+            "body": {"indexes": indexes}
+        })?;
+
+        let result: MultiDeleted =
+            controllers::query(self.kuzzle, request, QueryOptions::new()).await?;
+        let skipped = indexes
+            .iter()
+            .filter(|index| !result.deleted.iter().any(|deleted| deleted == *index))
+            .map(|index| index.to_string())
+            .collect();
+
+        Ok(DeletedIndexes {
+            deleted: result.deleted,
+            skipped,
+        })
+    }
+
+    /// Storage used by each index and collection
+    pub async fn stats(&self) -> Result<IndexStats, Box<dyn Error>> {
+        let request = request!({"controller": "index", "action": "stats"})?;
+
+        controllers::query(self.kuzzle, request, QueryOptions::new()).await
+    }
+
+    pub async fn exists(&self, index: &str) -> Result<bool, Box<dyn Error>> {
+        let request = request!({"controller": "index", "action": "exists", "index": index})?;
+
+        controllers::query(self.kuzzle, request, QueryOptions::new()).await
+    }
+
+    /// Names of the indexes the user is allowed to read
+    pub async fn list(&self) -> Result<Vec<String>, Box<dyn Error>> {
+        let request = request!({"controller": "index", "action": "list"})?;
+
+        let listed: Listed = controllers::query(self.kuzzle, request, QueryOptions::new()).await?;
+        Ok(listed.indexes)
+    }
+}
+
+#[derive(Deserialize)]
+struct MultiDeleted {
+    deleted: Vec<String>,
+}
+
+#[derive(Deserialize)]
+struct Listed {
+    indexes: Vec<String>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocols::InMemory;
+    use serde_json::json;
+
+    #[async_std::test]
+    async fn should_manage_indexes() -> Result<(), Box<dyn Error>> {
+        let protocol = InMemory::new();
+        protocol.respond(json!({"result": false}));
+        protocol.respond(json!({"result": {"acknowledged": true}}));
+        protocol.respond(json!({"result": {"indexes": ["nyc-open-data", "geonames"]}}));
+        protocol.respond(json!({"result": {"acknowledged": true}}));
+
+        let kuzzle = Kuzzle::new(protocol.clone());
+        kuzzle.connect().await?;
+        let index = kuzzle.index();
+
+        assert!(!index.exists("nyc-open-data").await?);
+        index.create("nyc-open-data").await?;
+        assert_eq!(index.list().await?, vec!["nyc-open-data", "geonames"]);
+        index.delete("geonames").await?;
+
+        let requests = protocol.requests();
+        let actions: Vec<_> = requests.iter().map(|request| &request["action"]).collect();
+        assert_eq!(actions, vec!["exists", "create", "list", "delete"]);
+        assert_eq!(requests[1]["index"], "nyc-open-data");
+        assert_eq!(requests[3]["index"], "geonames");
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn should_report_the_indexes_skipped() -> Result<(), Box<dyn Error>> {
+        let protocol = InMemory::new();
+        protocol.respond(json!({"result": {"deleted": ["nyc-open-data"]}}));
+
+        let kuzzle = Kuzzle::new(protocol.clone());
+        kuzzle.connect().await?;
+
+        let result = kuzzle
+            .index()
+            .m_delete(&["nyc-open-data", "restricted"])
+            .await?;
+
+        assert_eq!(result.deleted, vec!["nyc-open-data"]);
+        assert_eq!(result.skipped, vec!["restricted"]);
+        assert_eq!(
+            protocol.requests()[0]["body"]["indexes"],
+            json!(["nyc-open-data", "restricted"])
+        );
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn should_get_storage_statistics() -> Result<(), Box<dyn Error>> {
+        let protocol = InMemory::new();
+        protocol.respond(json!({
+            "result": {
+                "indexes": [{
+                    "name": "nyc-open-data",
+                    "size": 3000,
+                    "collections": [
+                        {"name": "yellow-taxi", "documentCount": 12, "size": 2000},
+                        {"name": "green-taxi", "documentCount": 30, "size": 1000}
+                    ]
+                }],
+                "size": 3000
+            }
+        }));
+
+        let kuzzle = Kuzzle::new(protocol.clone());
+        kuzzle.connect().await?;
+
+        let stats = kuzzle.index().stats().await?;
+
+        assert_eq!(stats.size, 3000);
+        assert_eq!(stats.indexes[0].name, "nyc-open-data");
+        assert_eq!(stats.indexes[0].collections[1].size, 1000);
+        assert_eq!(stats.indexes[0].document_count(), 42);
+        assert_eq!(protocol.requests()[0]["action"], "stats");
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn should_ensure_indexes_exist() -> Result<(), Box<dyn Error>> {
+        let protocol = InMemory::new();
+        protocol.respond(json!({"result": false}));
+        protocol.respond(json!({"result": {"acknowledged": true}}));
+        protocol.respond(json!({"result": true}));
+        protocol.respond(json!({"result": false}));
+        protocol.respond(json!({
+            "status": 412,
+            "error": {
+                "status": 412,
+                "id": "services.storage.index_already_exists",
+                "message": "Index already exists"
+            }
+        }));
+
+        let kuzzle = Kuzzle::new(protocol.clone());
+        kuzzle.connect().await?;
+        let index = kuzzle.index();
+
+        assert!(index.ensure("created").await?);
+        assert!(!index.ensure("existing").await?);
+        assert!(!index.ensure("created-meanwhile").await?);
+
+        let actions: Vec<_> = protocol
+            .requests()
+            .iter()
+            .map(|request| request["action"].clone())
+            .collect();
+        assert_eq!(
+            actions,
+            vec!["exists", "create", "exists", "exists", "create"]
+        );
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn should_fail_with_the_kuzzle_error() -> Result<(), Box<dyn Error>> {
+        let protocol = InMemory::new();
+        protocol.respond(json!({
+            "status": 412,
+            "error": {"status": 412, "message": "Index already exists"}
+        }));
+
+        let kuzzle = Kuzzle::new(protocol.clone());
+        kuzzle.connect().await?;
+
+        let error = kuzzle.index().create("nyc-open-data").await.unwrap_err();
+
+        assert_eq!(error.downcast_ref::<KuzzleError>().unwrap().status(), 412);
+        Ok(())
+    }
+}
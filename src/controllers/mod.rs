@@ -0,0 +1,47 @@
+//! Typed methods for the most common API actions, sparing the forging of
+//! requests by hand. Any other action is still available through
+//! `Kuzzle::query`.
+
+pub mod collection;
+pub mod document;
+#[cfg(not(feature = "wasm"))]
+pub mod export;
+pub mod index;
+pub mod multi;
+pub mod search;
+
+pub use self::collection::{
+    CollectionController, CollectionInfo, CollectionType, Dynamic, ListOptions, Mapping,
+};
+pub use self::document::{
+    DeletedDocuments, Document, DocumentController, KuzzleInfo, UpdateOptions, Validation,
+};
+#[cfg(not(feature = "wasm"))]
+pub use self::export::ExportFormat;
+pub use self::index::{CollectionSize, DeletedIndexes, IndexController, IndexSize, IndexStats};
+pub use self::multi::{BulkDocument, DeletionFailure, Documents, Failure, PartialResult};
+pub use self::search::{Hit, SearchOptions, SearchResult};
+
+use serde::de::DeserializeOwned;
+use std::error::Error;
+
+use crate::types::Request;
+use crate::{Kuzzle, QueryOptions};
+
+/// Send a query and deserialize its result, failing with the `KuzzleError`
+/// it was answered with, if any
+pub(crate) async fn query<T>(
+    kuzzle: &Kuzzle,
+    request: Request,
+    options: QueryOptions,
+) -> Result<T, Box<dyn Error>>
+where
+    T: DeserializeOwned,
+{
+    let response = kuzzle
+        .query_with_options(&request, options)
+        .await?
+        .error_for_status()?;
+
+    Ok(serde_json::from_value(response.result.unwrap_or_default())?)
+}
@@ -0,0 +1,66 @@
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::controllers::Document;
+
+/// Document written by a bulk action
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct BulkDocument<T = Value> {
+    /// Generated by Kuzzle on creation if none is given
+    #[serde(rename = "_id", default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+    pub body: T,
+}
+
+impl<T> BulkDocument<T> {
+    pub fn new(id: Option<&str>, body: T) -> Self {
+        Self {
+            id: id.map(str::to_string),
+            body,
+        }
+    }
+}
+
+/// Outcome of a bulk action, some documents being processed while others
+/// fail on their own
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct PartialResult<T, E = Failure> {
+    pub successes: Vec<T>,
+    pub errors: Vec<E>,
+}
+
+impl<T, E> PartialResult<T, E> {
+    /// Whether every document was processed
+    pub fn is_success(&self) -> bool {
+        self.errors.is_empty()
+    }
+}
+
+/// Document a bulk action failed to process, and why
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Failure {
+    /// Document as it was given
+    pub document: Value,
+    pub reason: String,
+    #[serde(default)]
+    pub status: u16,
+}
+
+/// Document a bulk deletion failed to delete, and why
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct DeletionFailure {
+    #[serde(rename = "_id")]
+    pub id: String,
+    pub reason: String,
+}
+
+/// Documents fetched at once, and the ids of the ones not found
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(bound(deserialize = "T: DeserializeOwned"))]
+pub struct Documents<T = Value> {
+    #[serde(rename = "successes")]
+    pub documents: Vec<Document<T>>,
+    #[serde(rename = "errors")]
+    pub missing: Vec<String>,
+}
@@ -0,0 +1,344 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::error::Error;
+use std::fmt;
+use uuid::Uuid;
+
+use crate::types::Request;
+use crate::{Kuzzle, QueryOptions};
+
+/// Pagination of a search, along with the options of the query itself
+#[derive(Debug, Clone, PartialEq)]
+pub struct SearchOptions {
+    pub from: Option<u64>,
+    pub size: Option<u64>,
+    pub scroll: Option<String>,
+    pub query: QueryOptions,
+}
+
+impl Default for SearchOptions {
+    fn default() -> Self {
+        Self {
+            from: None,
+            size: None,
+            scroll: None,
+            query: QueryOptions::default(),
+        }
+    }
+}
+
+impl SearchOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Offset of the first hit
+    pub fn from(mut self, from: u64) -> Self {
+        self.from = Some(from);
+        self
+    }
+
+    /// Maximum number of hits per page
+    pub fn size(mut self, size: u64) -> Self {
+        self.size = Some(size);
+        self
+    }
+
+    /// Keep a cursor alive for the given time, e.g. `30s`, to fetch the
+    /// following pages consistently
+    pub fn scroll(mut self, scroll: &str) -> Self {
+        self.scroll = Some(scroll.to_string());
+        self
+    }
+
+    pub fn query(mut self, options: QueryOptions) -> Self {
+        self.query = options;
+        self
+    }
+}
+
+/// Page of documents matching a search
+///
+/// # Example
+///
+/// ```no_run
+/// use kuzzle::controllers::SearchOptions;
+/// use kuzzle::protocols::WebSocket;
+/// use kuzzle::Kuzzle;
+/// use serde_json::json;
+///
+/// # async_std::task::block_on(async {
+/// let kuzzle = Kuzzle::new(WebSocket::new("localhost", None));
+/// kuzzle.connect().await.unwrap();
+///
+/// let mut page = kuzzle
+///     .document()
+///     .search(
+///         "nyc-open-data",
+///         "yellow-taxi",
+///         json!({"query": {"match": {"licence": "B"}}}),
+///         SearchOptions::new().size(100).scroll("30s"),
+///     )
+///     .await
+///     .unwrap();
+///
+/// loop {
+///     for hit in &page.hits {
+///         println!("{}: {:?}", hit.id, hit.source);
+///     }
+///
+///     page = match page.next().await.unwrap() {
+///         Some(next) => next,
+///         None => break,
+///     };
+/// }
+/// # })
+/// ```
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SearchResult {
+    pub hits: Vec<Hit>,
+    /// Number of documents matching, all pages included
+    pub total: u64,
+    pub aggregations: Option<Value>,
+    /// Cursor to the next page, if the search was given a `scroll`
+    #[serde(rename = "scrollId")]
+    pub scroll_id: Option<String>,
+    #[serde(skip)]
+    cursor: Option<Cursor>,
+}
+
+/// Document matching a search
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Hit {
+    #[serde(rename = "_id")]
+    pub id: String,
+    #[serde(rename = "_score")]
+    pub score: Option<f64>,
+    #[serde(rename = "_source", default)]
+    pub source: Value,
+    pub highlight: Option<Value>,
+}
+
+/// Search a result comes from, to fetch the following page
+#[derive(Clone)]
+struct Cursor {
+    kuzzle: Kuzzle,
+    request: Request,
+    options: QueryOptions,
+    /// Hits fetched so far, this page included
+    fetched: u64,
+}
+
+impl fmt::Debug for Cursor {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Cursor")
+            .field("request", &self.request)
+            .field("fetched", &self.fetched)
+            .finish()
+    }
+}
+
+impl SearchResult {
+    /// Send a search and remember it, so that `next` can fetch the following
+    /// page
+    pub(crate) async fn fetch(
+        kuzzle: &Kuzzle,
+        request: Request,
+        options: QueryOptions,
+        fetched: u64,
+    ) -> Result<SearchResult, Box<dyn Error>> {
+        let response = kuzzle
+            .query_with_options(&request, options.clone())
+            .await?
+            .error_for_status()?;
+
+        let mut result: SearchResult = serde_json::from_value(response.result.unwrap_or_default())?;
+        result.cursor = Some(Cursor {
+            kuzzle: kuzzle.clone(),
+            fetched: fetched + result.hits.len() as u64,
+            request,
+            options,
+        });
+        Ok(result)
+    }
+
+    /// Fetch the following page, or `None` once every hit was fetched. The
+    /// page is fetched with `document:scroll` if the search was given a
+    /// `scroll`, else after the last hit if the search is sorted, else from
+    /// the offset of the next hit.
+    pub async fn next(&self) -> Result<Option<SearchResult>, Box<dyn Error>> {
+        let cursor = match &self.cursor {
+            Some(cursor) => cursor,
+            None => return Ok(None),
+        };
+        let offset = cursor.request.from.unwrap_or(0);
+        if self.hits.is_empty() || offset + cursor.fetched >= self.total {
+            return Ok(None);
+        }
+
+        let mut request = cursor.request.clone();
+        request.request_id = Uuid::new_v4().to_string();
+
+        let sort = cursor
+            .request
+            .body
+            .as_ref()
+            .and_then(|body| body.get("sort"))
+            .cloned();
+
+        match (&self.scroll_id, sort, cursor.request.size) {
+            (Some(scroll_id), _, _) => {
+                request.action = "scroll".to_string();
+                request.scroll_id = Some(scroll_id.clone());
+                request.index = None;
+                request.collection = None;
+                request.body = None;
+                request.from = None;
+                request.size = None;
+            }
+            (None, Some(sort), Some(_)) => {
+                let last = &self.hits[self.hits.len() - 1];
+                if let Some(Value::Object(body)) = request.body.as_mut() {
+                    body.insert("search_after".to_string(), search_after(&sort, last));
+                }
+                request.from = None;
+            }
+            (None, None, Some(_)) => {
+                request.from = Some(offset + cursor.fetched);
+            }
+            (None, _, None) => {
+                return Err("No scroll, sort or size to fetch the next page with".into());
+            }
+        }
+
+        let mut next = SearchResult::fetch(
+            &cursor.kuzzle,
+            request,
+            cursor.options.clone(),
+            cursor.fetched,
+        )
+        .await?;
+        // Later pages are all fetched the same way as this one
+        if let Some(next_cursor) = next.cursor.as_mut() {
+            next_cursor.request = cursor.request.clone();
+        }
+        Ok(Some(next))
+    }
+}
+
+/// Values of the sort fields of a hit, to search after it
+fn search_after(sort: &Value, hit: &Hit) -> Value {
+    let fields = match sort {
+        Value::Array(fields) => fields.clone(),
+        field => vec![field.clone()],
+    };
+
+    fields
+        .iter()
+        .map(|field| {
+            let name = match field {
+                Value::Object(field) => field.keys().next().cloned().unwrap_or_default(),
+                field => field.as_str().unwrap_or_default().to_string(),
+            };
+
+            match name.as_str() {
+                "_id" => Value::from(hit.id.clone()),
+                _ => name
+                    .split('.')
+                    .fold(&hit.source, |value, key| &value[key])
+                    .clone(),
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocols::InMemory;
+    use serde_json::json;
+
+    fn page(ids: &[&str], total: u64, scroll_id: Option<&str>) -> Value {
+        let hits: Vec<Value> = ids
+            .iter()
+            .map(|id| json!({"_id": id, "_source": {"rank": id}}))
+            .collect();
+
+        json!({"result": {"hits": hits, "total": total, "scrollId": scroll_id}})
+    }
+
+    async fn search(
+        protocol: &InMemory,
+        query: Value,
+        options: SearchOptions,
+    ) -> Result<SearchResult, Box<dyn Error>> {
+        let kuzzle = Kuzzle::new(protocol.clone());
+        kuzzle.connect().await?;
+
+        kuzzle
+            .document()
+            .search("index", "collection", query, options)
+            .await
+    }
+
+    #[async_std::test]
+    async fn should_scroll_to_the_next_pages() -> Result<(), Box<dyn Error>> {
+        let protocol = InMemory::new();
+        protocol.respond(page(&["a", "b"], 3, Some("first")));
+        protocol.respond(page(&["c"], 3, Some("second")));
+
+        let options = SearchOptions::new().size(2).scroll("30s");
+        let first = search(&protocol, json!({}), options).await?;
+        let second = first.next().await?.unwrap();
+
+        assert_eq!(second.hits[0].id, "c");
+        assert!(second.next().await?.is_none());
+
+        let requests = protocol.requests();
+        assert_eq!(requests.len(), 2);
+        assert_eq!(requests[1]["action"], "scroll");
+        assert_eq!(requests[1]["scrollId"], "first");
+        assert_eq!(requests[1]["scroll"], "30s");
+        assert_ne!(requests[1]["requestId"], requests[0]["requestId"]);
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn should_search_after_the_last_hit() -> Result<(), Box<dyn Error>> {
+        let protocol = InMemory::new();
+        protocol.respond(page(&["a", "b"], 4, None));
+        protocol.respond(page(&["c", "d"], 4, None));
+
+        let query = json!({"sort": [{"rank": "asc"}, "_id"]});
+        let first = search(&protocol, query, SearchOptions::new().size(2)).await?;
+        let second = first.next().await?.unwrap();
+
+        assert_eq!(second.hits[1].id, "d");
+        assert!(second.next().await?.is_none());
+        assert_eq!(
+            protocol.requests()[1]["body"]["search_after"],
+            json!(["b", "b"])
+        );
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn should_page_from_the_next_offset() -> Result<(), Box<dyn Error>> {
+        let protocol = InMemory::new();
+        protocol.respond(page(&["a", "b"], 7, None));
+        protocol.respond(page(&["c", "d"], 7, None));
+        protocol.respond(page(&["e"], 7, None));
+
+        let options = SearchOptions::new().from(2).size(2);
+        let first = search(&protocol, json!({}), options).await?;
+        let third = first.next().await?.unwrap().next().await?.unwrap();
+
+        assert_eq!(third.hits[0].id, "e");
+        assert!(third.next().await?.is_none());
+
+        let requests = protocol.requests();
+        assert_eq!(requests[1]["from"], 4);
+        assert_eq!(requests[2]["from"], 6);
+        Ok(())
+    }
+}
@@ -0,0 +1,77 @@
+use std::sync::{Arc, RwLock};
+
+use crate::protocols::DisconnectReason;
+use crate::types::{Request, Response};
+
+/// Kuzzle error id of the responses to requests using an expired token
+pub(crate) const TOKEN_EXPIRED: &str = "security.token.expired";
+
+/// Lifecycle events of a Kuzzle client, as emitted by the other Kuzzle SDKs
+#[derive(Debug, Clone)]
+pub enum Event {
+    /// The connection was established
+    Connected,
+    /// The connection ended
+    Disconnected(DisconnectReason),
+    /// The connection was re-established after being lost, and the session
+    /// restored: token checked, rooms subscribed to again and queued queries
+    /// sent
+    Reconnected,
+    /// The connection was lost and could not be re-established within the
+    /// given number of attempts: the client stays offline
+    ReconnectFailed(u32),
+    /// A room was subscribed to again after a reconnection
+    Resubscribed(String),
+    /// A query was refused because its authentication token expired
+    TokenExpired,
+    /// A query was answered with an error
+    QueryError {
+        request: Request,
+        response: Response,
+    },
+    /// A query issued while reconnecting left the offline queue to be sent
+    OfflineQueuePop(Request),
+}
+
+pub type EventCallback = Box<dyn Fn(&Event) + Send + Sync>;
+
+/// Callbacks registered for the client events, shared with the protocol hooks
+#[derive(Clone, Default)]
+pub(crate) struct Emitter(Arc<RwLock<Vec<EventCallback>>>);
+
+impl Emitter {
+    pub(crate) fn on_event(&self, callback: EventCallback) {
+        self.0.write().unwrap().push(callback);
+    }
+
+    pub(crate) fn emit(&self, event: Event) {
+        for callback in self.0.read().unwrap().iter() {
+            callback(&event);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    #[test]
+    fn should_invoke_every_callback() {
+        let emitter = Emitter::default();
+        let received = Arc::new(Mutex::new(Vec::new()));
+
+        for _ in 0..2 {
+            let received = received.clone();
+            emitter.on_event(Box::new(move |event: &Event| {
+                received.lock().unwrap().push(format!("{:?}", event));
+            }));
+        }
+        emitter.emit(Event::Reconnected);
+
+        assert_eq!(
+            *received.lock().unwrap(),
+            vec!["Reconnected", "Reconnected"]
+        );
+    }
+}
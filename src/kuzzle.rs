@@ -1,146 +1,2786 @@
-use crate::protocols::Protocol;
-use crate::types::{Request, Response};
+use crate::auth::Authenticator;
+use crate::batch::BatchResponse;
+use crate::builder::KuzzleBuilder;
+use crate::cancel::CancellationToken;
+use crate::controllers::{CollectionController, DocumentController, IndexController};
+use crate::events::{Emitter, Event, EventCallback, TOKEN_EXPIRED};
+use crate::middleware::Middleware;
+use crate::protocols::incoming::Subscribers;
+use crate::protocols::{DisconnectReason, Incoming, Metrics, Protocol, ProtocolError, State};
+use crate::queue::OfflineQueue;
+use crate::queue_store::QueueStore;
+use crate::rate_limit::{RateLimit, TokenBucket};
+use crate::retry::RetryPolicy;
+use crate::runtime::{self, Instant};
+use crate::stats::{Stats, StatsRecorder};
+use crate::types::{ErrorDetails, KuzzleError, Request, Response};
 
+use futures_util::future::{self, Either};
+use futures_util::lock::Mutex;
+use futures_util::stream::StreamExt;
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+use std::any::Any;
+use std::collections::BTreeMap;
 use std::error::Error;
+use std::fmt;
+use std::io::Error as IoError;
+use std::io::ErrorKind as IoErrorKind;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, RwLock, Weak};
+use std::time::Duration;
+use uuid::Uuid;
 
+const QUEUE_POLL_INTERVAL: Duration = Duration::from_millis(100);
+/// Tokens are refreshed when a tenth of their lifetime is left, at most this
+/// long before they expire
+const TOKEN_REFRESH_MARGIN: Duration = Duration::from_secs(60);
+
+/// When the changes made by a query are searchable
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Refresh {
+    /// Once Kuzzle refreshes its indexes, about every second, the response
+    /// being sent right away
+    False,
+    /// Before the response is sent
+    WaitFor,
+}
+
+impl fmt::Display for Refresh {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Refresh::False => write!(f, "false"),
+            Refresh::WaitFor => write!(f, "wait_for"),
+        }
+    }
+}
+
+/// Per-query behavior, for `Kuzzle::query_with_options`
+///
+/// # Example
+///
+/// ```no_run
+/// use kuzzle::protocols::WebSocket;
+/// use kuzzle::{request, Kuzzle, QueryOptions, Refresh};
+/// use serde_json::json;
+/// use std::time::Duration;
+///
+/// # async_std::task::block_on(async {
+/// let kuzzle = Kuzzle::new(WebSocket::new("localhost", None));
+/// kuzzle.connect().await.unwrap();
+///
+/// let create = request!({
+///     "controller": "document",
+///     "action": "create",
+///     "index": "nyc-open-data",
+///     "collection": "yellow-taxi",
+///     "body": {"licence": "B"}
+/// })
+/// .unwrap();
+/// let options = QueryOptions::new()
+///     .queuable(false)
+///     .timeout(Duration::from_secs(5))
+///     .volatile(json!({"origin": "import"}))
+///     .header("x-request-origin", "import")
+///     .deadline(Duration::from_secs(30))
+///     .refresh(Refresh::WaitFor);
+///
+/// kuzzle.query_with_options(&create, options).await.unwrap();
+/// # })
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct QueryOptions {
+    pub queuable: bool,
+    pub timeout: Option<Duration>,
+    pub volatile: Option<Value>,
+    pub refresh: Refresh,
+    pub source: bool,
+    pub fetch_source: bool,
+    pub source_includes: Vec<String>,
+    pub source_excludes: Vec<String>,
+    pub cancel: Option<CancellationToken>,
+    pub headers: BTreeMap<String, String>,
+    pub deadline: Option<Duration>,
+}
+
+impl Default for QueryOptions {
+    fn default() -> Self {
+        Self {
+            queuable: true,
+            timeout: None,
+            volatile: None,
+            refresh: Refresh::False,
+            source: false,
+            fetch_source: true,
+            source_includes: Vec::new(),
+            source_excludes: Vec::new(),
+            cancel: None,
+            headers: BTreeMap::new(),
+            deadline: None,
+        }
+    }
+}
+
+impl QueryOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Whether the query is queued while reconnecting, which is the default,
+    /// or fails right away
+    pub fn queuable(mut self, queuable: bool) -> Self {
+        self.queuable = queuable;
+        self
+    }
+
+    /// Fail with `ProtocolError::Timeout` if no response is received in time,
+    /// overriding the client request timeout
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Volatile data of the query, taking precedence over the global one but
+    /// not over the request one
+    pub fn volatile(mut self, volatile: Value) -> Self {
+        self.volatile = Some(volatile);
+        self
+    }
+
+    /// When the changes made by the query are searchable
+    pub fn refresh(mut self, refresh: Refresh) -> Self {
+        self.refresh = refresh;
+        self
+    }
+
+    /// Include the content of the documents changed by the query in its
+    /// response
+    pub fn source(mut self, source: bool) -> Self {
+        self.source = source;
+        self
+    }
+
+    /// Whether the content of the documents read is part of the response,
+    /// which is the default
+    pub fn fetch_source(mut self, fetch: bool) -> Self {
+        self.fetch_source = fetch;
+        self
+    }
+
+    /// Only read the given fields of the documents, nested ones being given
+    /// as paths such as `driver.licence`
+    pub fn source_includes(mut self, fields: &[&str]) -> Self {
+        self.source_includes = fields.iter().map(|field| field.to_string()).collect();
+        self
+    }
+
+    /// Read every field of the documents but the given ones
+    pub fn source_excludes(mut self, fields: &[&str]) -> Self {
+        self.source_excludes = fields.iter().map(|field| field.to_string()).collect();
+        self
+    }
+
+    /// Abort the query once the given token is cancelled
+    pub fn cancel(mut self, token: CancellationToken) -> Self {
+        self.cancel = Some(token);
+        self
+    }
+
+    /// Add a header to the query, unless the request has its own with the
+    /// same name
+    pub fn header(mut self, name: &str, value: &str) -> Self {
+        self.headers.insert(name.to_string(), value.to_string());
+        self
+    }
+
+    /// Fail with `ProtocolError::DeadlineExceeded` if the query is not
+    /// completed in time, however long it spends queued, waiting between
+    /// retries or being sent again. Unlike `timeout`, which applies to each
+    /// attempt, this bounds the whole query.
+    pub fn deadline(mut self, deadline: Duration) -> Self {
+        self.deadline = Some(deadline);
+        self
+    }
+}
+
+/// Client of a Kuzzle server. Clones share the same connection, queue and
+/// settings, so that queries can be sent concurrently from several tasks.
+#[derive(Clone)]
 pub struct Kuzzle {
+    shared: Arc<Shared>,
+}
+
+struct Shared {
     protocol: Box<dyn Protocol>,
+    queue: OfflineQueue,
+    queuing: Arc<AtomicBool>,
+    events: Emitter,
+    /// Payloads received in place of a response, handed to `incoming`
+    stray: Subscribers,
+    /// Queries being executed, queued ones included
+    in_flight: AtomicUsize,
+    /// Whether the client is shutting down, refusing new queries
+    closing: AtomicBool,
+    /// Whether the notifications pushed by Kuzzle are being watched
+    watching: AtomicBool,
+    /// Whether the session is being restored after a reconnection
+    restoring: AtomicBool,
+    /// Subscription requests, by room, to send again after a reconnection
+    subscriptions: RwLock<Vec<(String, Request)>>,
+    jwt: RwLock<Option<String>>,
+    /// API key used as token, checked upon connection
+    api_key: RwLock<Option<String>>,
+    refresh_at: RwLock<Option<Instant>>,
+    auto_refresh: AtomicBool,
+    volatile: RwLock<Value>,
+    default_index: RwLock<Option<String>>,
+    default_collection: RwLock<Option<String>>,
+    middlewares: RwLock<Vec<Arc<dyn Middleware>>>,
+    authenticator: RwLock<Option<Arc<dyn Authenticator>>>,
+    /// Held while the authenticator is asked for a new token
+    renewal: Mutex<()>,
+    retry: RwLock<Option<RetryPolicy>>,
+    timeout: RwLock<Option<Duration>>,
+    stats: StatsRecorder,
+    limiter: RwLock<Option<Arc<TokenBucket>>>,
 }
 
-impl Kuzzle {
-    pub fn new<P>(protocol: P) -> Kuzzle
-    where
-        P: 'static + Protocol,
-    {
-        Kuzzle {
-            protocol: Box::new(protocol),
+impl Kuzzle {
+    pub fn new<P>(protocol: P) -> Kuzzle
+    where
+        P: 'static + Protocol,
+    {
+        Self::with_protocol(Box::new(protocol))
+    }
+
+    /// Configure a client step by step, see `KuzzleBuilder`
+    pub fn builder() -> KuzzleBuilder {
+        KuzzleBuilder::new()
+    }
+
+    pub(crate) fn with_protocol(mut protocol: Box<dyn Protocol>) -> Kuzzle {
+        let queue = OfflineQueue::new();
+        let events = Emitter::default();
+        let lost = Arc::new(AtomicBool::new(false));
+        let queuing = Arc::new(AtomicBool::new(false));
+        let stats = StatsRecorder::default();
+
+        let shared = Arc::new_cyclic(|client: &Weak<Shared>| {
+            let (replay, emitter, reconnected) = (queue.clone(), events.clone(), lost.clone());
+            let (held, client) = (queuing.clone(), client.clone());
+            protocol.on_connect(Box::new(move || {
+                if !reconnected.swap(false, Ordering::SeqCst) {
+                    emitter.emit(Event::Connected);
+                    if !held.load(Ordering::SeqCst) {
+                        replay.play();
+                    }
+                    return;
+                }
+
+                // Restoring the session takes queries, hence a task of its own
+                if let Some(shared) = client.upgrade() {
+                    shared.restoring.store(true, Ordering::SeqCst);
+                    let kuzzle = Kuzzle { shared };
+                    runtime::spawn(async move { kuzzle.restore().await });
+                }
+            }));
+
+            let emitter = events.clone();
+            protocol.on_disconnect(Box::new(move |reason: &DisconnectReason| {
+                lost.store(*reason != DisconnectReason::Requested, Ordering::SeqCst);
+                emitter.emit(Event::Disconnected(reason.clone()));
+            }));
+
+            let emitter = events.clone();
+            protocol.on_error(Box::new(move |error: &(dyn Error + 'static)| {
+                if let Some(ProtocolError::ReconnectFailed(attempts)) = error.downcast_ref() {
+                    emitter.emit(Event::ReconnectFailed(*attempts));
+                }
+            }));
+
+            Shared {
+                protocol,
+                queue,
+                queuing,
+                events,
+                stray: Subscribers::default(),
+                in_flight: AtomicUsize::new(0),
+                closing: AtomicBool::new(false),
+                watching: AtomicBool::new(false),
+                restoring: AtomicBool::new(false),
+                subscriptions: RwLock::new(Vec::new()),
+                jwt: RwLock::new(None),
+                api_key: RwLock::new(None),
+                refresh_at: RwLock::new(None),
+                auto_refresh: AtomicBool::new(true),
+                volatile: RwLock::new(Value::Null),
+                default_index: RwLock::new(None),
+                default_collection: RwLock::new(None),
+                middlewares: RwLock::new(Vec::new()),
+                authenticator: RwLock::new(None),
+                renewal: Mutex::new(()),
+                retry: RwLock::new(None),
+                timeout: RwLock::new(None),
+                stats,
+                limiter: RwLock::new(None),
+            }
+        });
+
+        Kuzzle { shared }
+    }
+
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip(self), err))]
+    pub async fn connect(&self) -> Result<(), Box<dyn Error>> {
+        self.shared.protocol.connect().await?;
+        self.watch_notifications();
+
+        let api_key = self.shared.api_key.read().unwrap().clone();
+        match api_key {
+            Some(api_key) if self.jwt().as_ref() == Some(&api_key) => {
+                self.login_with_api_key(&api_key).await
+            }
+            _ => Ok(()),
+        }
+    }
+
+    pub async fn disconnect(&self) -> Result<(), Box<dyn Error>> {
+        self.shared.protocol.disconnect().await
+    }
+
+    /// Stop sending queries and wait at most `timeout` for the pending ones to
+    /// complete before disconnecting, so that no write is lost on shutdown.
+    /// When connected, the queued queries are sent and waited for as well.
+    pub async fn disconnect_graceful(&self, timeout: Duration) -> Result<(), Box<dyn Error>> {
+        let deadline = Instant::now() + timeout;
+
+        if self.state() == State::Connected {
+            self.shared.queue.play();
+            self.settle(deadline).await;
+        }
+
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        self.shared.protocol.disconnect_graceful(remaining).await
+    }
+
+    /// Wait until no query is in flight anymore, or until `deadline`
+    async fn settle(&self, deadline: Instant) {
+        loop {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if self.shared.in_flight.load(Ordering::SeqCst) == 0 || remaining.as_nanos() == 0 {
+                return;
+            }
+            runtime::sleep(QUEUE_POLL_INTERVAL.min(remaining)).await;
+        }
+    }
+
+    /// Shut the client down, e.g. upon SIGTERM: refuse new queries, send the
+    /// queued ones, unsubscribe from the rooms, wait for the queries in
+    /// flight to complete, then disconnect. Gives up waiting after `timeout`,
+    /// disconnecting anyway. The client can't send queries anymore afterwards.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use kuzzle::protocols::WebSocket;
+    /// use kuzzle::Kuzzle;
+    /// use std::time::Duration;
+    ///
+    /// # async_std::task::block_on(async {
+    /// let kuzzle = Kuzzle::new(WebSocket::new("localhost", None));
+    /// kuzzle.connect().await.unwrap();
+    ///
+    /// // ... upon SIGTERM
+    /// kuzzle.shutdown(Duration::from_secs(10)).await.unwrap();
+    /// # })
+    /// ```
+    pub async fn shutdown(&self, timeout: Duration) -> Result<(), Box<dyn Error>> {
+        let deadline = Instant::now() + timeout;
+        self.shared.closing.store(true, Ordering::SeqCst);
+        self.stop_queuing();
+
+        match self.state() {
+            State::Connected => self.shared.queue.play(),
+            _ => self.shared.queue.flush(),
+        }
+
+        let subscriptions: Vec<(String, Request)> = self
+            .shared
+            .subscriptions
+            .write()
+            .unwrap()
+            .drain(..)
+            .collect();
+        let mut rooms: Vec<String> = Vec::new();
+        for (room, _) in subscriptions {
+            if !rooms.contains(&room) {
+                rooms.push(room);
+            }
+        }
+        for room in rooms {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if let Ok(mut unsubscribe) = crate::request!({
+                "controller": "realtime",
+                "action": "unsubscribe",
+                "body": {"roomId": room},
+            }) {
+                unsubscribe.jwt = self.jwt();
+                let _ = self.send(&unsubscribe, Some(remaining)).await;
+            }
+        }
+
+        self.settle(deadline).await;
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        self.shared.protocol.disconnect_graceful(remaining).await
+    }
+
+    /// Current state of the connection to Kuzzle
+    pub fn state(&self) -> State {
+        self.shared.protocol.state()
+    }
+
+    /// Messages sent by Kuzzle outside of any query, such as real-time
+    /// notifications, including the ones received in place of a response
+    pub fn incoming(&self) -> Incoming {
+        Incoming::merge(vec![
+            self.shared.protocol.incoming(),
+            self.shared.stray.subscribe(),
+        ])
+    }
+
+    /// Protocol the client was created with
+    pub fn protocol(&self) -> &dyn Protocol {
+        self.shared.protocol.as_ref()
+    }
+
+    /// Protocol the client was created with if it is a `P`, to reach the
+    /// features specific to it
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use kuzzle::protocols::InMemory;
+    /// use kuzzle::Kuzzle;
+    ///
+    /// let kuzzle = Kuzzle::new(InMemory::new());
+    ///
+    /// assert!(kuzzle.protocol_as::<InMemory>().is_some());
+    /// ```
+    pub fn protocol_as<P>(&self) -> Option<&P>
+    where
+        P: Protocol,
+    {
+        let protocol: &dyn Any = self.protocol();
+        protocol.downcast_ref()
+    }
+
+    /// Activity of the underlying protocol: requests, errors, latency...
+    pub fn metrics(&self) -> Metrics {
+        self.shared.protocol.metrics()
+    }
+
+    /// Activity of the client: queries, failures, latency, queue depth and
+    /// reconnections
+    pub fn stats(&self) -> Stats {
+        self.shared.stats.snapshot(self.shared.queue.len())
+    }
+
+    /// Queries issued while reconnecting, waiting to be sent
+    pub fn queue(&self) -> OfflineQueue {
+        self.shared.queue.clone()
+    }
+
+    /// Send the queued queries now, without waiting for the reconnection
+    pub fn play_queue(&self) {
+        self.shared.queue.play();
+    }
+
+    /// Drop the queued queries, which then fail
+    pub fn flush_queue(&self) {
+        self.shared.queue.flush();
+    }
+
+    /// Persist the queued queries in the given store, so that they survive a
+    /// process restart. The queries left in the store by a previous process
+    /// are queued again, oldest first, and sent with the current token along
+    /// with the rest of the queue, their outcome being ignored.
+    pub async fn set_queue_store<S>(&self, store: S) -> Result<(), Box<dyn Error>>
+    where
+        S: 'static + QueueStore,
+    {
+        let store: Arc<dyn QueueStore> = Arc::new(store);
+        let restored = store.load()?;
+        self.shared.queue.set_store(Some(store));
+
+        for mut request in restored {
+            request.jwt = None;
+            let released = self.shared.queue.restore(request.clone());
+            let kuzzle = self.clone();
+            runtime::spawn(async move {
+                if released.await == Ok(true) {
+                    kuzzle
+                        .shared
+                        .events
+                        .emit(Event::OfflineQueuePop(request.clone()));
+                    let _ = kuzzle.query(&request).await;
+                }
+            });
+        }
+
+        // Already connected, the queue won't be played by the connection
+        if self.state() == State::Connected && !self.is_queuing() {
+            self.shared.queue.play();
+        }
+        Ok(())
+    }
+
+    /// Queue every query, even while connected, e.g. during a planned
+    /// maintenance of Kuzzle. The queue is not played on reconnection until
+    /// `stop_queuing` is called.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use kuzzle::protocols::InMemory;
+    /// use kuzzle::Kuzzle;
+    ///
+    /// let kuzzle = Kuzzle::new(InMemory::new());
+    ///
+    /// kuzzle.start_queuing();
+    /// // ... maintenance window
+    /// kuzzle.stop_queuing();
+    /// kuzzle.play_queue();
+    /// ```
+    pub fn start_queuing(&self) {
+        self.shared.queuing.store(true, Ordering::SeqCst);
+    }
+
+    /// Stop queuing the queries issued while connected. The queries already
+    /// queued wait for `play_queue` or `flush_queue`.
+    pub fn stop_queuing(&self) {
+        self.shared.queuing.store(false, Ordering::SeqCst);
+    }
+
+    /// Whether queries are being queued because of `start_queuing`
+    pub fn is_queuing(&self) -> bool {
+        self.shared.queuing.load(Ordering::SeqCst)
+    }
+
+    /// Authentication token sent with the queries not providing theirs
+    pub fn jwt(&self) -> Option<String> {
+        self.shared.jwt.read().unwrap().clone()
+    }
+
+    /// Set the authentication token to send with every query not providing
+    /// its own, or forget it with `None`
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use kuzzle::protocols::InMemory;
+    /// use kuzzle::Kuzzle;
+    ///
+    /// let kuzzle = Kuzzle::new(InMemory::new());
+    /// kuzzle.set_jwt(Some("eyJhbGciOi".to_string()));
+    ///
+    /// assert_eq!(kuzzle.jwt(), Some("eyJhbGciOi".to_string()));
+    /// ```
+    pub fn set_jwt(&self, jwt: Option<String>) {
+        *self.shared.jwt.write().unwrap() = jwt;
+        *self.shared.refresh_at.write().unwrap() = None;
+    }
+
+    /// Authenticate with an API key, sent as token with every query not
+    /// providing its own once `auth:checkToken` confirmed it is valid. Fails
+    /// with `KuzzleError::Unauthorized` otherwise. The key is checked again
+    /// upon every connection.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use kuzzle::protocols::WebSocket;
+    /// use kuzzle::Kuzzle;
+    ///
+    /// # async_std::task::block_on(async {
+    /// let kuzzle = Kuzzle::new(WebSocket::new("localhost", None));
+    /// kuzzle.connect().await.unwrap();
+    /// kuzzle.login_with_api_key("eyJhbGciOi").await.unwrap();
+    /// # })
+    /// ```
+    pub async fn login_with_api_key(&self, api_key: &str) -> Result<(), Box<dyn Error>> {
+        if !self.verify_token(api_key).await? {
+            return Err(Box::new(KuzzleError::from(ErrorDetails {
+                id: "security.token.invalid".to_string(),
+                message: "Invalid API key".to_string(),
+                status: 401,
+                ..ErrorDetails::default()
+            })));
+        }
+
+        self.set_api_key(api_key);
+        Ok(())
+    }
+
+    /// Send the given API key as token, checking it upon connection
+    pub(crate) fn set_api_key(&self, api_key: &str) {
+        self.set_jwt(Some(api_key.to_string()));
+        *self.shared.api_key.write().unwrap() = Some(api_key.to_string());
+    }
+
+    /// Whether the token obtained with `auth:login` is refreshed with
+    /// `auth:refreshToken` before it expires, which is the default. The
+    /// refresh happens before sending the next query, and emits
+    /// `Event::TokenExpired` if it fails.
+    pub fn set_auto_refresh(&self, enabled: bool) {
+        self.shared.auto_refresh.store(enabled, Ordering::SeqCst);
+    }
+
+    /// Volatile data attached to every query
+    pub fn volatile(&self) -> Value {
+        self.shared.volatile.read().unwrap().clone()
+    }
+
+    /// Set volatile data attached to every query, such as the application
+    /// version or a device identifier. When a query has volatile data of its
+    /// own, both objects are merged, the query one taking precedence.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use kuzzle::protocols::InMemory;
+    /// use kuzzle::Kuzzle;
+    /// use serde_json::json;
+    ///
+    /// let kuzzle = Kuzzle::new(InMemory::new());
+    /// kuzzle.set_volatile(json!({"appVersion": "1.4.2"}));
+    /// ```
+    pub fn set_volatile(&self, volatile: Value) {
+        *self.shared.volatile.write().unwrap() = volatile;
+    }
+
+    /// Index of the queries giving a collection but no index
+    pub fn default_index(&self) -> Option<String> {
+        self.shared.default_index.read().unwrap().clone()
+    }
+
+    /// Set the index of the queries giving a collection but no index, or stop
+    /// completing them with `None`
+    pub fn set_default_index(&self, index: Option<String>) {
+        *self.shared.default_index.write().unwrap() = index;
+    }
+
+    /// Collection of the queries about documents or subscriptions giving
+    /// neither an index nor a collection
+    pub fn default_collection(&self) -> Option<String> {
+        self.shared.default_collection.read().unwrap().clone()
+    }
+
+    /// Set the collection of the queries about documents or subscriptions
+    /// giving neither an index nor a collection, or stop completing them with
+    /// `None`. Such queries are also given the default index.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use kuzzle::protocols::InMemory;
+    /// use kuzzle::Kuzzle;
+    ///
+    /// let kuzzle = Kuzzle::new(InMemory::new());
+    /// kuzzle.set_default_index(Some("nyc-open-data".to_string()));
+    /// kuzzle.set_default_collection(Some("yellow-taxi".to_string()));
+    /// ```
+    pub fn set_default_collection(&self, collection: Option<String>) {
+        *self.shared.default_collection.write().unwrap() = collection;
+    }
+
+    /// Add a step run on every query before it is sent, after the ones
+    /// already added, and on its response before the ones already added
+    pub fn add_middleware<M>(&self, middleware: M)
+    where
+        M: 'static + Middleware,
+    {
+        self.shared
+            .middlewares
+            .write()
+            .unwrap()
+            .push(Arc::new(middleware));
+    }
+
+    /// Ask the given authenticator for a new token when a query is rejected
+    /// because the stored one is invalid or expired, then send the query
+    /// again. Queries providing their own token are not retried.
+    pub fn set_authenticator<A>(&self, authenticator: A)
+    where
+        A: 'static + Authenticator,
+    {
+        *self.shared.authenticator.write().unwrap() = Some(Arc::new(authenticator));
+    }
+
+    /// Retry the queries failing transiently, or never retry them with `None`,
+    /// which is the default
+    pub fn set_retry_policy(&self, policy: Option<RetryPolicy>) {
+        *self.shared.retry.write().unwrap() = policy;
+    }
+
+    /// Time after which the queries not giving their own timeout fail with
+    /// `ProtocolError::Timeout`
+    pub fn request_timeout(&self) -> Option<Duration> {
+        *self.shared.timeout.read().unwrap()
+    }
+
+    /// Set the time after which the queries not giving their own timeout fail
+    /// with `ProtocolError::Timeout`, queued ones included once sent, or let
+    /// the protocol decide with `None`, which is the default
+    pub fn set_request_timeout(&self, timeout: Option<Duration>) {
+        *self.shared.timeout.write().unwrap() = timeout;
+    }
+
+    /// Limit the rate of the queries, or lift the limit with `None`, which is
+    /// the default
+    pub fn set_rate_limit(&self, limit: Option<RateLimit>) {
+        *self.shared.limiter.write().unwrap() =
+            limit.map(|limit| Arc::new(TokenBucket::new(limit)));
+    }
+
+    /// Register a callback invoked with every client event
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use kuzzle::events::Event;
+    /// use kuzzle::protocols::InMemory;
+    /// use kuzzle::Kuzzle;
+    ///
+    /// let kuzzle = Kuzzle::new(InMemory::new());
+    /// kuzzle.on_event(Box::new(|event: &Event| match event {
+    ///     Event::Reconnected => println!("Back online"),
+    ///     Event::TokenExpired => println!("Please log in again"),
+    ///     _ => (),
+    /// }));
+    /// ```
+    pub fn on_event(&self, callback: EventCallback) {
+        self.shared.events.on_event(callback);
+    }
+
+    /// Send a query, along with the stored authentication token if it doesn't
+    /// provide its own. While the connection is being re-established, or
+    /// after `start_queuing`, the query is queued and sent once the queue is
+    /// played. The queue is flushed if the protocol gives up reconnecting.
+    pub async fn query(&self, request: &Request) -> Result<Response, Box<dyn Error>> {
+        self.query_with_options(request, QueryOptions::default())
+            .await
+    }
+
+    /// Same as `query`, failing with the `KuzzleError` the query was answered
+    /// with when its status is not a success, see `Response::error_for_status`
+    pub async fn query_checked(&self, request: &Request) -> Result<Response, Box<dyn Error>> {
+        Ok(self.query(request).await?.error_for_status()?)
+    }
+
+    /// Send a query and deserialize its result, failing with the
+    /// `KuzzleError` it was answered with when its status is not a success,
+    /// see `Response::error_for_status`
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use kuzzle::protocols::WebSocket;
+    /// use kuzzle::{request, Kuzzle};
+    /// use serde::Deserialize;
+    ///
+    /// #[derive(Deserialize)]
+    /// struct Now {
+    ///     now: u64,
+    /// }
+    ///
+    /// # async_std::task::block_on(async {
+    /// let kuzzle = Kuzzle::new(WebSocket::new("localhost", None));
+    /// kuzzle.connect().await.unwrap();
+    ///
+    /// let request = request!({"controller": "server", "action": "now"}).unwrap();
+    /// let Now { now } = kuzzle.query_as(&request).await.unwrap();
+    /// # })
+    /// ```
+    pub async fn query_as<T>(&self, request: &Request) -> Result<T, Box<dyn Error>>
+    where
+        T: DeserializeOwned,
+    {
+        let response = self.query_checked(request).await?;
+
+        Ok(serde_json::from_value(response.result.unwrap_or_default())?)
+    }
+
+    /// Typed methods of the `document` controller
+    pub fn document(&self) -> DocumentController<'_> {
+        DocumentController::new(self)
+    }
+
+    /// Typed methods of the `collection` controller
+    pub fn collection(&self) -> CollectionController<'_> {
+        CollectionController::new(self)
+    }
+
+    /// Typed methods of the `index` controller
+    pub fn index(&self) -> IndexController<'_> {
+        IndexController::new(self)
+    }
+
+    /// Send several queries concurrently, over the same connection, and
+    /// gather their results in order. Each query is sent on its own: the
+    /// failure of one of them doesn't prevent the others from succeeding.
+    pub async fn query_batch(&self, requests: &[Request]) -> BatchResponse {
+        BatchResponse {
+            results: future::join_all(requests.iter().map(|request| self.query(request))).await,
+        }
+    }
+
+    /// Same as `query`, with a behavior tailored by the given options
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(
+            skip_all,
+            err,
+            fields(
+                controller = %request.controller,
+                action = %request.action,
+                status,
+                error,
+                latency_ms,
+            ),
+        )
+    )]
+    pub async fn query_with_options(
+        &self,
+        request: &Request,
+        options: QueryOptions,
+    ) -> Result<Response, Box<dyn Error>> {
+        match options.deadline {
+            Some(deadline) => {
+                match runtime::timeout(deadline, self.cancellable(request, options)).await {
+                    Some(result) => result,
+                    None => Err(Box::new(ProtocolError::DeadlineExceeded(deadline))),
+                }
+            }
+            None => self.cancellable(request, options).await,
+        }
+    }
+
+    async fn cancellable(
+        &self,
+        request: &Request,
+        options: QueryOptions,
+    ) -> Result<Response, Box<dyn Error>> {
+        let token = match options.cancel.clone() {
+            Some(token) => token,
+            None => return self.execute(request, options).await,
+        };
+
+        let query = Box::pin(self.execute(request, options));
+        match future::select(query, token.cancelled()).await {
+            Either::Left((result, _)) => result,
+            Either::Right(_) => Err(Box::new(ProtocolError::Cancelled)),
+        }
+    }
+
+    async fn execute(
+        &self,
+        request: &Request,
+        options: QueryOptions,
+    ) -> Result<Response, Box<dyn Error>> {
+        let _in_flight = InFlight::enter(&self.shared.in_flight);
+        if self.shared.closing.load(Ordering::SeqCst) {
+            return Err(Box::new(IoError::new(
+                IoErrorKind::NotConnected,
+                "Client shut down",
+            )));
+        }
+
+        let mut request = request.clone();
+        let injected = request.jwt.is_none();
+        if injected {
+            if !issues_token(&request) {
+                self.refresh_token().await;
+            }
+            request.jwt = self.injected_jwt(&request);
+        }
+        if request.index.is_none() && request.collection.is_none() && targets_collection(&request) {
+            request.collection = self.default_collection();
+        }
+        if request.index.is_none() && request.collection.is_some() {
+            request.index = self.default_index();
+        }
+        if let Some(volatile) = &options.volatile {
+            request.volatile = merge_volatile(volatile, request.volatile);
+        }
+        request.volatile = merge_volatile(&self.shared.volatile.read().unwrap(), request.volatile);
+        if options.refresh == Refresh::WaitFor {
+            request.refresh = Some(Refresh::WaitFor.to_string());
+        }
+        if options.source {
+            request.source = Some(true);
+        }
+        if !options.fetch_source {
+            request.fetch_source = Some(false);
+        }
+        if !options.source_includes.is_empty() {
+            request.source_includes = Some(options.source_includes.clone());
+        }
+        if !options.source_excludes.is_empty() {
+            request.source_excludes = Some(options.source_excludes.clone());
+        }
+        if !options.headers.is_empty() {
+            let mut headers = options.headers.clone();
+            headers.extend(request.headers.take().unwrap_or_default());
+            request.headers = Some(headers);
+        }
+
+        let middlewares = self.shared.middlewares.read().unwrap().clone();
+        for middleware in &middlewares {
+            middleware.on_request(&mut request).await?;
+        }
+
+        // Logging in is allowed while restoring the session, to renew the token
+        let restoring = self.shared.restoring.load(Ordering::SeqCst) && !issues_token(&request);
+        if self.is_queuing() || restoring || self.shared.protocol.state() == State::Reconnecting {
+            if !options.queuable {
+                return Err(Box::new(IoError::new(
+                    IoErrorKind::NotConnected,
+                    "Query not queuable while queuing",
+                )));
+            }
+
+            let mut released = self.shared.queue.push(request.clone());
+
+            let send = loop {
+                match runtime::timeout(QUEUE_POLL_INTERVAL, &mut released).await {
+                    Some(released) => break released.unwrap_or(false),
+                    None if self.shared.protocol.state() == State::Offline => {
+                        self.shared.queue.flush()
+                    }
+                    None => continue,
+                }
+            };
+
+            if !send {
+                return Err(Box::new(IoError::new(
+                    IoErrorKind::Interrupted,
+                    "Query discarded from the offline queue",
+                )));
+            }
+            // The token may have been renewed while the query was queued
+            if injected {
+                request.jwt = self.injected_jwt(&request);
+            }
+            self.shared
+                .events
+                .emit(Event::OfflineQueuePop(request.clone()));
+        }
+
+        let limiter = self.shared.limiter.read().unwrap().clone();
+        if let Some(limiter) = limiter {
+            match limiter.reserve() {
+                Some(wait) if wait > Duration::from_secs(0) => runtime::sleep(wait).await,
+                Some(_) => (),
+                None => {
+                    return Err(Box::new(IoError::new(
+                        IoErrorKind::WouldBlock,
+                        "Client-side rate limit reached",
+                    )))
+                }
+            }
+        }
+
+        let timeout = options.timeout.or_else(|| self.request_timeout());
+        let started = Instant::now();
+        let mut response = {
+            let result = self.send_with_retries(&request, timeout).await;
+            self.shared.stats.query(&result, started.elapsed());
+            result?
+        };
+        if injected && !issues_token(&request) {
+            if let Some(retried) = self
+                .reauthenticate(&mut request, &response, timeout)
+                .await?
+            {
+                response = retried;
+            }
+        }
+        #[cfg(feature = "tracing")]
+        trace_response(&request, &response, started);
+        self.track_token(&request, &response);
+        self.track_subscription(&request, &response, injected);
+
+        if let Some(error) = &response.error {
+            if error.id() == TOKEN_EXPIRED {
+                self.expire_token(request.jwt.as_deref());
+            }
+            self.shared.events.emit(Event::QueryError {
+                request: request.clone(),
+                response: response.clone(),
+            });
+        }
+
+        for middleware in middlewares.iter().rev() {
+            middleware.on_response(&request, &mut response).await?;
+        }
+
+        Ok(response)
+    }
+
+    async fn send(
+        &self,
+        request: &Request,
+        timeout: Option<Duration>,
+    ) -> Result<Response, Box<dyn Error>> {
+        let request_id = request.request_id.clone();
+        let request = serde_json::to_value(request)?;
+        let response = match timeout {
+            Some(timeout) => {
+                self.shared
+                    .protocol
+                    .send_with_timeout(request, timeout)
+                    .await?
+            }
+            None => self.shared.protocol.send(request).await?,
+        };
+        let payload: Value = serde_json::from_str(&response)?;
+
+        match payload["requestId"].as_str() == Some(request_id.as_str()) {
+            true => Ok(serde_json::from_value(payload)?),
+            false => {
+                self.shared.stray.publish(response);
+                Err(Box::new(ProtocolError::UnexpectedResponse(
+                    payload["requestId"]
+                        .as_str()
+                        .unwrap_or_default()
+                        .to_string(),
+                )))
+            }
+        }
+    }
+
+    /// Obtain a new token from the authenticator if the request was rejected
+    /// as unauthorized, and send it again with it
+    async fn reauthenticate(
+        &self,
+        request: &mut Request,
+        response: &Response,
+        timeout: Option<Duration>,
+    ) -> Result<Option<Response>, Box<dyn Error>> {
+        let authenticator = self.shared.authenticator.read().unwrap().clone();
+        let authenticator = match (authenticator, &response.error) {
+            (Some(authenticator), Some(KuzzleError::Unauthorized(_))) => authenticator,
+            _ => return Ok(None),
+        };
+
+        let rejected = request.jwt.take();
+        request.jwt = Some(self.renew_token(authenticator, rejected.as_deref()).await?);
+
+        let started = Instant::now();
+        let result = self.send_with_retries(request, timeout).await;
+        self.shared.stats.query(&result, started.elapsed());
+        result.map(Some)
+    }
+
+    /// Stored token to send with a request not providing its own. The token
+    /// being renewed must not be sent along with the login renewing it.
+    fn injected_jwt(&self, request: &Request) -> Option<String> {
+        match issues_token(request) && self.shared.renewal.try_lock().is_none() {
+            true => None,
+            false => self.jwt(),
+        }
+    }
+
+    /// Ask the authenticator for a token replacing `stale`, once for all the
+    /// queries rejected at the same time: the ones waiting for a renewal in
+    /// progress get its token. The stale token is kept meanwhile.
+    async fn renew_token(
+        &self,
+        authenticator: Arc<dyn Authenticator>,
+        stale: Option<&str>,
+    ) -> Result<String, Box<dyn Error>> {
+        let _renewal = self.shared.renewal.lock().await;
+        match self.jwt() {
+            Some(jwt) if Some(jwt.as_str()) != stale => return Ok(jwt),
+            _ => (),
+        }
+
+        let jwt = authenticator.authenticate(self).await?;
+        self.set_jwt(Some(jwt.clone()));
+        Ok(jwt)
+    }
+
+    /// Send a request, retrying it as allowed by the retry policy
+    async fn send_with_retries(
+        &self,
+        request: &Request,
+        timeout: Option<Duration>,
+    ) -> Result<Response, Box<dyn Error>> {
+        let policy = self.shared.retry.read().unwrap().clone();
+        let policy = match policy {
+            Some(policy) if policy.covers(request) => policy,
+            _ => return self.send(request, timeout).await,
+        };
+
+        let mut attempts = 1;
+        loop {
+            match self.send(request, timeout).await {
+                result if attempts >= policy.max_attempts || !policy.retries(&result) => {
+                    return result
+                }
+                _ => (),
+            }
+
+            runtime::sleep(policy.delay(attempts - 1)).await;
+            attempts += 1;
+        }
+    }
+
+    /// Store the token issued by a successful `auth:login` or
+    /// `auth:refreshToken`, and forget it on `auth:logout`
+    fn track_token(&self, request: &Request, response: &Response) {
+        if request.controller != "auth" || response.error.is_some() {
+            return;
+        }
+
+        let result = response.result.clone().unwrap_or_default();
+        match (request.action.as_str(), result["jwt"].as_str()) {
+            ("login", Some(jwt)) | ("refreshToken", Some(jwt)) => {
+                *self.shared.jwt.write().unwrap() = Some(jwt.to_string());
+                *self.shared.refresh_at.write().unwrap() = result["ttl"].as_u64().map(|ttl| {
+                    let ttl = Duration::from_millis(ttl);
+                    Instant::now() + ttl - (ttl / 10).min(TOKEN_REFRESH_MARGIN)
+                });
+            }
+            ("logout", _) => self.set_jwt(None),
+            _ => (),
+        }
+    }
+
+    /// Forget the stored token, unless it replaced the expired one meanwhile,
+    /// so that it is not sent anymore, and emit `Event::TokenExpired`
+    fn expire_token(&self, expired: Option<&str>) {
+        if expired.map_or(true, |jwt| self.jwt().as_deref() == Some(jwt)) {
+            self.set_jwt(None);
+        }
+        self.shared.events.emit(Event::TokenExpired);
+    }
+
+    /// Watch the notifications pushed by Kuzzle, once and for all, to forget
+    /// the stored token as soon as Kuzzle tells it expired
+    fn watch_notifications(&self) {
+        if self.shared.watching.swap(true, Ordering::SeqCst) {
+            return;
+        }
+
+        // The stream ends along with the protocol, hence with the client
+        let mut incoming = self.shared.protocol.incoming();
+        let client = Arc::downgrade(&self.shared);
+        runtime::spawn(async move {
+            while let Some(payload) = incoming.next().await {
+                let notification: Value = serde_json::from_str(&payload).unwrap_or_default();
+                if notification["type"] != "TokenExpired" {
+                    continue;
+                }
+                if let Some(shared) = client.upgrade() {
+                    Kuzzle { shared }.expire_token(None);
+                }
+            }
+        });
+    }
+
+    /// Remember the rooms subscribed to, in order to subscribe to them again
+    /// after a reconnection, and forget them once unsubscribed from
+    fn track_subscription(&self, request: &Request, response: &Response, injected: bool) {
+        if request.controller != "realtime" || response.error.is_some() {
+            return;
+        }
+
+        let result = response.result.clone().unwrap_or_default();
+        let mut subscriptions = self.shared.subscriptions.write().unwrap();
+        match (request.action.as_str(), result["roomId"].as_str()) {
+            ("subscribe", Some(room)) => {
+                let mut request = request.clone();
+                if injected {
+                    request.jwt = None;
+                }
+                subscriptions.push((room.to_string(), request));
+            }
+            ("unsubscribe", Some(room)) => subscriptions.retain(|(id, _)| id != room),
+            _ => (),
+        }
+    }
+
+    /// Restore the session once the connection is re-established: check the
+    /// token is still valid, subscribe to the rooms again, send the queued
+    /// queries, and only then emit `Event::Reconnected`. Queries issued
+    /// meanwhile are queued, except logins.
+    async fn restore(&self) {
+        self.check_token().await;
+        self.resubscribe().await;
+        self.shared.restoring.store(false, Ordering::SeqCst);
+
+        // Lost again, the next reconnection restores the session
+        if self.shared.protocol.state() != State::Connected {
+            return;
+        }
+        if !self.is_queuing() {
+            self.shared.queue.play();
+        }
+        self.shared.stats.reconnected();
+        self.shared.events.emit(Event::Reconnected);
+    }
+
+    /// Forget the stored token if Kuzzle doesn't accept it anymore, asking
+    /// the authenticator for a new one if any. Connection failures are
+    /// ignored, the token being kept.
+    async fn check_token(&self) {
+        let jwt = match self.jwt() {
+            Some(jwt) => jwt,
+            None => return,
+        };
+        match self.verify_token(&jwt).await {
+            Ok(false) => (),
+            _ => return,
+        }
+
+        let authenticator = self.shared.authenticator.read().unwrap().clone();
+        let renewed = match authenticator {
+            Some(authenticator) => self.renew_token(authenticator, Some(&jwt)).await.is_ok(),
+            None => false,
+        };
+        if !renewed {
+            self.expire_token(Some(&jwt));
+        }
+    }
+
+    /// Whether Kuzzle accepts the given token, according to `auth:checkToken`
+    async fn verify_token(&self, jwt: &str) -> Result<bool, Box<dyn Error>> {
+        let check = crate::request!({
+            "controller": "auth",
+            "action": "checkToken",
+            "body": {"token": jwt},
+        })?;
+        let response = self.send(&check, None).await?.error_for_status()?;
+
+        Ok(response.result.unwrap_or_default()["valid"] == true)
+    }
+
+    /// Subscribe again to the rooms, which Kuzzle forgot along with the lost
+    /// connection. Rooms subscribed to again are emitted as
+    /// `Event::Resubscribed`, while subscriptions refused are forgotten and
+    /// emitted as `Event::QueryError`.
+    async fn resubscribe(&self) {
+        let subscriptions = self.shared.subscriptions.read().unwrap().clone();
+
+        for (room, subscription) in subscriptions {
+            let mut request = subscription.clone();
+            request.request_id = Uuid::new_v4().to_string();
+            if request.jwt.is_none() {
+                request.jwt = self.jwt();
+            }
+
+            let response = match self.send(&request, None).await {
+                Ok(response) if response.error.is_some() => response,
+                Ok(_) => {
+                    self.shared.events.emit(Event::Resubscribed(room));
+                    continue;
+                }
+                Err(_) => continue,
+            };
+            self.shared
+                .subscriptions
+                .write()
+                .unwrap()
+                .retain(|(_, tracked)| tracked.request_id != subscription.request_id);
+            self.shared
+                .events
+                .emit(Event::QueryError { request, response });
+        }
+    }
+
+    /// Refresh the stored token if it is about to expire. Connection failures
+    /// are ignored, the refresh being attempted again with the next query.
+    async fn refresh_token(&self) {
+        let due =
+            matches!(*self.shared.refresh_at.read().unwrap(), Some(at) if Instant::now() >= at);
+        if !due
+            || !self.shared.auto_refresh.load(Ordering::SeqCst)
+            || self.shared.protocol.state() != State::Connected
+        {
+            return;
+        }
+
+        let refresh = match crate::request!({
+            "controller": "auth",
+            "action": "refreshToken",
+            "jwt": self.jwt(),
+        }) {
+            Ok(refresh) => refresh,
+            Err(_) => return,
+        };
+
+        if let Ok(response) = self.send(&refresh, None).await {
+            match response.error {
+                None => self.track_token(&refresh, &response),
+                Some(_) => {
+                    *self.shared.refresh_at.write().unwrap() = None;
+                    self.shared.events.emit(Event::TokenExpired);
+                }
+            }
+        }
+    }
+}
+
+/// Accounts for a query being executed, until dropped
+struct InFlight<'a>(&'a AtomicUsize);
+
+impl<'a> InFlight<'a> {
+    fn enter(count: &'a AtomicUsize) -> Self {
+        count.fetch_add(1, Ordering::SeqCst);
+        InFlight(count)
+    }
+}
+
+impl Drop for InFlight<'_> {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+/// Record the outcome of a query on its span
+#[cfg(feature = "tracing")]
+fn trace_response(request: &Request, response: &Response, started: Instant) {
+    let span = tracing::Span::current();
+    span.record("status", u64::from(response.status));
+    span.record("latency_ms", started.elapsed().as_millis() as u64);
+
+    match &response.error {
+        Some(error) => {
+            span.record("error", error.id());
+        }
+        None if request.controller == "realtime" && request.action == "subscribe" => {
+            let result = response.result.clone().unwrap_or_default();
+            tracing::debug!(room = %result["roomId"], "subscribed");
+        }
+        None => (),
+    }
+}
+
+/// Merge the global volatile data into the one of a query, which takes
+/// precedence
+fn merge_volatile(global: &Value, volatile: Option<Value>) -> Option<Value> {
+    match (global, volatile) {
+        (Value::Null, volatile) => volatile,
+        (Value::Object(global), Some(Value::Object(own))) => {
+            let mut merged = global.clone();
+            merged.extend(own);
+            Some(Value::Object(merged))
+        }
+        (_, None) | (_, Some(Value::Null)) => Some(global.clone()),
+        (_, volatile) => volatile,
+    }
+}
+
+/// Whether a request is about the documents or subscriptions of a collection
+fn targets_collection(request: &Request) -> bool {
+    matches!(
+        request.controller.as_str(),
+        "bulk" | "collection" | "document" | "realtime"
+    )
+}
+
+/// Whether a request obtains a new token, which then needs no refresh
+fn issues_token(request: &Request) -> bool {
+    request.controller == "auth" && matches!(request.action.as_str(), "login" | "refreshToken")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocols::{ConnectCallback, DisconnectCallback, ErrorCallback, InMemory};
+    use crate::request;
+    use crate::types::KuzzleError;
+
+    use async_trait::async_trait;
+    use serde_json::json;
+    use std::sync::Mutex;
+
+    #[faux::create]
+    pub struct MockedProtocol {}
+
+    #[faux::methods]
+    #[allow(unused_parens)]
+    #[async_trait]
+    impl Protocol for MockedProtocol {
+        async fn connect(&self) -> Result<(), Box<dyn Error>> {
+            todo!()
+        }
+        async fn disconnect(&self) -> Result<(), Box<dyn Error>> {
+            todo!()
+        }
+        async fn disconnect_graceful(&self, _: Duration) -> Result<(), Box<dyn Error>> {
+            todo!()
+        }
+        fn state(&self) -> State {
+            todo!()
+        }
+        fn on_connect(&mut self, _: ConnectCallback) {
+            todo!()
+        }
+        fn on_disconnect(&mut self, _: DisconnectCallback) {
+            todo!()
+        }
+        fn on_error(&mut self, _: ErrorCallback) {
+            todo!()
+        }
+        fn incoming(&self) -> Incoming {
+            todo!()
+        }
+        fn metrics(&self) -> Metrics {
+            todo!()
+        }
+        async fn send(&self, _: Value) -> Result<String, Box<dyn Error>> {
+            todo!()
+        }
+        async fn send_with_timeout(&self, _: Value, _: Duration) -> Result<String, Box<dyn Error>> {
+            todo!()
+        }
+    }
+
+    /// Protocol mock in the given state, accepting lifecycle hooks
+    fn mocked_protocol(state: State) -> MockedProtocol {
+        let mut protocol = MockedProtocol::faux();
+        faux::when!(protocol.state).then(move |_| state);
+        faux::when!(protocol.on_connect).then(|_| ());
+        faux::when!(protocol.on_disconnect).then(|_| ());
+        faux::when!(protocol.on_error).then(|_| ());
+        protocol
+    }
+
+    // Quick way to forge fake errors
+    fn forge_error() -> Box<dyn Error> {
+        Box::new(std::io::Error::last_os_error())
+    }
+
+    #[async_std::test]
+    async fn should_connect() {
+        let mut protocol = mocked_protocol(State::Connected);
+        faux::when!(protocol.connect).then(|_| Ok(()));
+        faux::when!(protocol.incoming).then(|_| Subscribers::default().subscribe());
+
+        let kuzzle = Kuzzle::new(protocol);
+        assert!(kuzzle.connect().await.is_ok());
+    }
+
+    #[async_std::test]
+    async fn should_not_connect() {
+        let mut protocol = mocked_protocol(State::Connected);
+        faux::when!(protocol.connect).then(|_| Err(forge_error()));
+
+        let kuzzle = Kuzzle::new(protocol);
+        assert!(kuzzle.connect().await.is_err());
+    }
+
+    #[async_std::test]
+    async fn should_disconnect() {
+        let mut protocol = mocked_protocol(State::Connected);
+        faux::when!(protocol.disconnect).then(|_| Ok(()));
+
+        let kuzzle = Kuzzle::new(protocol);
+        assert!(kuzzle.disconnect().await.is_ok());
+    }
+
+    #[async_std::test]
+    async fn should_not_disconnect() {
+        let mut protocol = mocked_protocol(State::Connected);
+        faux::when!(protocol.disconnect).then(|_| Err(forge_error()));
+
+        let kuzzle = Kuzzle::new(protocol);
+        assert!(kuzzle.disconnect().await.is_err());
+    }
+
+    #[test]
+    fn should_expose_protocol_state() {
+        let protocol = mocked_protocol(State::Reconnecting);
+
+        let kuzzle = Kuzzle::new(protocol);
+        assert_eq!(kuzzle.state(), State::Reconnecting);
+    }
+
+    #[async_std::test]
+    async fn should_query() -> Result<(), Box<dyn Error>> {
+        let mut protocol = mocked_protocol(State::Connected);
+        faux::when!(protocol.send).then(|request: Value| {
+            Ok(json!({
+                "requestId": request["requestId"],
+                "action": "fakeAction",
+                "controller": "fakeController",
+                "status": 200,
+                "result": {
+                    "success": true
+                }
+            })
+            .to_string())
+        });
+
+        let kuzzle = Kuzzle::new(protocol);
+        let request = request!({
+            "controller": "fakeController",
+            "action": "fakeAction"
+        })?;
+
+        let response = kuzzle.query(&request).await?;
+        assert_eq!(response.status, 200);
+        assert_eq!(response.result.unwrap()["success"], true);
+
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn should_query_concurrently() -> Result<(), Box<dyn Error>> {
+        let mut protocol = mocked_protocol(State::Connected);
+        faux::when!(protocol.send).then(|request: Value| {
+            Ok(json!({
+                "requestId": request["requestId"],
+                "action": "now",
+                "controller": "server",
+                "status": 200
+            })
+            .to_string())
+        });
+
+        let kuzzle = Kuzzle::new(protocol);
+        let queries: Vec<_> = (0..8)
+            .map(|_| {
+                let kuzzle = kuzzle.clone();
+                async_std::task::spawn(async move {
+                    let request = request!({"controller": "server", "action": "now"}).unwrap();
+                    kuzzle
+                        .query(&request)
+                        .await
+                        .map(|response| response.status)
+                        .ok()
+                })
+            })
+            .collect();
+
+        for query in queries {
+            assert_eq!(query.await, Some(200));
+        }
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn should_route_unexpected_responses_to_incoming() -> Result<(), Box<dyn Error>> {
+        let notification = json!({
+            "requestId": "someone-else",
+            "status": 200,
+            "type": "document",
+            "room": "some-room"
+        });
+        let stray = notification.clone();
+
+        let mut protocol = mocked_protocol(State::Connected);
+        faux::when!(protocol.send).then(move |_| Ok(stray.to_string()));
+        faux::when!(protocol.incoming).then(|_| Subscribers::default().subscribe());
+
+        let kuzzle = Kuzzle::new(protocol);
+        let mut incoming = kuzzle.incoming();
+
+        let request = request!({"controller": "server", "action": "now"})?;
+        let error = kuzzle.query(&request).await.unwrap_err();
+
+        assert_eq!(
+            error.downcast_ref::<ProtocolError>(),
+            Some(&ProtocolError::UnexpectedResponse(
+                "someone-else".to_string()
+            ))
+        );
+        assert_eq!(incoming.next().await, Some(notification.to_string()));
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn should_collect_stats() -> Result<(), Box<dyn Error>> {
+        let mut protocol = mocked_protocol(State::Connected);
+        faux::when!(protocol.send).then(|request: Value| {
+            Ok(json!({
+                "requestId": request["requestId"],
+                "action": "get",
+                "controller": "document",
+                "status": 404,
+                "error": {"status": 404, "id": "services.storage.not_found"}
+            })
+            .to_string())
+        });
+
+        let kuzzle = Kuzzle::new(protocol);
+        let request = request!({"controller": "document", "action": "get"})?;
+        kuzzle.query(&request).await?;
+        kuzzle.query(&request).await?;
+
+        let stats = kuzzle.stats();
+        assert_eq!(stats.queries, 2);
+        assert_eq!(stats.failures["not_found"], 2);
+        assert!(stats.average_latency.is_some());
+        assert_eq!(stats.queue_depth, 0);
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn should_limit_query_rate() -> Result<(), Box<dyn Error>> {
+        let mut protocol = mocked_protocol(State::Connected);
+        faux::when!(protocol.send).then(|request: Value| {
+            Ok(json!({
+                "requestId": request["requestId"],
+                "action": "now",
+                "controller": "server",
+                "status": 200
+            })
+            .to_string())
+        });
+
+        let kuzzle = Kuzzle::new(protocol);
+        kuzzle.set_rate_limit(Some(RateLimit::new(1.0).burst(2).fail_fast(true)));
+
+        let request = request!({"controller": "server", "action": "now"})?;
+        assert!(kuzzle.query(&request).await.is_ok());
+        assert!(kuzzle.query(&request).await.is_ok());
+        assert!(kuzzle.query(&request).await.is_err());
+        assert_eq!(kuzzle.stats().queries, 2);
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn should_cancel_queries() -> Result<(), Box<dyn Error>> {
+        let kuzzle = Kuzzle::new(mocked_protocol(State::Reconnecting));
+        let token = CancellationToken::new();
+        let canceller = token.clone();
+        let queue = kuzzle.queue();
+
+        async_std::task::spawn(async move {
+            while queue.is_empty() {
+                async_std::task::sleep(Duration::from_millis(10)).await;
+            }
+            canceller.cancel();
+        });
+
+        let request = request!({"controller": "document", "action": "search"})?;
+        let options = QueryOptions::new().cancel(token);
+        let error = kuzzle
+            .query_with_options(&request, options)
+            .await
+            .unwrap_err();
+
+        assert_eq!(
+            error.downcast_ref::<ProtocolError>(),
+            Some(&ProtocolError::Cancelled)
+        );
+        assert!(kuzzle.queue().is_empty());
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn should_fail_past_the_deadline() -> Result<(), Box<dyn Error>> {
+        let kuzzle = Kuzzle::new(mocked_protocol(State::Reconnecting));
+        let deadline = Duration::from_millis(50);
+
+        let request = request!({"controller": "document", "action": "search"})?;
+        let options = QueryOptions::new().deadline(deadline);
+        let error = kuzzle
+            .query_with_options(&request, options)
+            .await
+            .unwrap_err();
+
+        assert_eq!(
+            error.downcast_ref::<ProtocolError>(),
+            Some(&ProtocolError::DeadlineExceeded(deadline))
+        );
+        assert!(kuzzle.queue().is_empty());
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn should_query_as() -> Result<(), Box<dyn Error>> {
+        #[derive(serde::Deserialize, Debug, PartialEq)]
+        struct Now {
+            now: u64,
+        }
+
+        let mut protocol = mocked_protocol(State::Connected);
+        faux::when!(protocol.send).then(|request: Value| {
+            let (status, result) = match request["action"].as_str() {
+                Some("now") => (200, json!({"now": 1234})),
+                Some("publish") => (503, Value::Null),
+                _ => (404, Value::Null),
+            };
+
+            Ok(json!({
+                "requestId": request["requestId"],
+                "action": request["action"],
+                "controller": request["controller"],
+                "status": status,
+                "result": result,
+                "error": match status {
+                    404 => json!({"status": status, "message": "Not found"}),
+                    _ => Value::Null,
+                }
+            })
+            .to_string())
+        });
+
+        let kuzzle = Kuzzle::new(protocol);
+
+        let now = request!({"controller": "server", "action": "now"})?;
+        assert_eq!(kuzzle.query_as::<Now>(&now).await?, Now { now: 1234 });
+
+        let get = request!({"controller": "document", "action": "get"})?;
+        let error = kuzzle.query_as::<Value>(&get).await.unwrap_err();
+        assert!(matches!(
+            error.downcast_ref::<KuzzleError>(),
+            Some(KuzzleError::NotFound(_))
+        ));
+
+        let publish = request!({"controller": "realtime", "action": "publish"})?;
+        let error = kuzzle.query_as::<Value>(&publish).await.unwrap_err();
+        assert!(error.downcast_ref::<KuzzleError>().is_some());
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn should_share_state_between_clones() -> Result<(), Box<dyn Error>> {
+        fn shareable<T: Clone + Send + Sync + 'static>(_: &T) {}
+
+        let kuzzle = Kuzzle::new(InMemory::new());
+        let clone = kuzzle.clone();
+        shareable(&clone);
+
+        clone.set_jwt(Some("eyJhbGciOi".to_string()));
+        clone.start_queuing();
+        clone.connect().await?;
+
+        assert_eq!(kuzzle.jwt(), Some("eyJhbGciOi".to_string()));
+        assert!(kuzzle.is_queuing());
+        assert_eq!(kuzzle.state(), State::Connected);
+        Ok(())
+    }
+
+    #[test]
+    fn should_expose_the_protocol() {
+        let kuzzle = Kuzzle::new(InMemory::new());
+
+        assert_eq!(kuzzle.protocol().state(), State::Offline);
+        assert!(kuzzle.protocol_as::<InMemory>().is_some());
+        assert!(kuzzle.protocol_as::<MockedProtocol>().is_none());
+    }
+
+    #[async_std::test]
+    async fn should_query_batch() -> Result<(), Box<dyn Error>> {
+        let mut protocol = mocked_protocol(State::Connected);
+        faux::when!(protocol.send).then(|request: Value| {
+            let status = match request["action"].as_str() {
+                Some("now") => 200,
+                _ => 404,
+            };
+
+            Ok(json!({
+                "requestId": request["requestId"],
+                "action": request["action"],
+                "controller": request["controller"],
+                "status": status,
+                "error": match status {
+                    404 => json!({"status": status, "message": "Not found"}),
+                    _ => Value::Null,
+                }
+            })
+            .to_string())
+        });
+
+        let kuzzle = Kuzzle::new(protocol);
+        let requests = vec![
+            request!({"controller": "server", "action": "now"})?,
+            request!({"controller": "document", "action": "get"})?,
+            request!({"controller": "server", "action": "now"})?,
+        ];
+
+        let batch = kuzzle.query_batch(&requests).await;
+        assert_eq!(batch.results.len(), 3);
+        assert_eq!(batch.failures(), vec![1]);
+        assert_eq!(batch.results[0].as_ref().unwrap().action, "now");
+        assert_eq!(batch.results[1].as_ref().unwrap().action, "get");
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn should_inject_jwt() -> Result<(), Box<dyn Error>> {
+        let mut protocol = mocked_protocol(State::Connected);
+        faux::when!(protocol.send).then(|request: Value| {
+            Ok(json!({
+                "requestId": request["requestId"],
+                "action": "fakeAction",
+                "controller": "fakeController",
+                "status": 200,
+                "result": {
+                    "jwt": request["jwt"]
+                }
+            })
+            .to_string())
+        });
+
+        let kuzzle = Kuzzle::new(protocol);
+        kuzzle.set_jwt(Some("stored".to_string()));
+
+        let mut request = request!({
+            "controller": "fakeController",
+            "action": "fakeAction"
+        })?;
+        let response = kuzzle.query(&request).await?;
+        assert_eq!(response.result.unwrap()["jwt"], "stored");
+
+        request.jwt = Some("explicit".to_string());
+        let response = kuzzle.query(&request).await?;
+        assert_eq!(response.result.unwrap()["jwt"], "explicit");
+
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn should_merge_volatile_data() -> Result<(), Box<dyn Error>> {
+        let mut protocol = mocked_protocol(State::Connected);
+        faux::when!(protocol.send).then(|request: Value| {
+            Ok(json!({
+                "requestId": request["requestId"],
+                "action": "now",
+                "controller": "server",
+                "status": 200,
+                "volatile": request["volatile"]
+            })
+            .to_string())
+        });
+
+        let kuzzle = Kuzzle::new(protocol);
+        kuzzle.set_volatile(json!({"app": "foo", "device": "bar"}));
+
+        let request = request!({
+            "controller": "server",
+            "action": "now",
+            "volatile": {"device": "baz", "user": "qux"}
+        })?;
+        let response = kuzzle.query(&request).await?;
+
+        assert_eq!(
+            response.volatile,
+            Some(json!({"app": "foo", "device": "baz", "user": "qux"}))
+        );
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn should_not_send_unset_volatile_data() -> Result<(), Box<dyn Error>> {
+        let protocol = InMemory::new();
+        protocol.respond(json!({}));
+        let kuzzle = Kuzzle::new(protocol.clone());
+        kuzzle.connect().await?;
+
+        kuzzle
+            .query(&request!({"controller": "server", "action": "now"})?)
+            .await?;
+
+        assert_eq!(protocol.requests()[0].get("volatile"), None);
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn should_not_send_unset_refresh() -> Result<(), Box<dyn Error>> {
+        let protocol = InMemory::new();
+        protocol.respond(json!({}));
+        let kuzzle = Kuzzle::new(protocol.clone());
+        kuzzle.connect().await?;
+
+        let request = request!({"controller": "server", "action": "now"})?;
+        kuzzle
+            .query_with_options(&request, QueryOptions::new())
+            .await?;
+
+        assert_eq!(protocol.requests()[0].get("refresh"), None);
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn should_apply_query_options() -> Result<(), Box<dyn Error>> {
+        let mut protocol = mocked_protocol(State::Connected);
+        faux::when!(protocol.send_with_timeout).then(|(request, timeout): (Value, Duration)| {
+            assert_eq!(timeout, Duration::from_secs(5));
+            assert_eq!(request["refresh"], "wait_for");
+            assert_eq!(
+                request["headers"],
+                json!({"x-origin": "import", "x-user": "qux"})
+            );
+
+            Ok(json!({
+                "requestId": request["requestId"],
+                "action": "create",
+                "controller": "document",
+                "status": 200,
+                "volatile": request["volatile"]
+            })
+            .to_string())
+        });
+
+        let kuzzle = Kuzzle::new(protocol);
+        kuzzle.set_volatile(json!({"app": "foo", "origin": "bar"}));
+
+        let request = request!({
+            "controller": "document",
+            "action": "create",
+            "volatile": {"user": "qux"},
+            "headers": {"x-user": "qux"}
+        })?;
+        let options = QueryOptions::new()
+            .timeout(Duration::from_secs(5))
+            .volatile(json!({"origin": "baz", "user": "quux"}))
+            .header("x-origin", "import")
+            .header("x-user", "quux")
+            .refresh(Refresh::WaitFor);
+        let response = kuzzle.query_with_options(&request, options).await?;
+
+        assert_eq!(
+            response.volatile,
+            Some(json!({"app": "foo", "origin": "baz", "user": "qux"}))
+        );
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn should_apply_request_timeout() -> Result<(), Box<dyn Error>> {
+        let mut protocol = mocked_protocol(State::Connected);
+        faux::when!(protocol.send_with_timeout).then(|(request, timeout): (Value, Duration)| {
+            let expected = match request["action"].as_str() {
+                Some("search") => Duration::from_secs(30),
+                _ => Duration::from_secs(5),
+            };
+            assert_eq!(timeout, expected);
+
+            Ok(json!({
+                "requestId": request["requestId"],
+                "action": request["action"],
+                "controller": request["controller"],
+                "status": 200
+            })
+            .to_string())
+        });
+
+        let kuzzle = Kuzzle::new(protocol);
+        kuzzle.set_request_timeout(Some(Duration::from_secs(5)));
+
+        let now = request!({"controller": "server", "action": "now"})?;
+        kuzzle.query(&now).await?;
+
+        let search = request!({"controller": "document", "action": "search"})?;
+        let options = QueryOptions::new().timeout(Duration::from_secs(30));
+        kuzzle.query_with_options(&search, options).await?;
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn should_apply_default_index() -> Result<(), Box<dyn Error>> {
+        let mut protocol = mocked_protocol(State::Connected);
+        faux::when!(protocol.send).then(|request: Value| {
+            Ok(json!({
+                "requestId": request["requestId"],
+                "action": "search",
+                "controller": "document",
+                "index": request["index"],
+                "collection": request["collection"],
+                "status": 200
+            })
+            .to_string())
+        });
+
+        let kuzzle = Kuzzle::new(protocol);
+        kuzzle.set_default_index(Some("nyc-open-data".to_string()));
+
+        let search = request!({
+            "controller": "document",
+            "action": "search",
+            "collection": "yellow-taxi"
+        })?;
+        let response = kuzzle.query(&search).await?;
+
+        assert_eq!(response.index, Some("nyc-open-data".to_string()));
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn should_apply_default_collection() -> Result<(), Box<dyn Error>> {
+        let mut protocol = mocked_protocol(State::Connected);
+        faux::when!(protocol.send).then(|request: Value| {
+            Ok(json!({
+                "requestId": request["requestId"],
+                "action": request["action"],
+                "controller": request["controller"],
+                "index": request["index"],
+                "collection": request["collection"],
+                "status": 200
+            })
+            .to_string())
+        });
+
+        let kuzzle = Kuzzle::new(protocol);
+        kuzzle.set_default_index(Some("nyc-open-data".to_string()));
+        kuzzle.set_default_collection(Some("yellow-taxi".to_string()));
+
+        let search = request!({"controller": "document", "action": "search"})?;
+        let response = kuzzle.query(&search).await?;
+        assert_eq!(response.index, Some("nyc-open-data".to_string()));
+        assert_eq!(response.collection, Some("yellow-taxi".to_string()));
+
+        let list = request!({"controller": "collection", "action": "list", "index": "mtgdb"})?;
+        assert_eq!(kuzzle.query(&list).await?.collection, None);
+
+        let now = request!({"controller": "server", "action": "now"})?;
+        assert_eq!(kuzzle.query(&now).await?.collection, None);
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn should_not_queue_unqueuable_queries() -> Result<(), Box<dyn Error>> {
+        let kuzzle = Kuzzle::new(mocked_protocol(State::Reconnecting));
+
+        let request = request!({"controller": "server", "action": "now"})?;
+        let options = QueryOptions::new().queuable(false);
+
+        assert!(kuzzle.query_with_options(&request, options).await.is_err());
+        assert!(kuzzle.queue().is_empty());
+        Ok(())
+    }
+
+    struct Prefix;
+
+    #[async_trait]
+    impl Middleware for Prefix {
+        async fn on_request(&self, request: &mut Request) -> Result<(), Box<dyn Error>> {
+            request.index = request
+                .index
+                .as_ref()
+                .map(|index| format!("acme-{}", index));
+            Ok(())
+        }
+    }
+
+    struct Reject;
+
+    #[async_trait]
+    impl Middleware for Reject {
+        async fn on_request(&self, _: &mut Request) -> Result<(), Box<dyn Error>> {
+            Err(forge_error())
+        }
+    }
+
+    struct Redact;
+
+    #[async_trait]
+    impl Middleware for Redact {
+        async fn on_response(
+            &self,
+            _: &Request,
+            response: &mut Response,
+        ) -> Result<(), Box<dyn Error>> {
+            if let Some(result) = response.result.as_mut() {
+                result["password"] = json!("***");
+            }
+            Ok(())
         }
     }
 
-    pub async fn connect(&mut self) -> Result<(), Box<dyn Error>> {
-        self.protocol.connect().await
+    #[async_std::test]
+    async fn should_run_response_middlewares() -> Result<(), Box<dyn Error>> {
+        let mut protocol = mocked_protocol(State::Connected);
+        faux::when!(protocol.send).then(|request: Value| {
+            Ok(json!({
+                "requestId": request["requestId"],
+                "action": "get",
+                "controller": "document",
+                "status": 200,
+                "result": {"login": "foo", "password": "bar"}
+            })
+            .to_string())
+        });
+
+        let kuzzle = Kuzzle::new(protocol);
+        kuzzle.add_middleware(Redact);
+
+        let request = request!({"controller": "document", "action": "get"})?;
+        let response = kuzzle.query(&request).await?;
+
+        assert_eq!(
+            response.result,
+            Some(json!({"login": "foo", "password": "***"}))
+        );
+        Ok(())
     }
 
-    pub async fn disconnect(&mut self) -> Result<(), Box<dyn Error>> {
-        self.protocol.disconnect().await
+    #[async_std::test]
+    async fn should_run_middlewares() -> Result<(), Box<dyn Error>> {
+        let mut protocol = mocked_protocol(State::Connected);
+        faux::when!(protocol.send).then(|request: Value| {
+            Ok(json!({
+                "requestId": request["requestId"],
+                "action": "get",
+                "controller": "document",
+                "index": request["index"],
+                "status": 200
+            })
+            .to_string())
+        });
+
+        let kuzzle = Kuzzle::new(protocol);
+        kuzzle.add_middleware(Prefix);
+
+        let request = request!({"controller": "document", "action": "get", "index": "foo"})?;
+        let response = kuzzle.query(&request).await?;
+        assert_eq!(response.index, Some("acme-foo".to_string()));
+
+        kuzzle.add_middleware(Reject);
+        assert!(kuzzle.query(&request).await.is_err());
+
+        Ok(())
     }
 
-    pub async fn query(&mut self, request: &Request) -> Result<Response, Box<dyn Error>> {
-        let response = self.protocol.send(serde_json::to_string(&request)?).await?;
-        Ok(serde_json::from_str(&response)?)
+    #[async_std::test]
+    async fn should_retry_transient_failures() -> Result<(), Box<dyn Error>> {
+        let attempts = Arc::new(Mutex::new(0));
+        let counted = attempts.clone();
+
+        let mut protocol = mocked_protocol(State::Connected);
+        faux::when!(protocol.send).then(move |request: Value| {
+            let mut attempts = counted.lock().unwrap();
+            *attempts += 1;
+            let status = match *attempts {
+                1 | 2 => 503,
+                _ => 200,
+            };
+
+            Ok(json!({
+                "requestId": request["requestId"],
+                "action": request["action"],
+                "controller": "document",
+                "status": status,
+                "error": match status {
+                    200 => Value::Null,
+                    _ => json!({"status": status, "message": "Overloaded"}),
+                }
+            })
+            .to_string())
+        });
+
+        let kuzzle = Kuzzle::new(protocol);
+        kuzzle.set_retry_policy(Some(
+            RetryPolicy::new().initial_delay(Duration::from_millis(1)),
+        ));
+
+        let search = request!({"controller": "document", "action": "search"})?;
+        assert_eq!(kuzzle.query(&search).await?.status, 200);
+        assert_eq!(*attempts.lock().unwrap(), 3);
+
+        let create = request!({"controller": "document", "action": "create"})?;
+        *attempts.lock().unwrap() = 0;
+        assert_eq!(kuzzle.query(&create).await?.status, 503);
+        assert_eq!(*attempts.lock().unwrap(), 1);
+
+        Ok(())
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use crate::request;
+    /// Protocol answering `auth` actions as Kuzzle would, and echoing the
+    /// token of any other request
+    fn authenticating_protocol(refreshed: bool) -> MockedProtocol {
+        let mut protocol = mocked_protocol(State::Connected);
+        faux::when!(protocol.send).then(move |request: Value| {
+            let result = match request["action"].as_str() {
+                Some("login") => json!({"jwt": "first", "ttl": 0}),
+                Some("refreshToken") if refreshed => json!({"jwt": "second", "ttl": 3600000}),
+                Some("refreshToken") => {
+                    return Ok(json!({
+                        "requestId": request["requestId"],
+                        "action": "refreshToken",
+                        "controller": "auth",
+                        "status": 401,
+                        "error": {"id": "security.token.invalid"}
+                    })
+                    .to_string())
+                }
+                _ => json!({"jwt": request["jwt"]}),
+            };
 
-    use async_trait::async_trait;
-    use serde_json::json;
+            Ok(json!({
+                "requestId": request["requestId"],
+                "action": request["action"],
+                "controller": request["controller"],
+                "status": 200,
+                "result": result
+            })
+            .to_string())
+        });
+        protocol
+    }
 
-    #[faux::create]
-    pub struct MockedProtocol {}
+    #[async_std::test]
+    async fn should_refresh_expiring_token() -> Result<(), Box<dyn Error>> {
+        let kuzzle = Kuzzle::new(authenticating_protocol(true));
+        kuzzle
+            .query(&request!({"controller": "auth", "action": "login"})?)
+            .await?;
+        assert_eq!(kuzzle.jwt(), Some("first".to_string()));
+
+        let request = request!({"controller": "server", "action": "now"})?;
+        let response = kuzzle.query(&request).await?;
+
+        assert_eq!(response.result.unwrap()["jwt"], "second");
+        assert_eq!(kuzzle.jwt(), Some("second".to_string()));
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn should_emit_token_expired_when_refresh_fails() -> Result<(), Box<dyn Error>> {
+        let kuzzle = Kuzzle::new(authenticating_protocol(false));
+        let events = record_events(&kuzzle);
+        kuzzle
+            .query(&request!({"controller": "auth", "action": "login"})?)
+            .await?;
+
+        let request = request!({"controller": "server", "action": "now"})?;
+        kuzzle.query(&request).await?;
+        kuzzle.query(&request).await?;
+
+        assert_eq!(*events.lock().unwrap(), vec!["TokenExpired"]);
+        Ok(())
+    }
+
+    struct Relogin(Arc<Mutex<u32>>);
 
-    #[faux::methods]
-    #[allow(unused_parens)]
     #[async_trait]
-    impl Protocol for MockedProtocol {
-        async fn connect(&mut self) -> Result<(), Box<dyn Error>> {
-            todo!()
+    impl Authenticator for Relogin {
+        async fn authenticate(&self, _kuzzle: &Kuzzle) -> Result<String, Box<dyn Error>> {
+            *self.0.lock().unwrap() += 1;
+            Ok("fresh".to_string())
         }
-        async fn disconnect(&mut self) -> Result<(), Box<dyn Error>> {
-            todo!()
+    }
+
+    #[async_std::test]
+    async fn should_authenticate_again_when_unauthorized() -> Result<(), Box<dyn Error>> {
+        let mut protocol = mocked_protocol(State::Connected);
+        faux::when!(protocol.send).then(|request: Value| {
+            let status = match request["jwt"].as_str() {
+                Some("fresh") | None => 200,
+                _ => 401,
+            };
+
+            Ok(json!({
+                "requestId": request["requestId"],
+                "action": request["action"],
+                "controller": request["controller"],
+                "status": status,
+                "error": match status {
+                    200 => Value::Null,
+                    _ => json!({"status": 401, "id": "security.token.expired"}),
+                }
+            })
+            .to_string())
+        });
+
+        let kuzzle = Kuzzle::new(protocol);
+        let logins = Arc::new(Mutex::new(0));
+        kuzzle.set_authenticator(Relogin(logins.clone()));
+        kuzzle.set_jwt(Some("expired".to_string()));
+
+        let request = request!({"controller": "server", "action": "now"})?;
+        let response = kuzzle.query(&request).await?;
+        assert_eq!(response.status, 200);
+        assert_eq!(kuzzle.jwt(), Some("fresh".to_string()));
+        assert_eq!(*logins.lock().unwrap(), 1);
+
+        // Queries providing their own token are not retried
+        let request = request!({"controller": "server", "action": "now", "jwt": "own"})?;
+        assert_eq!(kuzzle.query(&request).await?.status, 401);
+        assert_eq!(*logins.lock().unwrap(), 1);
+        Ok(())
+    }
+
+    /// Authenticator taking some time, recording the stored token it is
+    /// asked to replace
+    struct SlowRelogin(Arc<Mutex<Vec<Option<String>>>>);
+
+    #[async_trait]
+    impl Authenticator for SlowRelogin {
+        async fn authenticate(&self, kuzzle: &Kuzzle) -> Result<String, Box<dyn Error>> {
+            self.0.lock().unwrap().push(kuzzle.jwt());
+            async_std::task::sleep(Duration::from_millis(50)).await;
+            Ok("fresh".to_string())
         }
-        async fn send(&mut self, _: String) -> Result<String, Box<dyn Error>> {
-            todo!()
+    }
+
+    #[async_std::test]
+    async fn should_authenticate_once_for_concurrent_rejections() -> Result<(), Box<dyn Error>> {
+        let mut protocol = mocked_protocol(State::Connected);
+        faux::when!(protocol.send).then(|request: Value| {
+            let status = match request["jwt"].as_str() {
+                Some("fresh") => 200,
+                _ => 401,
+            };
+
+            Ok(json!({
+                "requestId": request["requestId"],
+                "action": request["action"],
+                "controller": request["controller"],
+                "status": status,
+                "error": match status {
+                    200 => Value::Null,
+                    _ => json!({"status": 401, "id": "security.token.expired"}),
+                }
+            })
+            .to_string())
+        });
+
+        let kuzzle = Kuzzle::new(protocol);
+        let logins = Arc::new(Mutex::new(Vec::new()));
+        kuzzle.set_authenticator(SlowRelogin(logins.clone()));
+        kuzzle.set_jwt(Some("expired".to_string()));
+
+        let request = request!({"controller": "server", "action": "now"})?;
+        let responses = future::join_all((0..3).map(|_| kuzzle.query(&request))).await;
+
+        for response in responses {
+            assert_eq!(response?.status, 200);
         }
+        assert_eq!(*logins.lock().unwrap(), vec![Some("expired".to_string())]);
+        assert_eq!(kuzzle.jwt(), Some("fresh".to_string()));
+        Ok(())
     }
 
-    // Quick way to forge fake errors
-    fn forge_error() -> Box<dyn Error> {
-        Box::new(std::io::Error::last_os_error())
+    #[async_std::test]
+    async fn should_not_parse_response() -> Result<(), Box<dyn Error>> {
+        let mut protocol = mocked_protocol(State::Connected);
+        faux::when!(protocol.send).then(|_| Ok(String::from("NOT A VALID JSON STRING")));
+
+        let kuzzle = Kuzzle::new(protocol);
+        let request = request!({
+            "controller": "fakeController",
+            "action": "fakeAction"
+        })?;
+
+        let result = kuzzle.query(&request).await;
+        assert!(result.is_err());
+
+        Ok(())
     }
 
     #[async_std::test]
-    async fn should_connect() {
-        let mut protocol = MockedProtocol::faux();
-        faux::when!(protocol.connect).then(|_| Ok(()));
+    async fn should_fail_query_discarded_from_queue() -> Result<(), Box<dyn Error>> {
+        let protocol = mocked_protocol(State::Reconnecting);
+        let kuzzle = Kuzzle::new(protocol);
+        let queue = kuzzle.queue();
 
-        let mut kuzzle = Kuzzle::new(protocol);
-        assert!(kuzzle.connect().await.is_ok());
+        async_std::task::spawn(async move {
+            while queue.is_empty() {
+                async_std::task::sleep(Duration::from_millis(10)).await;
+            }
+            queue.flush();
+        });
+
+        let request = request!({
+            "controller": "fakeController",
+            "action": "fakeAction"
+        })?;
+
+        assert!(kuzzle.query(&request).await.is_err());
+        assert!(kuzzle.queue().is_empty());
+
+        Ok(())
     }
 
     #[async_std::test]
-    async fn should_not_connect() {
-        let mut protocol = MockedProtocol::faux();
-        faux::when!(protocol.connect).then(|_| Err(forge_error()));
+    async fn should_queue_while_queuing() -> Result<(), Box<dyn Error>> {
+        let mut protocol = mocked_protocol(State::Connected);
+        faux::when!(protocol.send).then(|request: Value| {
+            Ok(json!({
+                "requestId": request["requestId"],
+                "action": "now",
+                "controller": "server",
+                "status": 200
+            })
+            .to_string())
+        });
 
-        let mut kuzzle = Kuzzle::new(protocol);
-        assert!(kuzzle.connect().await.is_err());
+        let kuzzle = Kuzzle::new(protocol);
+        let queue = kuzzle.queue();
+        kuzzle.start_queuing();
+
+        async_std::task::spawn(async move {
+            while queue.is_empty() {
+                async_std::task::sleep(Duration::from_millis(10)).await;
+            }
+            queue.play();
+        });
+
+        let request = request!({"controller": "server", "action": "now"})?;
+        assert_eq!(kuzzle.query(&request).await?.status, 200);
+
+        kuzzle.stop_queuing();
+        assert!(!kuzzle.is_queuing());
+        assert_eq!(kuzzle.query(&request).await?.status, 200);
+        assert!(kuzzle.queue().is_empty());
+
+        Ok(())
+    }
+
+    /// Record the events emitted by a client, by name
+    fn record_events(kuzzle: &Kuzzle) -> Arc<Mutex<Vec<String>>> {
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let recorded = events.clone();
+
+        kuzzle.on_event(Box::new(move |event: &Event| {
+            let name = format!("{:?}", event);
+            let name = name.split(&['(', ' '][..]).next().unwrap_or_default();
+            recorded.lock().unwrap().push(name.to_string());
+        }));
+
+        events
+    }
+
+    /// Queue store keeping the requests in memory
+    #[derive(Clone, Default)]
+    struct MemoryStore(Arc<Mutex<Vec<Request>>>);
+
+    impl QueueStore for MemoryStore {
+        fn save(&self, request: &Request) -> Result<(), Box<dyn Error>> {
+            self.0.lock().unwrap().push(request.clone());
+            Ok(())
+        }
+
+        fn remove(&self, request_id: &str) -> Result<(), Box<dyn Error>> {
+            self.0
+                .lock()
+                .unwrap()
+                .retain(|request| request.request_id != request_id);
+            Ok(())
+        }
+
+        fn load(&self) -> Result<Vec<Request>, Box<dyn Error>> {
+            Ok(self.0.lock().unwrap().clone())
+        }
     }
 
     #[async_std::test]
-    async fn should_disconnect() {
-        let mut protocol = MockedProtocol::faux();
-        faux::when!(protocol.disconnect).then(|_| Ok(()));
+    async fn should_send_the_stored_queries_again() -> Result<(), Box<dyn Error>> {
+        let store = MemoryStore::default();
+        store.save(&request!({
+            "controller": "document",
+            "action": "create",
+            "jwt": "stale"
+        })?)?;
 
-        let mut kuzzle = Kuzzle::new(protocol);
-        assert!(kuzzle.disconnect().await.is_ok());
+        let protocol = InMemory::new();
+        protocol.respond(json!({"result": {"_id": "foo"}}));
+        let kuzzle = Kuzzle::new(protocol.clone());
+        kuzzle.set_jwt(Some("current".to_string()));
+        kuzzle.set_queue_store(store.clone()).await?;
+
+        // Queued until connected, without being stored twice
+        assert_eq!(kuzzle.queue().len(), 1);
+        assert_eq!(store.load()?.len(), 1);
+        assert!(protocol.requests().is_empty());
+
+        kuzzle.connect().await?;
+        for _ in 0..100 {
+            if !protocol.requests().is_empty() {
+                break;
+            }
+            async_std::task::sleep(Duration::from_millis(10)).await;
+        }
+
+        assert!(store.load()?.is_empty());
+        assert_eq!(protocol.requests()[0]["action"], "create");
+        assert_eq!(protocol.requests()[0]["jwt"], "current");
+        Ok(())
     }
 
     #[async_std::test]
-    async fn should_not_disconnect() {
-        let mut protocol = MockedProtocol::faux();
-        faux::when!(protocol.disconnect).then(|_| Err(forge_error()));
+    async fn should_shut_down_once_idle() -> Result<(), Box<dyn Error>> {
+        let protocol = InMemory::new();
+        protocol.respond(json!({"result": {"roomId": "room", "channel": "channel"}}));
+        let kuzzle = Kuzzle::new(protocol.clone());
+        kuzzle.connect().await?;
 
-        let mut kuzzle = Kuzzle::new(protocol);
-        assert!(kuzzle.disconnect().await.is_err());
+        let subscribe = request!({
+            "controller": "realtime",
+            "action": "subscribe",
+            "index": "nyc-open-data",
+            "collection": "yellow-taxi"
+        })?;
+        kuzzle.query(&subscribe).await?;
+
+        protocol.respond(json!({}));
+        protocol.respond(json!({}));
+        kuzzle.start_queuing();
+        let queued = {
+            let kuzzle = kuzzle.clone();
+            async_std::task::spawn(async move {
+                let now = request!({"controller": "server", "action": "now"}).unwrap();
+                kuzzle
+                    .query(&now)
+                    .await
+                    .map(|response| response.status)
+                    .ok()
+            })
+        };
+        while kuzzle.queue().is_empty() {
+            async_std::task::sleep(Duration::from_millis(10)).await;
+        }
+
+        kuzzle.shutdown(Duration::from_secs(1)).await?;
+
+        assert_eq!(queued.await, Some(200));
+        assert_eq!(kuzzle.state(), State::Offline);
+        let mut actions: Vec<Value> = protocol
+            .requests()
+            .iter()
+            .map(|request| request["action"].clone())
+            .collect();
+        actions.sort_by_key(|action| action.to_string());
+        assert_eq!(actions, vec!["now", "subscribe", "unsubscribe"]);
+
+        let now = request!({"controller": "server", "action": "now"})?;
+        assert!(kuzzle.query(&now).await.is_err());
+        Ok(())
     }
 
     #[async_std::test]
-    async fn should_query() -> Result<(), Box<dyn Error>> {
+    async fn should_send_queued_queries_with_the_current_token() -> Result<(), Box<dyn Error>> {
+        let protocol = InMemory::new();
+        protocol.respond(json!({}));
+        let kuzzle = Kuzzle::new(protocol.clone());
+        kuzzle.connect().await?;
+        kuzzle.set_jwt(Some("stale".to_string()));
+
+        kuzzle.start_queuing();
+        let queued = {
+            let kuzzle = kuzzle.clone();
+            async_std::task::spawn(async move {
+                let now = request!({"controller": "server", "action": "now"}).unwrap();
+                kuzzle.query(&now).await.is_ok()
+            })
+        };
+        while kuzzle.queue().is_empty() {
+            async_std::task::sleep(Duration::from_millis(10)).await;
+        }
+
+        kuzzle.set_jwt(Some("renewed".to_string()));
+        kuzzle.stop_queuing();
+        kuzzle.play_queue();
+
+        assert!(queued.await);
+        assert_eq!(protocol.requests()[0]["jwt"], "renewed");
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn should_send_the_queue_before_disconnecting_gracefully() -> Result<(), Box<dyn Error>> {
+        let protocol = InMemory::new();
+        protocol.respond(json!({}));
+        let kuzzle = Kuzzle::new(protocol.clone());
+        kuzzle.connect().await?;
+
+        kuzzle.start_queuing();
+        let queued = {
+            let kuzzle = kuzzle.clone();
+            async_std::task::spawn(async move {
+                let now = request!({"controller": "server", "action": "now"}).unwrap();
+                kuzzle
+                    .query(&now)
+                    .await
+                    .map(|response| response.status)
+                    .ok()
+            })
+        };
+        while kuzzle.queue().is_empty() {
+            async_std::task::sleep(Duration::from_millis(10)).await;
+        }
+
+        kuzzle.disconnect_graceful(Duration::from_secs(1)).await?;
+
+        assert_eq!(queued.await, Some(200));
+        assert_eq!(protocol.requests().len(), 1);
+        assert_eq!(kuzzle.state(), State::Offline);
+        Ok(())
+    }
+
+    #[test]
+    fn should_emit_reconnect_failures() {
+        let mut protocol = mocked_protocol(State::Offline);
+        let failed: Arc<Mutex<Option<ErrorCallback>>> = Arc::new(Mutex::new(None));
+        let slot = failed.clone();
+        faux::when!(protocol.on_error).then(move |callback: ErrorCallback| {
+            *slot.lock().unwrap() = Some(callback);
+        });
+
+        let kuzzle = Kuzzle::new(protocol);
+        let events = record_events(&kuzzle);
+
+        if let Some(failed) = failed.lock().unwrap().as_ref() {
+            failed(&*forge_error());
+            failed(&ProtocolError::ReconnectFailed(3));
+        }
+
+        assert_eq!(*events.lock().unwrap(), vec!["ReconnectFailed"]);
+    }
+
+    #[async_std::test]
+    async fn should_emit_connection_events() -> Result<(), Box<dyn Error>> {
+        let kuzzle = Kuzzle::new(InMemory::new());
+        let events = record_events(&kuzzle);
+
+        kuzzle.connect().await?;
+        kuzzle.disconnect().await?;
+
+        assert_eq!(*events.lock().unwrap(), vec!["Connected", "Disconnected"]);
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn should_restore_the_session_when_reconnected() -> Result<(), Box<dyn Error>> {
+        let connected: Arc<Mutex<Option<ConnectCallback>>> = Arc::default();
+        let disconnected: Arc<Mutex<Option<DisconnectCallback>>> = Arc::default();
+        let sent = Arc::new(Mutex::new(Vec::new()));
+
         let mut protocol = MockedProtocol::faux();
-        faux::when!(protocol.send).then(|_| {
+        faux::when!(protocol.state).then(|_| State::Connected);
+        let slot = connected.clone();
+        faux::when!(protocol.on_connect).then(move |callback: ConnectCallback| {
+            *slot.lock().unwrap() = Some(callback);
+        });
+        let slot = disconnected.clone();
+        faux::when!(protocol.on_disconnect).then(move |callback: DisconnectCallback| {
+            *slot.lock().unwrap() = Some(callback);
+        });
+        faux::when!(protocol.on_error).then(|_| ());
+        let log = sent.clone();
+        faux::when!(protocol.send).then(move |request: Value| {
+            log.lock().unwrap().push(format!(
+                "{}:{}",
+                request["controller"].as_str().unwrap_or_default(),
+                request["action"].as_str().unwrap_or_default()
+            ));
+            let result = match request["action"].as_str() {
+                Some("checkToken") => json!({"valid": false}),
+                Some("subscribe") => json!({"roomId": "room", "channel": "channel"}),
+                _ => Value::Null,
+            };
+
             Ok(json!({
-                "requestId": "my-fake-request-id",
-                "action": "fakeAction",
-                "controller": "fakeController",
+                "requestId": request["requestId"],
+                "action": request["action"],
+                "controller": request["controller"],
                 "status": 200,
-                "result": {
-                    "success": true
-                }
+                "result": result
             })
             .to_string())
         });
 
-        let mut kuzzle = Kuzzle::new(protocol);
-        let request = request!({
-            "controller": "fakeController",
-            "action": "fakeAction"
+        let kuzzle = Kuzzle::new(protocol);
+        let events = record_events(&kuzzle);
+        kuzzle.set_jwt(Some("expired".to_string()));
+
+        let subscribe = request!({
+            "controller": "realtime",
+            "action": "subscribe",
+            "index": "nyc-open-data",
+            "collection": "yellow-taxi"
         })?;
+        kuzzle.query(&subscribe).await?;
 
-        let response = kuzzle.query(&request).await?;
-        assert_eq!(response.status, 200);
-        assert_eq!(response.result.unwrap()["success"], true);
+        if let Some(lost) = disconnected.lock().unwrap().as_ref() {
+            lost(&DisconnectReason::ConnectionLost);
+        }
+        if let Some(reconnected) = connected.lock().unwrap().as_ref() {
+            reconnected();
+        }
+        for _ in 0..100 {
+            if events.lock().unwrap().contains(&"Reconnected".to_string()) {
+                break;
+            }
+            async_std::task::sleep(Duration::from_millis(10)).await;
+        }
 
+        assert_eq!(
+            *sent.lock().unwrap(),
+            vec![
+                "realtime:subscribe",
+                "auth:checkToken",
+                "realtime:subscribe"
+            ]
+        );
+        assert_eq!(
+            *events.lock().unwrap(),
+            vec![
+                "Disconnected",
+                "TokenExpired",
+                "Resubscribed",
+                "Reconnected"
+            ]
+        );
+        assert_eq!(kuzzle.jwt(), None);
         Ok(())
     }
 
     #[async_std::test]
-    async fn should_not_parse_response() -> Result<(), Box<dyn Error>> {
-        let mut protocol = MockedProtocol::faux();
-        faux::when!(protocol.send).then(|_| Ok(String::from("NOT A VALID JSON STRING")));
+    async fn should_emit_query_errors() -> Result<(), Box<dyn Error>> {
+        let mut protocol = mocked_protocol(State::Connected);
+        faux::when!(protocol.send).then(|request: Value| {
+            Ok(json!({
+                "requestId": request["requestId"],
+                "action": "fakeAction",
+                "controller": "fakeController",
+                "status": 401,
+                "error": {
+                    "id": "security.token.expired",
+                    "message": "Token expired"
+                }
+            })
+            .to_string())
+        });
 
-        let mut kuzzle = Kuzzle::new(protocol);
+        let kuzzle = Kuzzle::new(protocol);
+        let events = record_events(&kuzzle);
+        kuzzle.set_jwt(Some("eyJhbGciOi".to_string()));
         let request = request!({
             "controller": "fakeController",
             "action": "fakeAction"
         })?;
 
-        let result = kuzzle.query(&request).await;
-        assert!(result.is_err());
+        assert_eq!(kuzzle.query(&request).await?.status, 401);
+        assert_eq!(*events.lock().unwrap(), vec!["TokenExpired", "QueryError"]);
+        assert_eq!(kuzzle.jwt(), None);
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn should_forget_the_token_when_notified() -> Result<(), Box<dyn Error>> {
+        let protocol = InMemory::new();
+        let kuzzle = Kuzzle::new(protocol.clone());
+        let events = record_events(&kuzzle);
+        kuzzle.set_jwt(Some("eyJhbGciOi".to_string()));
+        kuzzle.connect().await?;
+
+        protocol.notify(json!({"type": "TokenExpired", "message": "Authentication Token Expired"}));
+        for _ in 0..100 {
+            if kuzzle.jwt().is_none() {
+                break;
+            }
+            async_std::task::sleep(Duration::from_millis(10)).await;
+        }
 
+        assert_eq!(kuzzle.jwt(), None);
+        assert_eq!(*events.lock().unwrap(), vec!["Connected", "TokenExpired"]);
         Ok(())
     }
 }
@@ -1,5 +1,21 @@
+pub mod auth;
+pub mod batch;
+#[cfg(feature = "blocking")]
+pub mod blocking;
+pub mod builder;
+pub mod cancel;
+pub mod controllers;
+pub mod events;
 pub mod kuzzle;
+pub mod middleware;
 pub mod protocols;
+pub mod queue;
+pub mod queue_store;
+pub mod rate_limit;
+pub mod retry;
+mod runtime;
+pub mod stats;
 pub mod types;
 
-pub use crate::kuzzle::Kuzzle;
+pub use crate::builder::KuzzleBuilder;
+pub use crate::kuzzle::{Kuzzle, QueryOptions, Refresh};
@@ -0,0 +1,55 @@
+use async_trait::async_trait;
+use std::error::Error;
+
+use crate::types::{Request, Response};
+
+/// Step run on every query before it is sent, e.g. to enforce naming
+/// conventions or add tracing identifiers, and on its response before it is
+/// returned, e.g. to map errors or redact sensitive fields. Requests go
+/// through middlewares in the order they were added to the client, and
+/// responses in the reverse order.
+///
+/// # Example
+///
+/// ```
+/// use async_trait::async_trait;
+/// use kuzzle::middleware::Middleware;
+/// use kuzzle::protocols::InMemory;
+/// use kuzzle::types::Request;
+/// use kuzzle::Kuzzle;
+/// use std::error::Error;
+///
+/// /// Isolate the indexes of each tenant
+/// struct Tenant(String);
+///
+/// #[async_trait]
+/// impl Middleware for Tenant {
+///     async fn on_request(&self, request: &mut Request) -> Result<(), Box<dyn Error>> {
+///         if let Some(index) = &request.index {
+///             request.index = Some(format!("{}-{}", self.0, index));
+///         }
+///         Ok(())
+///     }
+/// }
+///
+/// let kuzzle = Kuzzle::new(InMemory::new());
+/// kuzzle.add_middleware(Tenant("acme".to_string()));
+/// ```
+#[async_trait]
+pub trait Middleware: Send + Sync {
+    /// Inspect or alter a request. Returning an error aborts the query, which
+    /// fails with it.
+    async fn on_request(&self, _request: &mut Request) -> Result<(), Box<dyn Error>> {
+        Ok(())
+    }
+
+    /// Inspect or alter the response to a request. Returning an error makes
+    /// the query fail with it.
+    async fn on_response(
+        &self,
+        _request: &Request,
+        _response: &mut Response,
+    ) -> Result<(), Box<dyn Error>> {
+        Ok(())
+    }
+}
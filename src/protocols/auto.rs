@@ -0,0 +1,382 @@
+use async_trait::async_trait;
+use futures_util::future::join;
+use serde_json::Value;
+use std::error::Error;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use super::state::AggregatedHooks;
+use super::{ConnectCallback, DisconnectCallback, DisconnectReason, ErrorCallback};
+#[cfg(feature = "wire-trace")]
+use super::{Frame, FrameCallback};
+use super::{Http, Incoming, Metrics, Protocol, State, WebSocket};
+use crate::runtime;
+
+pub struct AutoOptions {
+    pub probe_interval: Duration,
+}
+
+impl Default for AutoOptions {
+    fn default() -> Self {
+        Self {
+            probe_interval: Duration::from_secs(30),
+        }
+    }
+}
+
+impl AutoOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Delay between two attempts to (re)open the WebSocket connection while
+    /// requests go through HTTP
+    pub fn probe_interval(mut self, interval: Duration) -> Self {
+        self.probe_interval = interval;
+        self
+    }
+}
+
+/// Use a WebSocket connection when possible, and transparently fall back to
+/// HTTP when it can't be established, e.g. when a proxy blocks the upgrade.
+/// The WebSocket connection is probed periodically and used again as soon as
+/// it is back.
+///
+/// Real-time notifications are only received while on WebSocket. The
+/// connection hooks are invoked when Kuzzle becomes reachable through either
+/// transport, and when it is not reachable through any anymore.
+pub struct Auto {
+    websocket: WebSocket,
+    http: Http,
+    options: AutoOptions,
+    generation: Arc<AtomicU64>,
+    hooks: AggregatedHooks,
+}
+
+impl Auto {
+    /// Create an auto-negotiating protocol from its two transports
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use kuzzle::protocols::{Auto, Http, WebSocket};
+    ///
+    /// let auto = Auto::new(
+    ///     WebSocket::new("localhost", None),
+    ///     Http::new("localhost", None),
+    ///     None,
+    /// );
+    /// ```
+    pub fn new(mut websocket: WebSocket, mut http: Http, options: Option<AutoOptions>) -> Auto {
+        let hooks = AggregatedHooks::new(2);
+
+        let connects = hooks.clone();
+        websocket.on_connect(Box::new(move || connects.connected(0)));
+        let connects = hooks.clone();
+        http.on_connect(Box::new(move || connects.connected(1)));
+        let disconnects = hooks.clone();
+        websocket.on_disconnect(Box::new(move |reason: &DisconnectReason| {
+            disconnects.disconnected(0, reason)
+        }));
+        let disconnects = hooks.clone();
+        http.on_disconnect(Box::new(move |reason: &DisconnectReason| {
+            disconnects.disconnected(1, reason)
+        }));
+
+        Auto {
+            websocket,
+            http,
+            options: options.unwrap_or_default(),
+            generation: Arc::new(AtomicU64::new(0)),
+            hooks,
+        }
+    }
+
+    /// Whether requests currently go through the WebSocket connection
+    pub fn is_websocket(&self) -> bool {
+        self.websocket.state() == State::Connected
+    }
+
+    /// Stop the probing task of the previous connection, returning the new
+    /// generation
+    fn next_generation(&self) -> u64 {
+        self.generation.fetch_add(1, Ordering::SeqCst) + 1
+    }
+}
+
+fn already_closed() -> Box<dyn Error> {
+    Box::new(std::io::Error::new(
+        std::io::ErrorKind::NotConnected,
+        "Already closed",
+    ))
+}
+
+/// Reopen the WebSocket connection every `interval` whenever it is down,
+/// until a newer generation is started
+async fn probe(websocket: WebSocket, interval: Duration, generation: Arc<AtomicU64>, own: u64) {
+    loop {
+        runtime::sleep(interval).await;
+
+        if generation.load(Ordering::SeqCst) != own {
+            return;
+        }
+
+        if websocket.state() == State::Offline {
+            let _ = websocket.connect().await;
+        }
+    }
+}
+
+#[async_trait]
+impl Protocol for Auto {
+    /// Connect using WebSocket, HTTP being kept ready to take over whenever
+    /// the WebSocket connection is down. Fails if Kuzzle can be reached with
+    /// neither, and only falls back to HTTP if it could be reached then.
+    async fn connect(&self) -> Result<(), Box<dyn Error>> {
+        // Failures are reported through the error hooks of each transport
+        let (websocket, http) = join(self.websocket.connect(), self.http.connect()).await;
+        if let (Err(_), Err(error)) = (websocket, http) {
+            return Err(error);
+        }
+
+        let generation = self.next_generation();
+        runtime::spawn(probe(
+            self.websocket.clone(),
+            self.options.probe_interval,
+            self.generation.clone(),
+            generation,
+        ));
+
+        Ok(())
+    }
+
+    async fn disconnect(&self) -> Result<(), Box<dyn Error>> {
+        self.next_generation();
+
+        let mut disconnected = false;
+        if self.websocket.state() != State::Offline {
+            disconnected |= self.websocket.disconnect().await.is_ok();
+        }
+        if self.http.state() != State::Offline {
+            disconnected |= self.http.disconnect().await.is_ok();
+        }
+
+        match disconnected {
+            true => Ok(()),
+            false => Err(already_closed()),
+        }
+    }
+
+    async fn disconnect_graceful(&self, timeout: Duration) -> Result<(), Box<dyn Error>> {
+        self.next_generation();
+
+        let mut disconnected = false;
+        if self.websocket.state() != State::Offline {
+            disconnected |= self.websocket.disconnect_graceful(timeout).await.is_ok();
+        }
+        if self.http.state() != State::Offline {
+            disconnected |= self.http.disconnect_graceful(timeout).await.is_ok();
+        }
+
+        match disconnected {
+            true => Ok(()),
+            false => Err(already_closed()),
+        }
+    }
+
+    /// Connected as long as either transport is
+    fn state(&self) -> State {
+        match (self.websocket.state(), self.http.state()) {
+            (State::Connected, _) | (_, State::Connected) => State::Connected,
+            (websocket, _) => websocket,
+        }
+    }
+
+    /// Invoked when the first transport connects
+    fn on_connect(&mut self, callback: ConnectCallback) {
+        self.hooks.on_connect(callback);
+    }
+
+    /// Invoked when the last connected transport disconnects
+    fn on_disconnect(&mut self, callback: DisconnectCallback) {
+        self.hooks.on_disconnect(callback);
+    }
+
+    fn on_error(&mut self, callback: ErrorCallback) {
+        let callback = Arc::new(callback);
+        let forwarded = callback.clone();
+
+        self.websocket
+            .on_error(Box::new(move |error: &(dyn Error + 'static)| {
+                forwarded(error)
+            }));
+        self.http
+            .on_error(Box::new(move |error: &(dyn Error + 'static)| {
+                callback(error)
+            }));
+    }
+
+    #[cfg(feature = "wire-trace")]
+    fn on_frame(&mut self, callback: FrameCallback) {
+        let callback = Arc::new(callback);
+        let forwarded = callback.clone();
+
+        self.websocket
+            .on_frame(Box::new(move |frame: &Frame| forwarded(frame)));
+        self.http
+            .on_frame(Box::new(move |frame: &Frame| callback(frame)));
+    }
+
+    fn incoming(&self) -> Incoming {
+        Incoming::merge(vec![self.websocket.incoming(), self.http.incoming()])
+    }
+
+    /// Sum of both transports metrics
+    fn metrics(&self) -> Metrics {
+        let mut metrics = self.websocket.metrics();
+        metrics.merge(&self.http.metrics());
+        metrics
+    }
+
+    async fn send(&self, request: Value) -> Result<String, Box<dyn Error>> {
+        match self.is_websocket() {
+            true => self.websocket.send(request).await,
+            false => self.http.send(request).await,
+        }
+    }
+
+    async fn send_with_timeout(
+        &self,
+        request: Value,
+        timeout: Duration,
+    ) -> Result<String, Box<dyn Error>> {
+        match self.is_websocket() {
+            true => self.websocket.send_with_timeout(request, timeout).await,
+            false => self.http.send_with_timeout(request, timeout).await,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocols::{HttpOptions, WebSocketOptions};
+    use async_std::io::{ReadExt, WriteExt};
+    use async_std::net::TcpListener;
+    use serde_json::json;
+
+    #[async_std::test]
+    async fn should_use_websocket_when_available() -> Result<(), Box<dyn Error>> {
+        let (_, port) = surimi::MockServer::default()
+            .responses(vec![json!({"requestId": "foo", "hello": "world"})])
+            .start()
+            .await?;
+
+        let auto = Auto::new(
+            WebSocket::new("localhost", Some(WebSocketOptions::new().port(port))),
+            Http::new("localhost", Some(HttpOptions::new().port(1))),
+            None,
+        );
+        auto.connect().await?;
+
+        assert!(auto.is_websocket());
+        assert!(auto.send(json!({"requestId": "foo"})).await.is_ok());
+
+        auto.disconnect().await?;
+        assert_eq!(auto.state(), State::Offline);
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn should_fall_back_to_http() -> Result<(), Box<dyn Error>> {
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let port = listener.local_addr()?.port();
+        let server = async_std::task::spawn(async move {
+            let mut requests = Vec::new();
+            for _ in 0..2 {
+                let (mut socket, _) = listener.accept().await.unwrap();
+                let mut buffer = vec![0u8; 4096];
+                let len = socket.read(&mut buffer).await.unwrap();
+                socket
+                    .write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 32\r\n\r\n{\"requestId\":\"foo\",\"status\":200}")
+                    .await
+                    .unwrap();
+                requests.push(String::from_utf8_lossy(&buffer[..len]).to_string());
+            }
+            requests
+        });
+
+        let auto = Auto::new(
+            WebSocket::new("127.0.0.1", Some(WebSocketOptions::new().port(1))),
+            Http::new("127.0.0.1", Some(HttpOptions::new().port(port))),
+            None,
+        );
+        auto.connect().await?;
+
+        assert!(!auto.is_websocket());
+        assert_eq!(auto.state(), State::Connected);
+        assert!(auto.send(json!({"requestId": "foo"})).await.is_ok());
+
+        let requests = server.await;
+        assert!(requests[0].contains("\"action\":\"now\""));
+        assert!(requests[1].ends_with("\r\n\r\n{\"requestId\":\"foo\"}"));
+
+        auto.disconnect().await?;
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn should_be_offline_when_kuzzle_is_unreachable() {
+        let auto = Auto::new(
+            WebSocket::new("127.0.0.1", Some(WebSocketOptions::new().port(1))),
+            Http::new("127.0.0.1", Some(HttpOptions::new().port(1))),
+            None,
+        );
+
+        assert!(auto.connect().await.is_err());
+        assert_eq!(auto.state(), State::Offline);
+    }
+
+    #[async_std::test]
+    async fn should_invoke_hooks_once_for_both_transports() -> Result<(), Box<dyn Error>> {
+        let (_, ws_port) = surimi::MockServer::default().start().await?;
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let http_port = listener.local_addr()?.port();
+        async_std::task::spawn(async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let mut buffer = vec![0u8; 4096];
+            socket.read(&mut buffer).await.unwrap();
+            socket
+                .write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 32\r\n\r\n{\"requestId\":\"foo\",\"status\":200}")
+                .await
+                .unwrap();
+        });
+
+        let mut auto = Auto::new(
+            WebSocket::new("localhost", Some(WebSocketOptions::new().port(ws_port))),
+            Http::new("127.0.0.1", Some(HttpOptions::new().port(http_port))),
+            None,
+        );
+        let connects = Arc::new(AtomicU64::new(0));
+        let disconnects = Arc::new(AtomicU64::new(0));
+        let counter = connects.clone();
+        auto.on_connect(Box::new(move || {
+            counter.fetch_add(1, Ordering::SeqCst);
+        }));
+        let counter = disconnects.clone();
+        auto.on_disconnect(Box::new(move |_: &DisconnectReason| {
+            counter.fetch_add(1, Ordering::SeqCst);
+        }));
+
+        auto.connect().await?;
+        assert!(auto.is_websocket());
+        assert_eq!(auto.http.state(), State::Connected);
+        assert_eq!(connects.load(Ordering::SeqCst), 1);
+
+        auto.disconnect().await?;
+        assert_eq!(disconnects.load(Ordering::SeqCst), 1);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,508 @@
+//! WebSocket protocol backed by the browser `WebSocket` API, used instead of
+//! the native one when the `wasm` feature is enabled. TLS and the underlying
+//! connection are handled by the browser, so there are no TLS, proxy or
+//! heartbeat options here.
+
+use async_trait::async_trait;
+use futures_channel::oneshot;
+use js_sys::{ArrayBuffer, Uint8Array};
+use send_wrapper::SendWrapper;
+use serde_json::Value;
+use std::cell::RefCell;
+use std::error::Error;
+use std::io::Error as IoError;
+use std::io::ErrorKind as IoErrorKind;
+use std::rc::Rc;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use wasm_bindgen::closure::Closure;
+use wasm_bindgen::{JsCast, JsValue};
+use web_sys::{BinaryType, CloseEvent, MessageEvent};
+
+use super::encoding::decode_binary;
+use super::hosts::url_host;
+use super::incoming::Subscribers;
+use super::metrics::MetricsRecorder;
+use super::pending::PendingRequests;
+use super::state::ConnectionState;
+#[cfg(feature = "wire-trace")]
+use super::FrameCallback;
+use super::{ConnectCallback, DisconnectCallback, DisconnectReason, ErrorCallback};
+use super::{Encoding, HostSelection, Hosts, Incoming, Metrics, Protocol, ProtocolError, State};
+use crate::runtime;
+
+/// Close code reported by browsers when the connection dropped without any
+/// close frame (RFC 6455)
+const ABNORMAL_CLOSURE_CODE: u16 = 1006;
+
+type Handler = Closure<dyn FnMut(JsValue)>;
+
+#[derive(Debug, Clone)]
+pub struct WebSocketOptions {
+    pub port: u16,
+    pub ssl: bool,
+    pub request_timeout: Option<Duration>,
+    pub encoding: Encoding,
+    pub host_selection: HostSelection,
+    pub connect_timeout: Option<Duration>,
+    pub path: String,
+    pub max_in_flight: Option<usize>,
+}
+
+impl Default for WebSocketOptions {
+    fn default() -> Self {
+        Self {
+            port: 7512,
+            ssl: false,
+            request_timeout: None,
+            encoding: Encoding::default(),
+            host_selection: HostSelection::default(),
+            connect_timeout: None,
+            path: String::new(),
+            max_in_flight: None,
+        }
+    }
+}
+
+impl WebSocketOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn port(mut self, port: u16) -> Self {
+        self.port = port;
+        self
+    }
+
+    pub fn ssl(mut self, ssl: bool) -> Self {
+        self.ssl = ssl;
+        self
+    }
+
+    /// Fail requests left unanswered after `timeout`
+    pub fn request_timeout(mut self, timeout: Duration) -> Self {
+        self.request_timeout = Some(timeout);
+        self
+    }
+
+    /// Encoding of the frames sent to Kuzzle
+    pub fn encoding(mut self, encoding: Encoding) -> Self {
+        self.encoding = encoding;
+        self
+    }
+
+    /// Order in which hosts are tried on connection
+    pub fn host_selection(mut self, selection: HostSelection) -> Self {
+        self.host_selection = selection;
+        self
+    }
+
+    /// Give up on a host if the connection isn't opened within `timeout`
+    pub fn connect_timeout(mut self, timeout: Duration) -> Self {
+        self.connect_timeout = Some(timeout);
+        self
+    }
+
+    /// Path Kuzzle is served under, e.g. behind a reverse proxy
+    pub fn path(mut self, path: &str) -> Self {
+        self.path = match path.trim_start_matches('/') {
+            "" => String::new(),
+            path => format!("/{}", path),
+        };
+        self
+    }
+
+    /// Refuse new requests while `limit` of them are waiting for a response
+    pub fn max_in_flight(mut self, limit: usize) -> Self {
+        self.max_in_flight = Some(limit);
+        self
+    }
+}
+
+pub struct WebSocket {
+    shared: Arc<Shared>,
+}
+
+struct Shared {
+    hosts: Hosts,
+    options: WebSocketOptions,
+    current: Mutex<Option<usize>>,
+    link: Mutex<Option<Link>>,
+    state: ConnectionState,
+    subscribers: Subscribers,
+    metrics: MetricsRecorder,
+}
+
+/// Browser socket of the current connection and the requests sent over it
+#[derive(Clone)]
+struct Link {
+    socket: Arc<SendWrapper<BrowserSocket>>,
+    pending: PendingRequests,
+}
+
+/// Browser socket along with the event handlers it calls, which must live as
+/// long as the socket does
+struct BrowserSocket {
+    socket: web_sys::WebSocket,
+    handlers: RefCell<Vec<Handler>>,
+}
+
+impl BrowserSocket {
+    fn on(&self, set: fn(&web_sys::WebSocket, Option<&js_sys::Function>), handler: Handler) {
+        set(&self.socket, Some(handler.as_ref().unchecked_ref()));
+        self.handlers.borrow_mut().push(handler);
+    }
+}
+
+impl Drop for BrowserSocket {
+    fn drop(&mut self) {
+        self.socket.set_onopen(None);
+        self.socket.set_onerror(None);
+        self.socket.set_onmessage(None);
+        self.socket.set_onclose(None);
+        let _ = self.socket.close();
+    }
+}
+
+impl WebSocket {
+    /// Create a new WebSocket instance, connecting through the browser
+    ///
+    /// # Example
+    ///
+    /// ```ignore
+    /// use kuzzle::protocols::{WebSocket, WebSocketOptions};
+    ///
+    /// let websocket = WebSocket::new("kuzzle.example.com", Some(WebSocketOptions::new().ssl(true)));
+    /// ```
+    pub fn new<H: Into<Hosts>>(hosts: H, options: Option<WebSocketOptions>) -> WebSocket {
+        WebSocket {
+            shared: Arc::new(Shared {
+                hosts: hosts.into(),
+                options: options.unwrap_or_default(),
+                current: Mutex::new(None),
+                link: Mutex::new(None),
+                state: ConnectionState::new(),
+                subscribers: Subscribers::default(),
+                metrics: MetricsRecorder::default(),
+            }),
+        }
+    }
+
+    /// URL of the host last connected to, or of the first one
+    pub fn get_url(&self) -> String {
+        let current = self.shared.current.lock().unwrap().unwrap_or(0);
+        self.shared
+            .url(self.shared.hosts.get(current).unwrap_or_default())
+    }
+
+    async fn send_request(&self, request: Value) -> Result<String, Box<dyn Error>> {
+        let link = self
+            .shared
+            .link()
+            .ok_or_else(|| IoError::new(IoErrorKind::NotConnected, "Not connected"))?;
+        let pending = link.pending.register(&request)?;
+
+        if self.shared.options.encoding.is_binary() {
+            let payload = self.shared.options.encoding.encode(&request)?;
+            self.shared.metrics.sent(&payload);
+            link.socket
+                .socket
+                .send_with_u8_array(&payload)
+                .map_err(js_error)?;
+        } else {
+            let request = request.to_string();
+            self.shared.metrics.sent(request.as_bytes());
+            link.socket
+                .socket
+                .send_with_str(&request)
+                .map_err(js_error)?;
+        }
+
+        pending.response().await
+    }
+}
+
+impl Shared {
+    fn link(&self) -> Option<Link> {
+        self.link.lock().unwrap().clone()
+    }
+
+    fn url(&self, host: &str) -> String {
+        let scheme = match &self.options.ssl {
+            true => "wss",
+            false => "ws",
+        };
+
+        format!(
+            "{}://{}:{}{}",
+            scheme,
+            url_host(host),
+            self.options.port,
+            self.options.path
+        )
+    }
+
+    /// Connect to the first available host, reporting every failed attempt
+    async fn open_any(&self) -> Result<SendWrapper<BrowserSocket>, Box<dyn Error>> {
+        let current = *self.current.lock().unwrap();
+        let attempts: Vec<usize> = self
+            .hosts
+            .attempts(self.options.host_selection, current)
+            .collect();
+
+        for (attempt, &index) in attempts.iter().enumerate() {
+            let host = self.hosts.get(index).unwrap_or_default();
+
+            match self.open_with_timeout(host).await {
+                Ok(socket) => {
+                    *self.current.lock().unwrap() = Some(index);
+                    return Ok(socket);
+                }
+                Err(error) => {
+                    self.metrics.connection_failed();
+                    self.state.error(&*error);
+                    if attempt + 1 == attempts.len() {
+                        return Err(error);
+                    }
+                }
+            }
+        }
+
+        Err(Box::new(ProtocolError::NoHost))
+    }
+
+    async fn open_with_timeout(
+        &self,
+        host: &str,
+    ) -> Result<SendWrapper<BrowserSocket>, Box<dyn Error>> {
+        match self.options.connect_timeout {
+            Some(timeout) => match runtime::timeout(timeout, self.open(host)).await {
+                Some(result) => result,
+                None => Err(Box::new(ProtocolError::ConnectTimeout(timeout))),
+            },
+            None => self.open(host).await,
+        }
+    }
+
+    /// Open a socket to `host` and wait for the browser to establish it
+    async fn open(&self, host: &str) -> Result<SendWrapper<BrowserSocket>, Box<dyn Error>> {
+        let url = self.url(host);
+        let (socket, established) = create_socket(&url)?;
+
+        match established.await {
+            Ok(true) => Ok(socket),
+            _ => Err(Box::new(IoError::new(
+                IoErrorKind::ConnectionRefused,
+                format!("Unable to connect to {}", url),
+            ))),
+        }
+    }
+
+    /// Route the messages of a freshly opened socket and make it the current
+    /// connection
+    fn install(&self, socket: SendWrapper<BrowserSocket>) {
+        let pending = PendingRequests::new(self.options.max_in_flight);
+        let generation = self.state.connected();
+        self.metrics.connected();
+
+        socket.socket.set_onopen(None);
+        socket.socket.set_onerror(None);
+
+        let (routing, subscribers, metrics) = (
+            pending.clone(),
+            self.subscribers.clone(),
+            self.metrics.clone(),
+        );
+        socket.on(
+            web_sys::WebSocket::set_onmessage,
+            Closure::wrap(Box::new(move |event: JsValue| {
+                let data = event.unchecked_into::<MessageEvent>().data();
+                let payload = if let Some(text) = data.as_string() {
+                    metrics.received(text.as_bytes());
+                    text
+                } else if let Ok(buffer) = data.dyn_into::<ArrayBuffer>() {
+                    let bytes = Uint8Array::new(&buffer).to_vec();
+                    metrics.received(&bytes);
+                    match decode_binary(bytes) {
+                        Ok(text) => text,
+                        Err(_) => return,
+                    }
+                } else {
+                    return;
+                };
+
+                if let Some(payload) = routing.dispatch(payload) {
+                    subscribers.publish(payload);
+                }
+            }) as Box<dyn FnMut(JsValue)>),
+        );
+
+        let (closing, state) = (pending.clone(), self.state.clone());
+        socket.on(
+            web_sys::WebSocket::set_onclose,
+            Closure::wrap(Box::new(move |event: JsValue| {
+                let event = event.unchecked_into::<CloseEvent>();
+                let reason = match event.code() {
+                    ABNORMAL_CLOSURE_CODE => DisconnectReason::ConnectionLost,
+                    code => DisconnectReason::ClosedByServer {
+                        code,
+                        reason: event.reason(),
+                    },
+                };
+
+                closing.close();
+                state.lost(generation, reason);
+            }) as Box<dyn FnMut(JsValue)>),
+        );
+
+        *self.link.lock().unwrap() = Some(Link {
+            socket: Arc::new(socket),
+            pending,
+        });
+    }
+}
+
+/// Create a browser socket to `url`, along with a receiver telling whether it
+/// could be opened
+fn create_socket(
+    url: &str,
+) -> Result<(SendWrapper<BrowserSocket>, oneshot::Receiver<bool>), Box<dyn Error>> {
+    let socket = web_sys::WebSocket::new(url).map_err(js_error)?;
+    socket.set_binary_type(BinaryType::Arraybuffer);
+
+    let socket = BrowserSocket {
+        socket,
+        handlers: RefCell::new(Vec::new()),
+    };
+    let (opened, established) = oneshot::channel();
+    let opened = Rc::new(RefCell::new(Some(opened)));
+    let settle = |result: bool| {
+        let opened = opened.clone();
+        Closure::wrap(Box::new(move |_: JsValue| {
+            if let Some(opened) = opened.borrow_mut().take() {
+                let _ = opened.send(result);
+            }
+        }) as Box<dyn FnMut(JsValue)>)
+    };
+
+    socket.on(web_sys::WebSocket::set_onopen, settle(true));
+    socket.on(web_sys::WebSocket::set_onerror, settle(false));
+
+    Ok((SendWrapper::new(socket), established))
+}
+
+fn js_error(error: JsValue) -> Box<dyn Error> {
+    Box::new(IoError::new(
+        IoErrorKind::Other,
+        error.as_string().unwrap_or_else(|| format!("{:?}", error)),
+    ))
+}
+
+#[async_trait]
+impl Protocol for WebSocket {
+    async fn connect(&self) -> Result<(), Box<dyn Error>> {
+        self.shared.state.set(State::Connecting);
+
+        match self.shared.open_any().await {
+            Ok(socket) => {
+                self.shared.install(socket);
+                Ok(())
+            }
+            Err(error) => {
+                self.shared.state.set(State::Offline);
+                Err(error)
+            }
+        }
+    }
+
+    async fn disconnect(&self) -> Result<(), Box<dyn Error>> {
+        let link = self.shared.link.lock().unwrap().take();
+
+        match link {
+            Some(link) => {
+                link.pending.close();
+                self.shared.state.disconnected();
+                link.socket.socket.close().map_err(js_error)
+            }
+            None => Err(Box::new(IoError::new(
+                IoErrorKind::NotConnected,
+                "Already closed",
+            ))),
+        }
+    }
+
+    async fn disconnect_graceful(&self, timeout: Duration) -> Result<(), Box<dyn Error>> {
+        if let Some(link) = self.shared.link() {
+            link.pending.drain();
+            runtime::timeout(timeout, link.pending.settled()).await;
+        }
+        self.disconnect().await
+    }
+
+    fn state(&self) -> State {
+        self.shared.state.get()
+    }
+
+    fn on_connect(&mut self, callback: ConnectCallback) {
+        self.shared.state.on_connect(callback);
+    }
+
+    fn on_disconnect(&mut self, callback: DisconnectCallback) {
+        self.shared.state.on_disconnect(callback);
+    }
+
+    fn on_error(&mut self, callback: ErrorCallback) {
+        self.shared.state.on_error(callback);
+    }
+
+    #[cfg(feature = "wire-trace")]
+    fn on_frame(&mut self, callback: FrameCallback) {
+        self.shared.metrics.on_frame(callback);
+    }
+
+    fn incoming(&self) -> Incoming {
+        self.shared.subscribers.subscribe()
+    }
+
+    fn metrics(&self) -> Metrics {
+        self.shared.metrics.snapshot()
+    }
+
+    async fn send(&self, request: Value) -> Result<String, Box<dyn Error>> {
+        self.shared
+            .metrics
+            .track(
+                self.send_request(request),
+                self.shared.options.request_timeout,
+            )
+            .await
+    }
+
+    async fn send_with_timeout(
+        &self,
+        request: Value,
+        timeout: Duration,
+    ) -> Result<String, Box<dyn Error>> {
+        self.shared
+            .metrics
+            .track(self.send_request(request), Some(timeout))
+            .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_forge_ws_url() {
+        let ws = WebSocket::new("localhost", Some(WebSocketOptions::new().path("kuzzle")));
+        assert_eq!("ws://localhost:7512/kuzzle", ws.get_url());
+    }
+
+    #[test]
+    fn should_forge_wss_url_with_ipv6_host() {
+        let ws = WebSocket::new("::1", Some(WebSocketOptions::new().ssl(true)));
+        assert_eq!("wss://[::1]:7512", ws.get_url());
+    }
+}
@@ -0,0 +1,380 @@
+use async_trait::async_trait;
+use rand::Rng;
+use serde_json::Value;
+use std::error::Error;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+#[cfg(feature = "wire-trace")]
+use super::FrameCallback;
+use super::{ConnectCallback, DisconnectCallback, DisconnectReason, ErrorCallback};
+use super::{Incoming, Metrics, Protocol, ProtocolError, State};
+use crate::runtime;
+
+#[derive(Debug, Clone, Default)]
+pub struct ChaosOptions {
+    pub latency: Option<(Duration, Duration)>,
+    pub drop_rate: f64,
+    pub corrupt_rate: f64,
+    pub disconnect_every: Option<Duration>,
+    pub downtime: Duration,
+}
+
+impl ChaosOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Delay every request by a random duration between `min` and `max`
+    pub fn latency(mut self, min: Duration, max: Duration) -> Self {
+        self.latency = Some((min, max.max(min)));
+        self
+    }
+
+    /// Share of the responses never delivered, between 0 and 1. The
+    /// requests concerned only end when they time out.
+    pub fn drop_rate(mut self, rate: f64) -> Self {
+        self.drop_rate = rate.clamp(0.0, 1.0);
+        self
+    }
+
+    /// Share of the responses truncated, between 0 and 1
+    pub fn corrupt_rate(mut self, rate: f64) -> Self {
+        self.corrupt_rate = rate.clamp(0.0, 1.0);
+        self
+    }
+
+    /// Disconnect every `interval`, and reconnect after `downtime`. The
+    /// protocol reports itself as `Reconnecting` in the meantime.
+    pub fn disconnect_every(mut self, interval: Duration, downtime: Duration) -> Self {
+        self.disconnect_every = Some(interval);
+        self.downtime = downtime;
+        self
+    }
+}
+
+/// Decorate a protocol to inject faults: latency, lost or corrupted responses
+/// and disconnections. Meant to test how an application copes with an
+/// unreliable network, e.g. its reconnection and offline queue handling.
+///
+/// Forced disconnections go through a clone of the protocol, which must share
+/// its connection with the original, as `WebSocket` and `InMemory` do. They
+/// are reported to the disconnection hooks as `DisconnectReason::ConnectionLost`.
+///
+/// # Example
+///
+/// ```
+/// use kuzzle::protocols::{ChaosOptions, ChaosProxy, WebSocket};
+/// use std::time::Duration;
+///
+/// let options = ChaosOptions::new()
+///     .latency(Duration::from_millis(50), Duration::from_millis(500))
+///     .drop_rate(0.01)
+///     .disconnect_every(Duration::from_secs(60), Duration::from_secs(5));
+///
+/// let protocol = ChaosProxy::new(WebSocket::new("localhost", None), options);
+/// ```
+pub struct ChaosProxy<P> {
+    inner: P,
+    options: ChaosOptions,
+    down: Arc<AtomicBool>,
+    generation: Arc<AtomicU64>,
+}
+
+impl<P: Protocol> ChaosProxy<P> {
+    pub fn new(inner: P, options: ChaosOptions) -> ChaosProxy<P> {
+        ChaosProxy {
+            inner,
+            options,
+            down: Arc::new(AtomicBool::new(false)),
+            generation: Arc::new(AtomicU64::new(0)),
+        }
+    }
+
+    /// Decorated protocol
+    pub fn inner(&self) -> &P {
+        &self.inner
+    }
+
+    fn latency(&self) -> Option<Duration> {
+        self.options.latency.map(|(min, max)| match max > min {
+            true => rand::thread_rng().gen_range(min..=max),
+            false => min,
+        })
+    }
+
+    async fn send_request(&self, request: Value) -> Result<String, Box<dyn Error>> {
+        if let Some(latency) = self.latency() {
+            runtime::sleep(latency).await;
+        }
+
+        let response = self.inner.send(request).await?;
+
+        if rand::random::<f64>() < self.options.drop_rate {
+            futures_util::future::pending::<()>().await;
+        }
+
+        match rand::random::<f64>() < self.options.corrupt_rate {
+            true => Ok(corrupt(response)),
+            false => Ok(response),
+        }
+    }
+
+    fn next_generation(&self) -> u64 {
+        self.down.store(false, Ordering::SeqCst);
+        self.generation.fetch_add(1, Ordering::SeqCst) + 1
+    }
+}
+
+/// Truncate a payload at a random position, so that it can't be parsed
+fn corrupt(mut response: String) -> String {
+    let length = rand::thread_rng().gen_range(0..response.len().max(1));
+    let boundary = (0..=length)
+        .rev()
+        .find(|index| response.is_char_boundary(*index))
+        .unwrap_or(0);
+
+    response.truncate(boundary);
+    response
+}
+
+/// Disconnect the protocol every `interval` and reconnect it after
+/// `downtime`, until a newer generation is started
+async fn disrupt<P>(
+    protocol: P,
+    options: ChaosOptions,
+    interval: Duration,
+    down: Arc<AtomicBool>,
+    generation: Arc<AtomicU64>,
+    own: u64,
+) where
+    P: Protocol,
+{
+    loop {
+        runtime::sleep(interval).await;
+
+        if generation.load(Ordering::SeqCst) != own {
+            return;
+        }
+        if protocol.state() != State::Connected {
+            continue;
+        }
+
+        down.store(true, Ordering::SeqCst);
+        let _ = protocol.disconnect().await;
+        runtime::sleep(options.downtime).await;
+
+        if generation.load(Ordering::SeqCst) != own {
+            return;
+        }
+        let _ = protocol.connect().await;
+        down.store(false, Ordering::SeqCst);
+    }
+}
+
+#[async_trait]
+impl<P> Protocol for ChaosProxy<P>
+where
+    P: Protocol + Clone + 'static,
+{
+    async fn connect(&self) -> Result<(), Box<dyn Error>> {
+        self.inner.connect().await?;
+
+        let generation = self.next_generation();
+        if let Some(interval) = self.options.disconnect_every {
+            runtime::spawn(disrupt(
+                self.inner.clone(),
+                self.options.clone(),
+                interval,
+                self.down.clone(),
+                self.generation.clone(),
+                generation,
+            ));
+        }
+
+        Ok(())
+    }
+
+    async fn disconnect(&self) -> Result<(), Box<dyn Error>> {
+        self.next_generation();
+        self.inner.disconnect().await
+    }
+
+    async fn disconnect_graceful(&self, timeout: Duration) -> Result<(), Box<dyn Error>> {
+        self.next_generation();
+        self.inner.disconnect_graceful(timeout).await
+    }
+
+    /// `Reconnecting` while a forced disconnection lasts
+    fn state(&self) -> State {
+        match self.down.load(Ordering::SeqCst) {
+            true => State::Reconnecting,
+            false => self.inner.state(),
+        }
+    }
+
+    /// The forced disconnection is over once reconnected
+    fn on_connect(&mut self, callback: ConnectCallback) {
+        let down = self.down.clone();
+
+        self.inner.on_connect(Box::new(move || {
+            down.store(false, Ordering::SeqCst);
+            callback()
+        }));
+    }
+
+    /// Forced disconnections are reported as lost connections
+    fn on_disconnect(&mut self, callback: DisconnectCallback) {
+        let down = self.down.clone();
+
+        self.inner
+            .on_disconnect(Box::new(move |reason: &DisconnectReason| {
+                match down.load(Ordering::SeqCst) {
+                    true => callback(&DisconnectReason::ConnectionLost),
+                    false => callback(reason),
+                }
+            }));
+    }
+
+    fn on_error(&mut self, callback: ErrorCallback) {
+        self.inner.on_error(callback);
+    }
+
+    #[cfg(feature = "wire-trace")]
+    fn on_frame(&mut self, callback: FrameCallback) {
+        self.inner.on_frame(callback);
+    }
+
+    fn incoming(&self) -> Incoming {
+        self.inner.incoming()
+    }
+
+    fn metrics(&self) -> Metrics {
+        self.inner.metrics()
+    }
+
+    async fn send(&self, request: Value) -> Result<String, Box<dyn Error>> {
+        self.send_request(request).await
+    }
+
+    async fn send_with_timeout(
+        &self,
+        request: Value,
+        timeout: Duration,
+    ) -> Result<String, Box<dyn Error>> {
+        match runtime::timeout(timeout, self.send_request(request)).await {
+            Some(result) => result,
+            None => Err(Box::new(ProtocolError::Timeout(timeout))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::events::Event;
+    use crate::protocols::InMemory;
+    use crate::Kuzzle;
+    use serde_json::json;
+    use std::sync::Mutex;
+    use std::time::Instant;
+
+    #[async_std::test]
+    async fn should_delay_requests() -> Result<(), Box<dyn Error>> {
+        let memory = InMemory::new();
+        memory.respond(json!({}));
+
+        let latency = Duration::from_millis(50);
+        let chaos = ChaosProxy::new(memory, ChaosOptions::new().latency(latency, latency));
+        chaos.connect().await?;
+
+        let started_at = Instant::now();
+        chaos.send(json!({"requestId": "foo"})).await?;
+
+        assert!(started_at.elapsed() >= latency);
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn should_drop_responses() -> Result<(), Box<dyn Error>> {
+        let memory = InMemory::new();
+        memory.respond(json!({}));
+
+        let chaos = ChaosProxy::new(memory, ChaosOptions::new().drop_rate(1.0));
+        chaos.connect().await?;
+
+        let result = chaos
+            .send_with_timeout(json!({"requestId": "foo"}), Duration::from_millis(50))
+            .await;
+
+        assert!(result.is_err());
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn should_corrupt_responses() -> Result<(), Box<dyn Error>> {
+        let memory = InMemory::new();
+        memory.respond(json!({"result": "foo"}));
+
+        let chaos = ChaosProxy::new(memory, ChaosOptions::new().corrupt_rate(1.0));
+        chaos.connect().await?;
+
+        let response = chaos.send(json!({"requestId": "foo"})).await?;
+
+        assert!(serde_json::from_str::<serde_json::Value>(&response).is_err());
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn should_force_disconnections() -> Result<(), Box<dyn Error>> {
+        let memory = InMemory::new();
+        let chaos = ChaosProxy::new(
+            memory.clone(),
+            ChaosOptions::new()
+                .disconnect_every(Duration::from_millis(20), Duration::from_millis(100)),
+        );
+        chaos.connect().await?;
+
+        async_std::task::sleep(Duration::from_millis(60)).await;
+        assert_eq!(chaos.state(), State::Reconnecting);
+        assert_eq!(memory.state(), State::Offline);
+
+        async_std::task::sleep(Duration::from_millis(100)).await;
+        assert_eq!(memory.metrics().connections, 2);
+
+        chaos.disconnect().await?;
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn should_restore_the_session_after_forced_disconnections() -> Result<(), Box<dyn Error>>
+    {
+        let chaos = ChaosProxy::new(
+            InMemory::new(),
+            ChaosOptions::new()
+                .disconnect_every(Duration::from_millis(20), Duration::from_millis(20)),
+        );
+        let kuzzle = Kuzzle::new(chaos);
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let recorded = events.clone();
+        kuzzle.on_event(Box::new(move |event: &Event| {
+            recorded.lock().unwrap().push(format!("{:?}", event));
+        }));
+        kuzzle.connect().await?;
+
+        for _ in 0..100 {
+            if events.lock().unwrap().contains(&"Reconnected".to_string()) {
+                break;
+            }
+            async_std::task::sleep(Duration::from_millis(10)).await;
+        }
+        // Stop disrupting, the connection being possibly down already
+        let _ = kuzzle.disconnect().await;
+
+        let events = events.lock().unwrap();
+        assert!(events.contains(&"Disconnected(ConnectionLost)".to_string()));
+        assert!(events.contains(&"Reconnected".to_string()));
+        Ok(())
+    }
+}
@@ -0,0 +1,113 @@
+use std::sync::{Arc, Mutex};
+
+/// Cookies set by Kuzzle, or by the load balancer in front of it, sent back
+/// on every subsequent connection or request. This keeps sticky sessions
+/// routing a client to the same node, and enables cookie-based
+/// authentication.
+///
+/// Clones share the same cookies, so that a single jar can be given to
+/// several protocols.
+///
+/// # Example
+///
+/// ```
+/// use kuzzle::protocols::CookieJar;
+///
+/// let jar = CookieJar::new();
+/// jar.store("SERVERID=node2; Path=/; HttpOnly");
+/// jar.store("jwt=eyJhbGciOi; Max-Age=3600");
+///
+/// assert_eq!(jar.header(), Some("SERVERID=node2; jwt=eyJhbGciOi".to_string()));
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct CookieJar(Arc<Mutex<Vec<(String, String)>>>);
+
+impl CookieJar {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Store the cookie described by a `Set-Cookie` header value. A cookie
+    /// with an expired `Max-Age` or an empty value is removed instead.
+    pub fn store(&self, set_cookie: &str) {
+        let mut attributes = set_cookie.split(';').map(str::trim);
+        let (name, value) = match attributes.next().and_then(|c| c.split_once('=')) {
+            Some((name, value)) if !name.trim().is_empty() => (name.trim(), value.trim()),
+            _ => return,
+        };
+        let expired = value.is_empty()
+            || attributes.any(|attribute| match attribute.split_once('=') {
+                Some((key, age)) if key.trim().eq_ignore_ascii_case("max-age") => {
+                    matches!(age.trim().parse::<i64>(), Ok(age) if age <= 0)
+                }
+                _ => false,
+            });
+
+        let mut cookies = self.0.lock().unwrap();
+        cookies.retain(|(stored, _)| stored != name);
+
+        if !expired {
+            cookies.push((name.to_string(), value.to_string()));
+        }
+    }
+
+    /// Every stored cookie, as `(name, value)` pairs
+    pub fn cookies(&self) -> Vec<(String, String)> {
+        self.0.lock().unwrap().clone()
+    }
+
+    pub fn clear(&self) {
+        self.0.lock().unwrap().clear();
+    }
+
+    /// Value of the `Cookie` header to send, if any cookie is stored
+    pub fn header(&self) -> Option<String> {
+        let cookies = self.0.lock().unwrap();
+
+        match cookies.is_empty() {
+            true => None,
+            false => Some(
+                cookies
+                    .iter()
+                    .map(|(name, value)| format!("{}={}", name, value))
+                    .collect::<Vec<_>>()
+                    .join("; "),
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_replace_cookie_with_same_name() {
+        let jar = CookieJar::new();
+        jar.store("SERVERID=node1");
+        jar.store("SERVERID=node2; Path=/");
+
+        assert_eq!(
+            jar.cookies(),
+            vec![("SERVERID".to_string(), "node2".to_string())]
+        );
+    }
+
+    #[test]
+    fn should_remove_expired_cookie() {
+        let jar = CookieJar::new();
+        jar.store("jwt=foo");
+        jar.store("jwt=deleted; Max-Age=0");
+
+        assert_eq!(jar.header(), None);
+    }
+
+    #[test]
+    fn should_ignore_malformed_cookie() {
+        let jar = CookieJar::new();
+        jar.store("; Path=/");
+        jar.store("=value");
+
+        assert!(jar.cookies().is_empty());
+    }
+}
@@ -0,0 +1,78 @@
+use serde_json::Value;
+use std::error::Error;
+
+/// Wire encoding of the payloads exchanged with Kuzzle
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Encoding {
+    /// JSON text, understood by every Kuzzle entry point
+    #[default]
+    Json,
+    /// MessagePack binary payloads, noticeably smaller for document-heavy workloads
+    #[cfg(feature = "msgpack")]
+    MessagePack,
+}
+
+impl Encoding {
+    /// Whether payloads are sent as binary data rather than text
+    pub fn is_binary(&self) -> bool {
+        !matches!(self, Encoding::Json)
+    }
+
+    /// Encode a request
+    pub(crate) fn encode(&self, request: &Value) -> Result<Vec<u8>, Box<dyn Error>> {
+        match self {
+            Encoding::Json => Ok(serde_json::to_vec(request)?),
+            #[cfg(feature = "msgpack")]
+            Encoding::MessagePack => Ok(rmp_serde::to_vec_named(request)?),
+        }
+    }
+}
+
+/// Decode a binary payload back to JSON, whatever encoding it uses
+pub(crate) fn decode_binary(payload: Vec<u8>) -> Result<String, Box<dyn Error>> {
+    #[cfg(feature = "msgpack")]
+    {
+        if let Ok(value) = rmp_serde::from_slice::<Value>(&payload) {
+            return Ok(value.to_string());
+        }
+    }
+
+    let json: Value = serde_json::from_slice(&payload)?;
+    Ok(json.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn should_keep_json_as_is() -> Result<(), Box<dyn Error>> {
+        let request = json!({"requestId": "foo"});
+
+        assert!(!Encoding::default().is_binary());
+        assert_eq!(
+            Encoding::Json.encode(&request)?,
+            request.to_string().as_bytes()
+        );
+        assert_eq!(
+            decode_binary(request.to_string().into_bytes())?,
+            request.to_string()
+        );
+
+        Ok(())
+    }
+
+    #[cfg(feature = "msgpack")]
+    #[test]
+    fn should_round_trip_message_pack() -> Result<(), Box<dyn Error>> {
+        let request = json!({"requestId": "foo", "body": {"answer": 42}});
+        let encoded = Encoding::MessagePack.encode(&request)?;
+
+        assert!(Encoding::MessagePack.is_binary());
+        assert!(encoded.len() < request.to_string().len());
+        assert_eq!(decode_binary(encoded)?, request.to_string());
+
+        Ok(())
+    }
+}
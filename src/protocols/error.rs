@@ -0,0 +1,81 @@
+use std::error::Error;
+use std::fmt;
+use std::time::Duration;
+
+/// Errors raised by the protocols themselves, as opposed to the ones coming
+/// from the underlying transport libraries
+#[derive(Debug, Clone, PartialEq)]
+pub enum ProtocolError {
+    /// No response was received within the given duration
+    Timeout(Duration),
+    /// No host was given to connect to
+    NoHost,
+    /// The connection could not be established within the given duration
+    ConnectTimeout(Duration),
+    /// The maximum number of requests awaiting a response was reached
+    TooManyRequests(usize),
+    /// The connection could not be re-established within the retry budget
+    ReconnectFailed(u32),
+    /// A payload with another `requestId` was received instead of the
+    /// response to a request
+    UnexpectedResponse(String),
+    /// The request was cancelled before its response was received
+    Cancelled,
+    /// The query, retries and reconnections included, did not complete
+    /// within the given duration
+    DeadlineExceeded(Duration),
+    /// A request with the same `requestId` is already awaiting its response
+    DuplicateRequestId(String),
+}
+
+impl fmt::Display for ProtocolError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ProtocolError::Timeout(duration) => {
+                write!(f, "No response received after {:?}", duration)
+            }
+            ProtocolError::NoHost => write!(f, "No host to connect to"),
+            ProtocolError::ConnectTimeout(duration) => {
+                write!(f, "Connection not established after {:?}", duration)
+            }
+            ProtocolError::TooManyRequests(limit) => {
+                write!(f, "Too many requests in flight (limit: {})", limit)
+            }
+            ProtocolError::ReconnectFailed(attempts) => {
+                write!(f, "Gave up reconnecting after {} attempts", attempts)
+            }
+            ProtocolError::UnexpectedResponse(request_id) => {
+                write!(
+                    f,
+                    "Unexpected response received (requestId: {})",
+                    request_id
+                )
+            }
+            ProtocolError::Cancelled => write!(f, "Request cancelled"),
+            ProtocolError::DeadlineExceeded(duration) => {
+                write!(f, "Query not completed after {:?}", duration)
+            }
+            ProtocolError::DuplicateRequestId(request_id) => {
+                write!(f, "Request already in flight (requestId: {})", request_id)
+            }
+        }
+    }
+}
+
+impl Error for ProtocolError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_display_timeout() {
+        let error = ProtocolError::Timeout(Duration::from_millis(1500));
+        assert_eq!(error.to_string(), "No response received after 1.5s");
+    }
+
+    #[test]
+    fn should_display_no_host() {
+        assert_eq!(ProtocolError::NoHost.to_string(), "No host to connect to");
+    }
+}
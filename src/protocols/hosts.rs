@@ -0,0 +1,167 @@
+use std::net::{IpAddr, Ipv6Addr};
+
+/// Kuzzle nodes a protocol can connect to. When several nodes are given, they
+/// are tried in turn until one of them accepts the connection.
+///
+/// # Example
+///
+/// ```
+/// use kuzzle::protocols::Hosts;
+///
+/// let single = Hosts::from("localhost");
+/// let cluster = Hosts::from(&["node1", "node2", "node3"]);
+/// assert_eq!(cluster.len(), 3);
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct Hosts(Vec<String>);
+
+/// Order in which the hosts are tried on connection
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum HostSelection {
+    /// Always start from the first host, the next ones being fallbacks
+    #[default]
+    InOrder,
+    /// Start from the host following the last one connected to, spreading
+    /// the connections across the nodes
+    RoundRobin,
+}
+
+impl Hosts {
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub fn get(&self, index: usize) -> Option<&str> {
+        self.0.get(index).map(String::as_str)
+    }
+
+    /// Indexes of the hosts to try, given the selection policy and the index
+    /// of the host last connected to
+    pub(crate) fn attempts(
+        &self,
+        selection: HostSelection,
+        last: Option<usize>,
+    ) -> impl Iterator<Item = usize> {
+        let len = self.len();
+        let start = match (selection, last) {
+            (HostSelection::RoundRobin, Some(last)) => last + 1,
+            _ => 0,
+        };
+
+        (start..start + len).map(move |index| index % len)
+    }
+}
+
+/// Host as it must be written in a URL or an authority: IPv6 literals are
+/// enclosed in brackets
+pub(crate) fn url_host(host: &str) -> String {
+    match host.parse::<Ipv6Addr>() {
+        Ok(ip) => format!("[{}]", ip),
+        Err(_) => host.into(),
+    }
+}
+
+/// Host as expected when opening a socket: IPv6 literals without brackets
+#[cfg_attr(feature = "wasm", allow(dead_code))]
+pub(crate) fn socket_host(host: &str) -> &str {
+    host.strip_prefix('[')
+        .and_then(|host| host.strip_suffix(']'))
+        .unwrap_or(host)
+}
+
+/// IP address of a literal host, brackets allowed
+#[cfg_attr(feature = "wasm", allow(dead_code))]
+pub(crate) fn ip_literal(host: &str) -> Option<IpAddr> {
+    socket_host(host).parse().ok()
+}
+
+impl From<&str> for Hosts {
+    fn from(host: &str) -> Self {
+        Hosts(vec![host.into()])
+    }
+}
+
+impl From<String> for Hosts {
+    fn from(host: String) -> Self {
+        Hosts(vec![host])
+    }
+}
+
+impl From<&[&str]> for Hosts {
+    fn from(hosts: &[&str]) -> Self {
+        Hosts(hosts.iter().map(|host| host.to_string()).collect())
+    }
+}
+
+impl<const N: usize> From<&[&str; N]> for Hosts {
+    fn from(hosts: &[&str; N]) -> Self {
+        Hosts::from(&hosts[..])
+    }
+}
+
+impl From<Vec<&str>> for Hosts {
+    fn from(hosts: Vec<&str>) -> Self {
+        Hosts::from(&hosts[..])
+    }
+}
+
+impl From<Vec<String>> for Hosts {
+    fn from(hosts: Vec<String>) -> Self {
+        Hosts(hosts)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_build_from_single_or_multiple_hosts() {
+        assert_eq!(Hosts::from("a"), Hosts::from(vec!["a".to_string()]));
+        assert_eq!(Hosts::from(&["a", "b"]), Hosts::from(vec!["a", "b"]));
+        assert_eq!(Hosts::from(&["a", "b"]).get(1), Some("b"));
+    }
+
+    #[test]
+    fn should_try_hosts_in_order() {
+        let hosts = Hosts::from(&["a", "b", "c"]);
+        let attempts: Vec<usize> = hosts.attempts(HostSelection::InOrder, Some(1)).collect();
+
+        assert_eq!(attempts, vec![0, 1, 2]);
+    }
+
+    #[test]
+    fn should_try_hosts_round_robin() {
+        let hosts = Hosts::from(&["a", "b", "c"]);
+
+        let first: Vec<usize> = hosts.attempts(HostSelection::RoundRobin, None).collect();
+        let next: Vec<usize> = hosts.attempts(HostSelection::RoundRobin, Some(1)).collect();
+
+        assert_eq!(first, vec![0, 1, 2]);
+        assert_eq!(next, vec![2, 0, 1]);
+    }
+
+    #[test]
+    fn should_bracket_ipv6_literals() {
+        assert_eq!(url_host("::1"), "[::1]");
+        assert_eq!(url_host("fe80:0:0::1"), "[fe80::1]");
+        assert_eq!(url_host("[::1]"), "[::1]");
+        assert_eq!(url_host("127.0.0.1"), "127.0.0.1");
+        assert_eq!(url_host("localhost"), "localhost");
+
+        assert_eq!(socket_host("[::1]"), "::1");
+        assert_eq!(socket_host("::1"), "::1");
+        assert_eq!(ip_literal("[::1]"), Some("::1".parse().unwrap()));
+        assert_eq!(ip_literal("localhost"), None);
+    }
+
+    #[test]
+    fn should_not_try_anything_without_hosts() {
+        let hosts = Hosts::from(Vec::<String>::new());
+        assert_eq!(hosts.attempts(HostSelection::InOrder, None).count(), 0);
+    }
+}
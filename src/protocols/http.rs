@@ -0,0 +1,615 @@
+use async_trait::async_trait;
+use serde_json::{json, Value};
+use std::error::Error;
+use std::io::Error as IoError;
+use std::io::ErrorKind as IoErrorKind;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use super::hosts::{socket_host, url_host};
+use super::incoming::Subscribers;
+use super::metrics::MetricsRecorder;
+use super::state::ConnectionState;
+#[cfg(feature = "wire-trace")]
+use super::FrameCallback;
+use super::{ConnectCallback, DisconnectCallback, ErrorCallback};
+use super::{CookieJar, HostSelection, Hosts, Incoming, Metrics, Protocol, ProtocolError};
+use super::{ProxyOptions, State, TlsOptions};
+use crate::runtime::{self, AsyncReadExt, AsyncWriteExt, TcpStream};
+use uuid::Uuid;
+
+/// Route accepting any API request in its raw JSON form
+const QUERY_ROUTE: &str = "/_query";
+
+#[derive(Clone)]
+pub struct HttpOptions {
+    pub port: u16,
+    pub ssl: bool,
+    pub tls: Option<TlsOptions>,
+    pub proxy: Option<ProxyOptions>,
+    pub request_timeout: Option<Duration>,
+    pub headers: Vec<(String, String)>,
+    pub host_selection: HostSelection,
+    pub path: String,
+    pub cookies: Option<CookieJar>,
+}
+
+impl Default for HttpOptions {
+    fn default() -> Self {
+        Self {
+            port: 7512,
+            ssl: false,
+            tls: None,
+            proxy: None,
+            request_timeout: None,
+            headers: Vec::new(),
+            host_selection: HostSelection::default(),
+            path: String::new(),
+            cookies: None,
+        }
+    }
+}
+
+impl HttpOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn port(mut self, port: u16) -> Self {
+        self.port = port;
+        self
+    }
+
+    pub fn ssl(mut self, ssl: bool) -> Self {
+        self.ssl = ssl;
+        self
+    }
+
+    /// Custom TLS settings, implies `ssl(true)`
+    pub fn tls(mut self, tls: TlsOptions) -> Self {
+        self.ssl = true;
+        self.tls = Some(tls);
+        self
+    }
+
+    /// Reach Kuzzle through an HTTP or SOCKS5 proxy
+    pub fn proxy(mut self, proxy: ProxyOptions) -> Self {
+        self.proxy = Some(proxy);
+        self
+    }
+
+    /// Fail requests left unanswered after `timeout`
+    pub fn request_timeout(mut self, timeout: Duration) -> Self {
+        self.request_timeout = Some(timeout);
+        self
+    }
+
+    /// Add a header to every request
+    pub fn header(mut self, name: &str, value: &str) -> Self {
+        self.headers.push((name.into(), value.into()));
+        self
+    }
+
+    /// Order in which hosts are tried
+    pub fn host_selection(mut self, selection: HostSelection) -> Self {
+        self.host_selection = selection;
+        self
+    }
+
+    /// Path Kuzzle is served under, e.g. behind a reverse proxy
+    pub fn path(mut self, path: &str) -> Self {
+        self.path = match path.trim_matches('/') {
+            "" => String::new(),
+            path => format!("/{}", path),
+        };
+        self
+    }
+
+    /// Keep the cookies set by the responses in `jar` and send them back with
+    /// every request, e.g. for sticky sessions or cookie authentication
+    pub fn cookies(mut self, jar: CookieJar) -> Self {
+        self.cookies = Some(jar);
+        self
+    }
+}
+
+/// Send every request as a `POST /_query` HTTP request. There is no
+/// persistent connection: `connect` only checks that Kuzzle can be reached
+/// before enabling requests, and no real-time notification is ever received.
+pub struct Http {
+    hosts: Hosts,
+    options: HttpOptions,
+    current: Mutex<Option<usize>>,
+    state: ConnectionState,
+    subscribers: Subscribers,
+    metrics: MetricsRecorder,
+}
+
+/// Parsed HTTP response
+struct Reply {
+    status: u16,
+    cookies: Vec<String>,
+    body: String,
+}
+
+impl Http {
+    /// Create a new HTTP instance
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use kuzzle::protocols::{Http, HttpOptions};
+    ///
+    /// let http = Http::new("localhost", Some(HttpOptions::new().port(7512)));
+    /// ```
+    pub fn new<H: Into<Hosts>>(hosts: H, options: Option<HttpOptions>) -> Http {
+        Http {
+            hosts: hosts.into(),
+            options: options.unwrap_or_default(),
+            current: Mutex::new(None),
+            state: ConnectionState::new(),
+            subscribers: Subscribers::default(),
+            metrics: MetricsRecorder::default(),
+        }
+    }
+
+    async fn send_request(&self, request: Value) -> Result<String, Box<dyn Error>> {
+        if self.state.get() != State::Connected {
+            return Err(Box::new(IoError::new(
+                IoErrorKind::NotConnected,
+                "Not connected",
+            )));
+        }
+
+        self.post(request).await
+    }
+
+    /// Send a request whatever the connection state
+    async fn post(&self, request: Value) -> Result<String, Box<dyn Error>> {
+        let mut request = request;
+        let headers: Vec<(String, String)> = match request
+            .as_object_mut()
+            .and_then(|request| request.remove("headers"))
+        {
+            Some(Value::Object(headers)) => headers
+                .into_iter()
+                .map(|(name, value)| match value {
+                    Value::String(value) => (name, value),
+                    value => (name, value.to_string()),
+                })
+                .collect(),
+            _ => Vec::new(),
+        };
+
+        let body = request.to_string();
+        let (host, stream) = self.open_any().await?;
+
+        let response = match self.options.ssl {
+            false => {
+                let message = self.message(&host, &body, &headers)?;
+                self.metrics.sent(&message);
+                exchange(stream, &message).await?
+            }
+            true => {
+                let tls = self.options.tls.clone().unwrap_or_default();
+                let name = tls.server_name_for(&host);
+                let message = self.message(name, &body, &headers)?;
+                self.metrics.sent(&message);
+
+                let connector = tls.connector()?;
+                let stream = connector.connect(socket_host(name), stream).await?;
+                exchange(stream, &message).await?
+            }
+        };
+        self.metrics.received(&response);
+
+        let reply = parse_reply(&response)?;
+        if let Some(jar) = &self.options.cookies {
+            for cookie in &reply.cookies {
+                jar.store(cookie);
+            }
+        }
+
+        match reply.body.is_empty() {
+            true => Err(Box::new(IoError::new(
+                IoErrorKind::InvalidData,
+                format!("Empty response (HTTP {})", reply.status),
+            ))),
+            false => Ok(reply.body),
+        }
+    }
+
+    /// Open a TCP connection to the first available host
+    async fn open_any(&self) -> Result<(String, TcpStream), Box<dyn Error>> {
+        let current = *self.current.lock().unwrap();
+        let attempts: Vec<usize> = self
+            .hosts
+            .attempts(self.options.host_selection, current)
+            .collect();
+
+        for (attempt, &index) in attempts.iter().enumerate() {
+            let host = self.hosts.get(index).unwrap_or_default().to_string();
+
+            match self.open(&host).await {
+                Ok(stream) => {
+                    *self.current.lock().unwrap() = Some(index);
+                    return Ok((host, stream));
+                }
+                Err(error) => {
+                    self.metrics.connection_failed();
+                    self.state.error(&*error);
+                    if attempt + 1 == attempts.len() {
+                        return Err(error);
+                    }
+                }
+            }
+        }
+
+        Err(Box::new(ProtocolError::NoHost))
+    }
+
+    async fn open(&self, host: &str) -> Result<TcpStream, Box<dyn Error>> {
+        match &self.options.proxy {
+            Some(proxy) => proxy.connect(host, self.options.port).await,
+            None => Ok(TcpStream::connect((socket_host(host), self.options.port)).await?),
+        }
+    }
+
+    /// Forge the HTTP request carrying `body`, along with the given headers
+    /// of the request
+    fn message(
+        &self,
+        host: &str,
+        body: &str,
+        headers: &[(String, String)],
+    ) -> Result<Vec<u8>, Box<dyn Error>> {
+        let mut message = format!(
+            "POST {}{} HTTP/1.1\r\nHost: {}:{}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n",
+            self.options.path,
+            QUERY_ROUTE,
+            url_host(host),
+            self.options.port,
+            body.len()
+        );
+
+        // Cookies given as headers are merged with the jar ones, a single
+        // `Cookie` header being allowed
+        let mut cookies = Vec::new();
+
+        for (name, value) in self.options.headers.iter().chain(headers) {
+            if name.contains(&['\r', '\n', ':'][..]) || value.contains(&['\r', '\n'][..]) {
+                return Err(Box::new(IoError::new(
+                    IoErrorKind::InvalidInput,
+                    format!("Invalid header: {}", name),
+                )));
+            }
+            match name.eq_ignore_ascii_case("cookie") {
+                true => cookies.push(value.clone()),
+                false => message.push_str(&format!("{}: {}\r\n", name, value)),
+            }
+        }
+
+        cookies.extend(self.options.cookies.as_ref().and_then(CookieJar::header));
+        if !cookies.is_empty() {
+            message.push_str(&format!("Cookie: {}\r\n", cookies.join("; ")));
+        }
+
+        message.push_str("\r\n");
+        message.push_str(body);
+
+        Ok(message.into_bytes())
+    }
+}
+
+/// Write a request and read the whole response, the server closing the
+/// connection once it is sent
+async fn exchange<S>(mut stream: S, message: &[u8]) -> Result<Vec<u8>, Box<dyn Error>>
+where
+    S: AsyncReadExt + AsyncWriteExt + Unpin,
+{
+    stream.write_all(message).await?;
+    stream.flush().await?;
+
+    let mut response = Vec::new();
+    stream.read_to_end(&mut response).await?;
+
+    Ok(response)
+}
+
+fn parse_reply(response: &[u8]) -> Result<Reply, Box<dyn Error>> {
+    let invalid = || IoError::new(IoErrorKind::InvalidData, "Malformed HTTP response");
+    let split = response
+        .windows(4)
+        .position(|window| window == b"\r\n\r\n")
+        .ok_or_else(invalid)?;
+    let head = String::from_utf8_lossy(&response[..split]);
+    let body = &response[split + 4..];
+
+    let mut lines = head.split("\r\n");
+    let status = lines
+        .next()
+        .and_then(|line| line.split_whitespace().nth(1))
+        .and_then(|status| status.parse().ok())
+        .ok_or_else(invalid)?;
+
+    let mut cookies = Vec::new();
+    let mut chunked = false;
+    let mut length = None;
+
+    for line in lines {
+        let (name, value) = match line.split_once(':') {
+            Some((name, value)) => (name.trim().to_ascii_lowercase(), value.trim()),
+            None => continue,
+        };
+
+        match name.as_str() {
+            "set-cookie" => cookies.push(value.to_string()),
+            "transfer-encoding" => chunked = value.eq_ignore_ascii_case("chunked"),
+            "content-length" => length = value.parse::<usize>().ok(),
+            _ => {}
+        }
+    }
+
+    let body = match (chunked, length) {
+        (true, _) => decode_chunked(body).ok_or_else(invalid)?,
+        (false, Some(length)) => body.get(..length).ok_or_else(invalid)?.to_vec(),
+        (false, None) => body.to_vec(),
+    };
+
+    Ok(Reply {
+        status,
+        cookies,
+        body: String::from_utf8(body)?,
+    })
+}
+
+/// Reassemble a body sent with `Transfer-Encoding: chunked`
+fn decode_chunked(mut body: &[u8]) -> Option<Vec<u8>> {
+    let mut decoded = Vec::new();
+
+    loop {
+        let line_end = body.windows(2).position(|window| window == b"\r\n")?;
+        let size = std::str::from_utf8(&body[..line_end]).ok()?;
+        let size = usize::from_str_radix(size.split(';').next()?.trim(), 16).ok()?;
+        body = &body[line_end + 2..];
+
+        if size == 0 {
+            return Some(decoded);
+        }
+
+        decoded.extend_from_slice(body.get(..size)?);
+        body = body.get(size + 2..)?;
+    }
+}
+
+#[async_trait]
+impl Protocol for Http {
+    /// Check that Kuzzle answers `server:now` before enabling requests, so
+    /// that an unreachable server is reported as such
+    async fn connect(&self) -> Result<(), Box<dyn Error>> {
+        if self.hosts.is_empty() {
+            return Err(Box::new(ProtocolError::NoHost));
+        }
+
+        self.state.set(State::Connecting);
+        let probe = json!({
+            "requestId": Uuid::new_v4().to_string(),
+            "controller": "server",
+            "action": "now",
+        });
+        let reached = match self.options.request_timeout {
+            Some(timeout) => match runtime::timeout(timeout, self.post(probe)).await {
+                Some(reached) => reached,
+                None => Err(ProtocolError::Timeout(timeout).into()),
+            },
+            None => self.post(probe).await,
+        };
+        if let Err(error) = reached {
+            self.state.set(State::Offline);
+            return Err(error);
+        }
+
+        self.state.connected();
+        self.metrics.connected();
+        Ok(())
+    }
+
+    async fn disconnect(&self) -> Result<(), Box<dyn Error>> {
+        match self.state.get() {
+            State::Offline => Err(Box::new(IoError::new(
+                IoErrorKind::NotConnected,
+                "Already closed",
+            ))),
+            _ => {
+                self.state.disconnected();
+                Ok(())
+            }
+        }
+    }
+
+    /// Requests are sent on their own connection: there is nothing to drain
+    async fn disconnect_graceful(&self, _: Duration) -> Result<(), Box<dyn Error>> {
+        self.disconnect().await
+    }
+
+    fn state(&self) -> State {
+        self.state.get()
+    }
+
+    fn on_connect(&mut self, callback: ConnectCallback) {
+        self.state.on_connect(callback);
+    }
+
+    fn on_disconnect(&mut self, callback: DisconnectCallback) {
+        self.state.on_disconnect(callback);
+    }
+
+    fn on_error(&mut self, callback: ErrorCallback) {
+        self.state.on_error(callback);
+    }
+
+    #[cfg(feature = "wire-trace")]
+    fn on_frame(&mut self, callback: FrameCallback) {
+        self.metrics.on_frame(callback);
+    }
+
+    /// Never receives anything, HTTP having no real-time notification
+    fn incoming(&self) -> Incoming {
+        self.subscribers.subscribe()
+    }
+
+    fn metrics(&self) -> Metrics {
+        self.metrics.snapshot()
+    }
+
+    async fn send(&self, request: Value) -> Result<String, Box<dyn Error>> {
+        self.metrics
+            .track(self.send_request(request), self.options.request_timeout)
+            .await
+    }
+
+    async fn send_with_timeout(
+        &self,
+        request: Value,
+        timeout: Duration,
+    ) -> Result<String, Box<dyn Error>> {
+        self.metrics
+            .track(self.send_request(request), Some(timeout))
+            .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use async_std::net::TcpListener;
+    use serde_json::json;
+
+    /// Answer the connection probe then a single request with `response`,
+    /// resolving with the request
+    async fn serve_once(
+        response: &'static str,
+    ) -> Result<(u16, async_std::task::JoinHandle<String>), Box<dyn Error>> {
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let port = listener.local_addr()?.port();
+
+        let server = async_std::task::spawn(async move {
+            let mut request = String::new();
+            for _ in 0..2 {
+                let (mut socket, _) = listener.accept().await.unwrap();
+                let mut buffer = vec![0u8; 4096];
+                let len = socket.read(&mut buffer).await.unwrap();
+                socket.write_all(response.as_bytes()).await.unwrap();
+                request = String::from_utf8_lossy(&buffer[..len]).to_string();
+            }
+            request
+        });
+
+        Ok((port, server))
+    }
+
+    #[async_std::test]
+    async fn should_send_request() -> Result<(), Box<dyn Error>> {
+        let (port, server) = serve_once(
+            "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: 32\r\nSet-Cookie: SERVERID=node1\r\n\r\n{\"requestId\":\"foo\",\"status\":200}",
+        )
+        .await?;
+
+        let jar = CookieJar::new();
+        let http = Http::new(
+            "127.0.0.1",
+            Some(HttpOptions::new().port(port).cookies(jar.clone())),
+        );
+        http.connect().await?;
+
+        let raw = http.send(json!({"requestId": "foo"})).await?;
+        assert_eq!(raw, json!({"requestId": "foo", "status": 200}).to_string());
+        assert_eq!(jar.header(), Some("SERVERID=node1".to_string()));
+
+        let request = server.await;
+        assert!(request.starts_with("POST /_query HTTP/1.1\r\n"));
+        assert!(request.ends_with("\r\n\r\n{\"requestId\":\"foo\"}"));
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn should_send_request_headers() -> Result<(), Box<dyn Error>> {
+        let (port, server) = serve_once(
+            "HTTP/1.1 200 OK\r\nContent-Length: 32\r\n\r\n{\"requestId\":\"foo\",\"status\":200}",
+        )
+        .await?;
+
+        let http = Http::new("127.0.0.1", Some(HttpOptions::new().port(port)));
+        http.connect().await?;
+        http.send(json!({"requestId": "foo", "headers": {"x-kuzzle-volatile": "bar"}}))
+            .await?;
+
+        let request = server.await;
+        assert!(request.contains("\r\nx-kuzzle-volatile: bar\r\n"));
+        assert!(request.ends_with("\r\n\r\n{\"requestId\":\"foo\"}"));
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn should_not_connect_to_unreachable_server() {
+        let http = Http::new("127.0.0.1", Some(HttpOptions::new().port(1)));
+
+        assert!(http.connect().await.is_err());
+        assert_eq!(http.state(), State::Offline);
+    }
+
+    #[async_std::test]
+    async fn should_not_send_request_when_disconnected() {
+        let http = Http::new("127.0.0.1", None);
+        assert!(http.send(json!({"requestId": "foo"})).await.is_err());
+    }
+
+    #[test]
+    fn should_decode_chunked_body() -> Result<(), Box<dyn Error>> {
+        let reply = parse_reply(
+            b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n4\r\n{\"a\"\r\n3\r\n:1}\r\n0\r\n\r\n",
+        )?;
+
+        assert_eq!(reply.status, 200);
+        assert_eq!(reply.body, "{\"a\":1}");
+        Ok(())
+    }
+
+    #[test]
+    fn should_not_parse_malformed_response() {
+        assert!(parse_reply(b"garbage").is_err());
+    }
+
+    #[test]
+    fn should_merge_cookies_with_cookie_headers() -> Result<(), Box<dyn Error>> {
+        let jar = CookieJar::new();
+        jar.store("SERVERID=node2; Path=/");
+
+        let http = Http::new(
+            "localhost",
+            Some(
+                HttpOptions::new()
+                    .header("Cookie", "session=42")
+                    .cookies(jar),
+            ),
+        );
+        let message = String::from_utf8(http.message("localhost", "{}", &[])?)?;
+
+        assert_eq!(message.matches("Cookie: ").count(), 1);
+        assert!(message.contains("\r\nCookie: session=42; SERVERID=node2\r\n"));
+        Ok(())
+    }
+
+    #[test]
+    fn should_refuse_header_injection() {
+        let http = Http::new(
+            "localhost",
+            Some(HttpOptions::new().header("X-Foo", "bar\r\nEvil: 1")),
+        );
+        assert!(http.message("localhost", "{}", &[]).is_err());
+
+        let http = Http::new("localhost", None);
+        let headers = [("X-Foo".to_string(), "bar\r\nEvil: 1".to_string())];
+        assert!(http.message("localhost", "{}", &headers).is_err());
+    }
+}
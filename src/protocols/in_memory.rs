@@ -0,0 +1,257 @@
+use async_trait::async_trait;
+use serde_json::{Map, Value};
+use std::collections::VecDeque;
+use std::error::Error;
+use std::io::Error as IoError;
+use std::io::ErrorKind as IoErrorKind;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use super::incoming::Subscribers;
+use super::metrics::MetricsRecorder;
+use super::state::ConnectionState;
+#[cfg(feature = "wire-trace")]
+use super::FrameCallback;
+use super::{ConnectCallback, DisconnectCallback, ErrorCallback};
+use super::{Incoming, Metrics, Protocol, State};
+
+#[derive(Default)]
+struct Inner {
+    requests: Mutex<Vec<Value>>,
+    responses: Mutex<VecDeque<Value>>,
+}
+
+/// Protocol recording the requests it is given and answering them with
+/// scripted responses, without any network. Meant to unit test code using a
+/// `Kuzzle` instance.
+///
+/// Clones share the same recorded requests and scripted responses, so that a
+/// clone can be kept to inspect what was sent once the original is handed
+/// over to `Kuzzle`.
+///
+/// # Example
+///
+/// ```
+/// use kuzzle::protocols::InMemory;
+/// use kuzzle::{request, Kuzzle};
+/// use serde_json::json;
+///
+/// # async_std::task::block_on(async {
+/// let protocol = InMemory::new();
+/// protocol.respond(json!({"result": {"now": 1234}}));
+///
+/// let kuzzle = Kuzzle::new(protocol.clone());
+/// kuzzle.connect().await.unwrap();
+///
+/// let request = request!({"controller": "server", "action": "now"}).unwrap();
+/// let response = kuzzle.query(&request).await.unwrap();
+///
+/// assert_eq!(response.result.unwrap()["now"], 1234);
+/// assert_eq!(protocol.requests()[0]["action"], "now");
+/// # });
+/// ```
+#[derive(Clone)]
+pub struct InMemory {
+    inner: Arc<Inner>,
+    state: ConnectionState,
+    subscribers: Subscribers,
+    metrics: MetricsRecorder,
+}
+
+impl Default for InMemory {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl InMemory {
+    pub fn new() -> InMemory {
+        InMemory {
+            inner: Arc::new(Inner::default()),
+            state: ConnectionState::new(),
+            subscribers: Subscribers::default(),
+            metrics: MetricsRecorder::default(),
+        }
+    }
+
+    /// Queue the response to the next request. `requestId`, `controller` and
+    /// `action` are copied from the request and `status` defaults to 200 when
+    /// missing.
+    pub fn respond(&self, response: Value) {
+        self.inner.responses.lock().unwrap().push_back(response);
+    }
+
+    /// Every request sent so far, in order
+    pub fn requests(&self) -> Vec<Value> {
+        self.inner.requests.lock().unwrap().clone()
+    }
+
+    /// Push a message to the incoming streams, as Kuzzle would do with a
+    /// real-time notification
+    pub fn notify(&self, message: Value) {
+        self.subscribers.publish(message.to_string());
+    }
+
+    fn answer(&self, request: Value) -> Result<String, Box<dyn Error>> {
+        self.inner.requests.lock().unwrap().push(request.clone());
+
+        if self.state.get() != State::Connected {
+            return Err(Box::new(IoError::new(
+                IoErrorKind::NotConnected,
+                "Not connected",
+            )));
+        }
+
+        let response = self
+            .inner
+            .responses
+            .lock()
+            .unwrap()
+            .pop_front()
+            .ok_or_else(|| IoError::new(IoErrorKind::UnexpectedEof, "No scripted response"))?;
+
+        let mut response = match response {
+            Value::Object(response) => response,
+            _ => Map::new(),
+        };
+        for field in &["requestId", "controller", "action"] {
+            if let Some(value) = request.get(*field) {
+                response
+                    .entry(field.to_string())
+                    .or_insert_with(|| value.clone());
+            }
+        }
+        response.entry("status").or_insert_with(|| Value::from(200));
+
+        Ok(Value::Object(response).to_string())
+    }
+}
+
+#[async_trait]
+impl Protocol for InMemory {
+    async fn connect(&self) -> Result<(), Box<dyn Error>> {
+        self.state.connected();
+        self.metrics.connected();
+        Ok(())
+    }
+
+    async fn disconnect(&self) -> Result<(), Box<dyn Error>> {
+        self.state.disconnected();
+        Ok(())
+    }
+
+    async fn disconnect_graceful(&self, _: Duration) -> Result<(), Box<dyn Error>> {
+        self.disconnect().await
+    }
+
+    fn state(&self) -> State {
+        self.state.get()
+    }
+
+    fn on_connect(&mut self, callback: ConnectCallback) {
+        self.state.on_connect(callback);
+    }
+
+    fn on_disconnect(&mut self, callback: DisconnectCallback) {
+        self.state.on_disconnect(callback);
+    }
+
+    fn on_error(&mut self, callback: ErrorCallback) {
+        self.state.on_error(callback);
+    }
+
+    #[cfg(feature = "wire-trace")]
+    fn on_frame(&mut self, callback: FrameCallback) {
+        self.metrics.on_frame(callback);
+    }
+
+    fn incoming(&self) -> Incoming {
+        self.subscribers.subscribe()
+    }
+
+    fn metrics(&self) -> Metrics {
+        self.metrics.snapshot()
+    }
+
+    async fn send(&self, request: Value) -> Result<String, Box<dyn Error>> {
+        self.metrics.sent(request.to_string().as_bytes());
+
+        self.metrics
+            .track(
+                async move {
+                    let response = self.answer(request);
+                    if let Ok(response) = &response {
+                        self.metrics.received(response.as_bytes());
+                    }
+                    response
+                },
+                None,
+            )
+            .await
+    }
+
+    async fn send_with_timeout(
+        &self,
+        request: Value,
+        _: Duration,
+    ) -> Result<String, Box<dyn Error>> {
+        self.send(request).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures_util::stream::StreamExt;
+    use serde_json::json;
+
+    #[async_std::test]
+    async fn should_answer_scripted_responses() -> Result<(), Box<dyn Error>> {
+        let protocol = InMemory::new();
+        protocol.respond(json!({"status": 404, "error": {"message": "not found"}}));
+        protocol.connect().await?;
+
+        let request = json!({"requestId": "foo", "controller": "document", "action": "get"});
+        let response: Value = serde_json::from_str(&protocol.send(request.clone()).await?)?;
+
+        assert_eq!(
+            response,
+            json!({
+                "requestId": "foo",
+                "controller": "document",
+                "action": "get",
+                "status": 404,
+                "error": {"message": "not found"}
+            })
+        );
+        assert_eq!(protocol.requests(), vec![request]);
+        assert_eq!(protocol.metrics().requests, 1);
+
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn should_fail_without_scripted_response() -> Result<(), Box<dyn Error>> {
+        let protocol = InMemory::new();
+        assert!(protocol.send(json!({})).await.is_err());
+
+        protocol.connect().await?;
+        assert!(protocol.send(json!({})).await.is_err());
+        assert_eq!(protocol.requests().len(), 2);
+
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn should_stream_notifications() {
+        let protocol = InMemory::new();
+        let mut incoming = protocol.incoming();
+
+        protocol.notify(json!({"type": "document"}));
+
+        assert_eq!(
+            incoming.next().await,
+            Some(json!({"type": "document"}).to_string())
+        );
+    }
+}
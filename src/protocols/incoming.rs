@@ -0,0 +1,78 @@
+use futures_channel::mpsc::{self, UnboundedSender};
+use futures_util::stream::{self, BoxStream, Stream, StreamExt};
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+
+/// Stream of the messages sent by Kuzzle without being the response of a
+/// request, such as real-time notifications or `tokenExpired` pushes.
+///
+/// It survives reconnections and ends when the protocol is dropped.
+pub struct Incoming(BoxStream<'static, String>);
+
+impl Incoming {
+    /// Merge several streams into one, yielding messages as they arrive
+    pub(crate) fn merge(streams: Vec<Incoming>) -> Incoming {
+        Incoming(stream::select_all(streams).boxed())
+    }
+}
+
+impl Stream for Incoming {
+    type Item = String;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<String>> {
+        self.0.as_mut().poll_next(cx)
+    }
+}
+
+/// Every `Incoming` stream handed out by a protocol, fed by its reading task
+#[derive(Clone, Default)]
+pub(crate) struct Subscribers(Arc<Mutex<Vec<UnboundedSender<String>>>>);
+
+impl Subscribers {
+    pub(crate) fn subscribe(&self) -> Incoming {
+        let (tx, rx) = mpsc::unbounded();
+        self.0.lock().unwrap().push(tx);
+        Incoming(rx.boxed())
+    }
+
+    /// Forward a message to every live stream, forgetting the dropped ones
+    pub(crate) fn publish(&self, payload: String) {
+        self.0
+            .lock()
+            .unwrap()
+            .retain(|tx| tx.unbounded_send(payload.clone()).is_ok());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[async_std::test]
+    async fn should_publish_to_every_stream() {
+        let subscribers = Subscribers::default();
+        let mut first = subscribers.subscribe();
+        let second = subscribers.subscribe();
+
+        drop(second);
+        subscribers.publish("foo".into());
+
+        assert_eq!(first.next().await, Some("foo".to_string()));
+        assert_eq!(subscribers.0.lock().unwrap().len(), 1);
+
+        drop(subscribers);
+        assert_eq!(first.next().await, None);
+    }
+
+    #[async_std::test]
+    async fn should_merge_streams() {
+        let subscribers = Subscribers::default();
+        let mut merged = Incoming::merge(vec![subscribers.subscribe(), subscribers.subscribe()]);
+
+        subscribers.publish("foo".into());
+
+        assert_eq!(merged.next().await, Some("foo".to_string()));
+        assert_eq!(merged.next().await, Some("foo".to_string()));
+    }
+}
@@ -0,0 +1,348 @@
+use std::error::Error;
+use std::future::Future;
+use std::sync::atomic::{AtomicU64, Ordering};
+#[cfg(feature = "wire-trace")]
+use std::sync::RwLock;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use super::ProtocolError;
+#[cfg(feature = "wire-trace")]
+use super::{Direction, Frame, FrameCallback};
+use crate::runtime::{self, Instant};
+
+/// Upper bounds of the latency histogram buckets, the last bucket gathering
+/// every slower request
+const LATENCY_BOUNDS: [Duration; 10] = [
+    Duration::from_millis(1),
+    Duration::from_millis(5),
+    Duration::from_millis(10),
+    Duration::from_millis(25),
+    Duration::from_millis(50),
+    Duration::from_millis(100),
+    Duration::from_millis(250),
+    Duration::from_millis(500),
+    Duration::from_secs(1),
+    Duration::from_secs(5),
+];
+
+/// Snapshot of the activity of a protocol since its creation
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Metrics {
+    /// Requests sent, failed ones included
+    pub requests: u64,
+    /// Requests that failed, timeouts included
+    pub errors: u64,
+    /// Connections established, reconnections included
+    pub connections: u64,
+    /// Connection attempts that failed
+    pub connection_errors: u64,
+    /// Payload bytes written to the connection
+    pub bytes_sent: u64,
+    /// Payload bytes read from the connection, notifications included
+    pub bytes_received: u64,
+    /// Time taken by the requests to complete
+    pub latency: Latency,
+}
+
+/// Latency histogram
+#[derive(Debug, Clone, PartialEq)]
+pub struct Latency {
+    /// Number of requests per bucket, along with the bucket upper bound.
+    /// The last bucket has no upper bound.
+    pub buckets: Vec<(Option<Duration>, u64)>,
+    pub count: u64,
+    pub total: Duration,
+    pub max: Duration,
+}
+
+impl Default for Latency {
+    fn default() -> Self {
+        Self {
+            buckets: LATENCY_BOUNDS
+                .iter()
+                .map(|bound| Some(*bound))
+                .chain(std::iter::once(None))
+                .map(|bound| (bound, 0))
+                .collect(),
+            count: 0,
+            total: Duration::from_secs(0),
+            max: Duration::from_secs(0),
+        }
+    }
+}
+
+impl Latency {
+    /// Average latency, if any request completed
+    pub fn mean(&self) -> Option<Duration> {
+        match self.count {
+            0 => None,
+            count => Some(Duration::from_nanos(
+                (self.total.as_nanos() / count as u128) as u64,
+            )),
+        }
+    }
+
+    fn record(&mut self, latency: Duration) {
+        let bucket = LATENCY_BOUNDS
+            .iter()
+            .position(|bound| latency <= *bound)
+            .unwrap_or(LATENCY_BOUNDS.len());
+
+        self.buckets[bucket].1 += 1;
+        self.count += 1;
+        self.total += latency;
+        self.max = self.max.max(latency);
+    }
+
+    fn merge(&mut self, other: &Latency) {
+        for (bucket, (_, count)) in self.buckets.iter_mut().zip(&other.buckets) {
+            bucket.1 += count;
+        }
+        self.count += other.count;
+        self.total += other.total;
+        self.max = self.max.max(other.max);
+    }
+}
+
+impl Metrics {
+    /// Add up the metrics of another protocol, as a pool does with its members
+    pub fn merge(&mut self, other: &Metrics) {
+        self.requests += other.requests;
+        self.errors += other.errors;
+        self.connections += other.connections;
+        self.connection_errors += other.connection_errors;
+        self.bytes_sent += other.bytes_sent;
+        self.bytes_received += other.bytes_received;
+        self.latency.merge(&other.latency);
+    }
+}
+
+#[derive(Default)]
+struct Counters {
+    requests: AtomicU64,
+    errors: AtomicU64,
+    connections: AtomicU64,
+    connection_errors: AtomicU64,
+    bytes_sent: AtomicU64,
+    bytes_received: AtomicU64,
+    latency: Mutex<Latency>,
+    #[cfg(feature = "wire-trace")]
+    on_frame: RwLock<Vec<FrameCallback>>,
+}
+
+/// Collect the metrics of a protocol, shared with its background tasks. With
+/// the `wire-trace` feature, frames are also handed to the trace callbacks.
+#[derive(Clone, Default)]
+pub(crate) struct MetricsRecorder(Arc<Counters>);
+
+impl MetricsRecorder {
+    /// Await a request, failing with `ProtocolError::Timeout` if it takes more
+    /// than `timeout`, and record its outcome and latency
+    pub(crate) async fn track<F>(
+        &self,
+        request: F,
+        timeout: Option<Duration>,
+    ) -> Result<String, Box<dyn Error>>
+    where
+        F: Future<Output = Result<String, Box<dyn Error>>>,
+    {
+        let started_at = Instant::now();
+        let result = match timeout {
+            Some(timeout) => match runtime::timeout(timeout, request).await {
+                Some(result) => result,
+                None => Err(Box::new(ProtocolError::Timeout(timeout))),
+            },
+            None => request.await,
+        };
+
+        self.0.requests.fetch_add(1, Ordering::Relaxed);
+        match &result {
+            Ok(_) => self.0.latency.lock().unwrap().record(started_at.elapsed()),
+            Err(_) => {
+                self.0.errors.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+
+        result
+    }
+
+    pub(crate) fn connected(&self) {
+        self.0.connections.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn connection_failed(&self) {
+        self.0.connection_errors.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn sent(&self, payload: &[u8]) {
+        self.0
+            .bytes_sent
+            .fetch_add(payload.len() as u64, Ordering::Relaxed);
+
+        #[cfg(feature = "wire-trace")]
+        self.trace(Direction::Outbound, payload);
+    }
+
+    pub(crate) fn received(&self, payload: &[u8]) {
+        self.0
+            .bytes_received
+            .fetch_add(payload.len() as u64, Ordering::Relaxed);
+
+        #[cfg(feature = "wire-trace")]
+        self.trace(Direction::Inbound, payload);
+    }
+
+    #[cfg(feature = "wire-trace")]
+    pub(crate) fn on_frame(&self, callback: FrameCallback) {
+        self.0.on_frame.write().unwrap().push(callback);
+    }
+
+    #[cfg(feature = "wire-trace")]
+    fn trace(&self, direction: Direction, payload: &[u8]) {
+        let callbacks = self.0.on_frame.read().unwrap();
+        if callbacks.is_empty() {
+            return;
+        }
+
+        let frame = Frame {
+            direction,
+            payload,
+            at: Instant::now(),
+        };
+        for callback in callbacks.iter() {
+            callback(&frame);
+        }
+    }
+
+    pub(crate) fn snapshot(&self) -> Metrics {
+        Metrics {
+            requests: self.0.requests.load(Ordering::Relaxed),
+            errors: self.0.errors.load(Ordering::Relaxed),
+            connections: self.0.connections.load(Ordering::Relaxed),
+            connection_errors: self.0.connection_errors.load(Ordering::Relaxed),
+            bytes_sent: self.0.bytes_sent.load(Ordering::Relaxed),
+            bytes_received: self.0.bytes_received.load(Ordering::Relaxed),
+            latency: self.0.latency.lock().unwrap().clone(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[async_std::test]
+    async fn should_track_requests() {
+        let recorder = MetricsRecorder::default();
+
+        assert!(recorder
+            .track(async { Ok("foo".into()) }, None)
+            .await
+            .is_ok());
+        assert!(recorder
+            .track(
+                async { Err(Box::new(std::fmt::Error) as Box<dyn Error>) },
+                None
+            )
+            .await
+            .is_err());
+        recorder.sent(b"foo");
+        recorder.received(b"hello");
+
+        let metrics = recorder.snapshot();
+        assert_eq!(metrics.requests, 2);
+        assert_eq!(metrics.errors, 1);
+        assert_eq!(metrics.bytes_sent, 3);
+        assert_eq!(metrics.bytes_received, 5);
+        assert_eq!(metrics.latency.count, 1);
+        assert_eq!(metrics.latency.buckets[0].1, 1);
+    }
+
+    #[async_std::test]
+    async fn should_track_timeouts() {
+        let recorder = MetricsRecorder::default();
+        let timeout = Duration::from_millis(10);
+
+        let err = recorder
+            .track(futures_util::future::pending(), Some(timeout))
+            .await
+            .err()
+            .unwrap();
+
+        assert_eq!(
+            err.downcast_ref::<ProtocolError>(),
+            Some(&ProtocolError::Timeout(timeout))
+        );
+        assert_eq!(recorder.snapshot().errors, 1);
+    }
+
+    #[test]
+    fn should_bucket_latencies() {
+        let mut latency = Latency::default();
+        latency.record(Duration::from_millis(30));
+        latency.record(Duration::from_secs(10));
+
+        assert_eq!(latency.buckets.len(), LATENCY_BOUNDS.len() + 1);
+        assert_eq!(latency.buckets[4], (Some(Duration::from_millis(50)), 1));
+        assert_eq!(latency.buckets[10], (None, 1));
+        assert_eq!(latency.max, Duration::from_secs(10));
+        assert_eq!(latency.mean(), Some(Duration::from_millis(5015)));
+    }
+
+    #[test]
+    fn should_average_large_counts() {
+        let latency = Latency {
+            count: 1 << 32,
+            total: Duration::from_secs(1 << 33),
+            ..Latency::default()
+        };
+
+        assert_eq!(latency.mean(), Some(Duration::from_secs(2)));
+    }
+
+    #[test]
+    fn should_merge_metrics() {
+        let mut metrics = Metrics {
+            requests: 1,
+            ..Metrics::default()
+        };
+        let mut other = Metrics {
+            requests: 2,
+            connections: 1,
+            ..Metrics::default()
+        };
+        other.latency.record(Duration::from_millis(2));
+
+        metrics.merge(&other);
+
+        assert_eq!(metrics.requests, 3);
+        assert_eq!(metrics.connections, 1);
+        assert_eq!(metrics.latency.count, 1);
+    }
+
+    #[cfg(feature = "wire-trace")]
+    #[test]
+    fn should_trace_frames() {
+        let recorder = MetricsRecorder::default();
+        let frames = Arc::new(Mutex::new(Vec::new()));
+        let traced = frames.clone();
+        recorder.on_frame(Box::new(move |frame: &Frame| {
+            traced
+                .lock()
+                .unwrap()
+                .push((frame.direction, frame.payload.to_vec()));
+        }));
+
+        recorder.sent(b"foo");
+        recorder.received(b"bar");
+
+        assert_eq!(
+            *frames.lock().unwrap(),
+            vec![
+                (Direction::Outbound, b"foo".to_vec()),
+                (Direction::Inbound, b"bar".to_vec())
+            ]
+        );
+    }
+}
@@ -1,12 +1,105 @@
 use async_trait::async_trait;
+use serde_json::Value;
+use std::any::Any;
 use std::error::Error as Errors;
+use std::time::Duration;
 
+/// Transport to a Kuzzle server. Protocols are shared between tasks, hence
+/// manage their connection through interior mutability.
 #[async_trait]
-pub trait Protocol {
-    async fn connect(&mut self) -> Result<(), Box<dyn Errors>>;
-    async fn disconnect(&mut self) -> Result<(), Box<dyn Errors>>;
-    async fn send(&mut self, request: String) -> Result<String, Box<dyn Errors>>;
+pub trait Protocol: Any + Send + Sync {
+    async fn connect(&self) -> Result<(), Box<dyn Errors>>;
+    async fn disconnect(&self) -> Result<(), Box<dyn Errors>>;
+    /// Refuse new requests and wait at most `timeout` for the pending ones to
+    /// complete before disconnecting. Only the requests already sent are
+    /// waited for: `Kuzzle::disconnect_graceful` and `Kuzzle::shutdown` send
+    /// the client offline queue beforehand.
+    async fn disconnect_graceful(&self, timeout: Duration) -> Result<(), Box<dyn Errors>>;
+    /// Current connection state, telling whether a request can be sent
+    fn state(&self) -> State;
+    /// Register a callback invoked every time a connection is established
+    fn on_connect(&mut self, callback: ConnectCallback);
+    /// Register a callback invoked every time the connection ends
+    fn on_disconnect(&mut self, callback: DisconnectCallback);
+    /// Register a callback invoked on connection errors
+    fn on_error(&mut self, callback: ErrorCallback);
+    /// Register a callback invoked with every frame written or read. Ignored
+    /// by protocols not supporting it.
+    #[cfg(feature = "wire-trace")]
+    fn on_frame(&mut self, _callback: FrameCallback) {}
+    /// Stream of the messages not matching any request, such as real-time
+    /// notifications. Each call returns a new stream receiving every message.
+    fn incoming(&self) -> Incoming;
+    /// Activity of the protocol since its creation
+    fn metrics(&self) -> Metrics;
+    /// Send a request and resolve with its response. The request is encoded
+    /// by the protocol, according to its transport and options. Several
+    /// requests can be in flight at once: responses are matched using their
+    /// `requestId`.
+    async fn send(&self, request: Value) -> Result<String, Box<dyn Errors>>;
+    /// Same as `send`, but resolves with `ProtocolError::Timeout` if no response
+    /// was received within `timeout`
+    async fn send_with_timeout(
+        &self,
+        request: Value,
+        timeout: Duration,
+    ) -> Result<String, Box<dyn Errors>>;
 }
 
+#[cfg(not(feature = "wasm"))]
+pub mod auto;
+#[cfg(feature = "wasm")]
+pub mod browser;
+pub mod chaos;
+pub mod cookies;
+pub mod encoding;
+pub mod error;
+pub mod hosts;
+#[cfg(not(feature = "wasm"))]
+pub mod http;
+pub mod in_memory;
+pub mod incoming;
+pub mod metrics;
+#[cfg(not(feature = "wasm"))]
+pub mod mqtt;
+mod pending;
+pub mod pool;
+#[cfg(not(feature = "wasm"))]
+pub mod proxy;
+pub mod reconnect;
+pub mod state;
+#[cfg(not(feature = "wasm"))]
+pub mod tls;
+#[cfg(feature = "wire-trace")]
+pub mod trace;
+#[cfg(not(feature = "wasm"))]
 pub mod websocket;
+#[cfg(not(feature = "wasm"))]
+pub use self::auto::{Auto, AutoOptions};
+#[cfg(feature = "wasm")]
+pub use self::browser::{WebSocket, WebSocketOptions};
+pub use self::chaos::{ChaosOptions, ChaosProxy};
+pub use self::cookies::CookieJar;
+pub use self::encoding::Encoding;
+pub use self::error::ProtocolError;
+pub use self::hosts::{HostSelection, Hosts};
+#[cfg(not(feature = "wasm"))]
+pub use self::http::{Http, HttpOptions};
+pub use self::in_memory::InMemory;
+pub use self::incoming::Incoming;
+pub use self::metrics::{Latency, Metrics};
+#[cfg(not(feature = "wasm"))]
+pub use self::mqtt::{Mqtt, MqttOptions, QoS};
+pub use self::pool::{Pool, PoolOptions};
+#[cfg(not(feature = "wasm"))]
+pub use self::proxy::{ProxyKind, ProxyOptions};
+pub use self::reconnect::ReconnectPolicy;
+pub use self::state::{
+    ConnectCallback, DisconnectCallback, DisconnectReason, ErrorCallback, State,
+};
+#[cfg(not(feature = "wasm"))]
+pub use self::tls::TlsOptions;
+#[cfg(feature = "wire-trace")]
+pub use self::trace::{Direction, Frame, FrameCallback};
+#[cfg(not(feature = "wasm"))]
 pub use self::websocket::{WebSocket, WebSocketOptions};
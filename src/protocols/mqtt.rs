@@ -0,0 +1,693 @@
+use async_trait::async_trait;
+use futures_util::lock::Mutex;
+use serde_json::Value;
+use std::error::Error;
+use std::io::Error as IoError;
+use std::io::ErrorKind as IoErrorKind;
+use std::sync::atomic::{AtomicU16, Ordering};
+use std::sync::{Arc, Mutex as StdMutex, Weak};
+use std::time::Duration;
+use uuid::Uuid;
+
+use super::hosts::socket_host;
+use super::incoming::Subscribers;
+use super::metrics::MetricsRecorder;
+use super::pending::PendingRequests;
+use super::state::ConnectionState;
+#[cfg(feature = "wire-trace")]
+use super::FrameCallback;
+use super::{ConnectCallback, DisconnectCallback, DisconnectReason, ErrorCallback};
+use super::{HostSelection, Hosts, Incoming, Metrics, Protocol, ProtocolError, State};
+use crate::runtime::{self, AsyncReadExt, AsyncWriteExt, TcpReadHalf, TcpStream, TcpWriteHalf};
+
+/// Topic Kuzzle listens to for incoming requests
+const REQUEST_TOPIC: &str = "Kuzzle/request";
+/// Topic Kuzzle publishes the responses to
+const RESPONSE_TOPIC: &str = "Kuzzle/response";
+
+// MQTT 3.1.1 control packet types, including their fixed header flags
+const CONNECT: u8 = 0x10;
+const CONNACK: u8 = 0x20;
+const PUBLISH: u8 = 0x30;
+const PUBACK: u8 = 0x40;
+const SUBSCRIBE: u8 = 0x82;
+const SUBACK: u8 = 0x90;
+const PINGREQ: u8 = 0xc0;
+const DISCONNECT: u8 = 0xe0;
+
+const PROTOCOL_LEVEL: u8 = 4;
+const CLEAN_SESSION: u8 = 0x02;
+const SUBSCRIPTION_FAILURE: u8 = 0x80;
+
+/// Quality of service used to publish requests and receive responses
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum QoS {
+    AtMostOnce = 0,
+    AtLeastOnce = 1,
+}
+
+pub struct MqttOptions {
+    pub port: u16,
+    pub client_id: String,
+    pub qos: QoS,
+    pub keep_alive: Duration,
+    pub request_timeout: Option<Duration>,
+    pub host_selection: HostSelection,
+    pub max_in_flight: Option<usize>,
+}
+
+impl Default for MqttOptions {
+    fn default() -> Self {
+        Self {
+            port: 1883,
+            client_id: format!("kuzzle-rs-{}", Uuid::new_v4()),
+            qos: QoS::AtMostOnce,
+            keep_alive: Duration::from_secs(60),
+            request_timeout: None,
+            host_selection: HostSelection::InOrder,
+            max_in_flight: None,
+        }
+    }
+}
+
+impl MqttOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn port(mut self, port: u16) -> Self {
+        self.port = port;
+        self
+    }
+
+    /// Client identifier presented to the broker. A random one is generated
+    /// by default.
+    pub fn client_id(mut self, client_id: &str) -> Self {
+        self.client_id = client_id.into();
+        self
+    }
+
+    pub fn qos(mut self, qos: QoS) -> Self {
+        self.qos = qos;
+        self
+    }
+
+    /// Maximum interval between two packets sent to the broker, pings are sent
+    /// when idle. A zero duration disables the keep alive mechanism.
+    pub fn keep_alive(mut self, keep_alive: Duration) -> Self {
+        self.keep_alive = keep_alive;
+        self
+    }
+
+    /// Default time to wait for a response before failing with
+    /// `ProtocolError::Timeout`. Requests wait forever when unset.
+    pub fn request_timeout(mut self, timeout: Duration) -> Self {
+        self.request_timeout = Some(timeout);
+        self
+    }
+
+    /// Order in which the brokers are tried when several of them are given
+    pub fn host_selection(mut self, selection: HostSelection) -> Self {
+        self.host_selection = selection;
+        self
+    }
+
+    /// Maximum number of requests awaiting a response at once. Requests
+    /// beyond it fail with `ProtocolError::TooManyRequests`.
+    pub fn max_in_flight(mut self, limit: usize) -> Self {
+        self.max_in_flight = Some(limit);
+        self
+    }
+}
+
+pub struct Mqtt {
+    hosts: Hosts,
+    current: StdMutex<Option<usize>>,
+    options: MqttOptions,
+    link: StdMutex<Option<Link>>,
+    state: ConnectionState,
+    subscribers: Subscribers,
+    metrics: MetricsRecorder,
+    packet_id: AtomicU16,
+}
+
+/// Writing half of the current connection and the requests sent over it
+#[derive(Clone)]
+struct Link {
+    writer: Arc<Mutex<TcpWriteHalf>>,
+    pending: PendingRequests,
+}
+
+impl Mqtt {
+    /// Create a new MQTT instance
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use kuzzle::protocols::{Mqtt, MqttOptions, QoS};
+    ///
+    /// let mqtt = Mqtt::new("localhost", None);
+    ///
+    /// let options = MqttOptions::new()
+    ///     .client_id("my-device")
+    ///     .qos(QoS::AtLeastOnce);
+    ///
+    /// let customized_mqtt = Mqtt::new("localhost", Some(options));
+    ///
+    /// // Several brokers can be given: they are tried in turn on connection
+    /// let cluster_mqtt = Mqtt::new(&["node1", "node2"], None);
+    /// ```
+    pub fn new<H: Into<Hosts>>(hosts: H, options: Option<MqttOptions>) -> Mqtt {
+        Mqtt {
+            hosts: hosts.into(),
+            current: StdMutex::new(None),
+            options: options.unwrap_or_default(),
+            link: StdMutex::new(None),
+            state: ConnectionState::new(),
+            subscribers: Subscribers::default(),
+            metrics: MetricsRecorder::default(),
+            packet_id: AtomicU16::new(1),
+        }
+    }
+
+    fn link(&self) -> Option<Link> {
+        self.link.lock().unwrap().clone()
+    }
+
+    /// Packet identifiers must be non-zero
+    fn next_packet_id(&self) -> u16 {
+        loop {
+            let id = self.packet_id.fetch_add(1, Ordering::Relaxed);
+            if id != 0 {
+                return id;
+            }
+        }
+    }
+
+    /// Connect to the first available broker, reporting every failed attempt
+    async fn open_any(&self) -> Result<(TcpReadHalf, TcpWriteHalf), Box<dyn Error>> {
+        let current = *self.current.lock().unwrap();
+        let attempts: Vec<usize> = self
+            .hosts
+            .attempts(self.options.host_selection, current)
+            .collect();
+
+        for (attempt, &index) in attempts.iter().enumerate() {
+            let host = self.hosts.get(index).unwrap_or_default().to_string();
+
+            match self.open(&host).await {
+                Ok(halves) => {
+                    *self.current.lock().unwrap() = Some(index);
+                    return Ok(halves);
+                }
+                Err(error) => {
+                    self.metrics.connection_failed();
+                    self.state.error(&*error);
+                    if attempt + 1 == attempts.len() {
+                        return Err(error);
+                    }
+                }
+            }
+        }
+
+        Err(Box::new(ProtocolError::NoHost))
+    }
+
+    /// Connect to the broker and subscribe to Kuzzle responses
+    async fn open(&self, host: &str) -> Result<(TcpReadHalf, TcpWriteHalf), Box<dyn Error>> {
+        let stream = TcpStream::connect((socket_host(host), self.options.port)).await?;
+        let (mut reader, mut writer) = runtime::split_tcp(stream);
+
+        writer
+            .write_all(&connect_packet(
+                &self.options.client_id,
+                self.options.keep_alive,
+            ))
+            .await?;
+
+        let (header, body) = read_packet(&mut reader).await?;
+        if header != CONNACK || body.get(1) != Some(&0) {
+            return Err(Box::new(IoError::new(
+                IoErrorKind::ConnectionRefused,
+                format!("MQTT connection refused (return code {:?})", body.get(1)),
+            )));
+        }
+
+        writer
+            .write_all(&subscribe_packet(
+                RESPONSE_TOPIC,
+                self.options.qos,
+                self.next_packet_id(),
+            ))
+            .await?;
+
+        let (header, body) = read_packet(&mut reader).await?;
+        let return_code = body.get(2).copied().unwrap_or(SUBSCRIPTION_FAILURE);
+        if header != SUBACK || return_code == SUBSCRIPTION_FAILURE {
+            return Err(Box::new(IoError::new(
+                IoErrorKind::ConnectionRefused,
+                "Could not subscribe to Kuzzle responses",
+            )));
+        }
+
+        Ok((reader, writer))
+    }
+
+    async fn send_request(&self, request: Value) -> Result<String, Box<dyn Error>> {
+        let link = self.link().ok_or_else(not_connected)?;
+        let pending = link.pending.register(&request)?;
+        let packet = publish_packet(
+            REQUEST_TOPIC,
+            request.to_string().as_bytes(),
+            self.options.qos,
+            self.next_packet_id(),
+        );
+
+        self.metrics.sent(&packet);
+        link.writer.lock().await.write_all(&packet).await?;
+        pending.response().await
+    }
+}
+
+fn not_connected() -> IoError {
+    IoError::new(
+        IoErrorKind::NotConnected,
+        "Not connected to the MQTT broker",
+    )
+}
+
+/// Encode a packet from its fixed header first byte and its body
+fn packet(header: u8, body: &[u8]) -> Vec<u8> {
+    let mut packet = vec![header];
+    let mut remaining = body.len();
+
+    loop {
+        let mut byte = (remaining % 128) as u8;
+        remaining /= 128;
+
+        if remaining > 0 {
+            byte |= 0x80;
+        }
+        packet.push(byte);
+
+        if remaining == 0 {
+            break;
+        }
+    }
+
+    packet.extend_from_slice(body);
+    packet
+}
+
+fn push_str(buffer: &mut Vec<u8>, value: &str) {
+    buffer.extend_from_slice(&(value.len() as u16).to_be_bytes());
+    buffer.extend_from_slice(value.as_bytes());
+}
+
+fn connect_packet(client_id: &str, keep_alive: Duration) -> Vec<u8> {
+    let keep_alive = keep_alive.as_secs().min(u16::MAX as u64) as u16;
+    let mut body = Vec::new();
+
+    push_str(&mut body, "MQTT");
+    body.push(PROTOCOL_LEVEL);
+    body.push(CLEAN_SESSION);
+    body.extend_from_slice(&keep_alive.to_be_bytes());
+    push_str(&mut body, client_id);
+
+    packet(CONNECT, &body)
+}
+
+fn subscribe_packet(topic: &str, qos: QoS, packet_id: u16) -> Vec<u8> {
+    let mut body = packet_id.to_be_bytes().to_vec();
+
+    push_str(&mut body, topic);
+    body.push(qos as u8);
+
+    packet(SUBSCRIBE, &body)
+}
+
+fn publish_packet(topic: &str, payload: &[u8], qos: QoS, packet_id: u16) -> Vec<u8> {
+    let mut body = Vec::new();
+
+    push_str(&mut body, topic);
+    if qos != QoS::AtMostOnce {
+        body.extend_from_slice(&packet_id.to_be_bytes());
+    }
+    body.extend_from_slice(payload);
+
+    packet(PUBLISH | ((qos as u8) << 1), &body)
+}
+
+/// Extract the topic, the packet identifier (when it must be acknowledged)
+/// and the payload of a PUBLISH packet
+fn parse_publish(header: u8, body: &[u8]) -> Option<(String, Option<u16>, &[u8])> {
+    let topic_len = u16::from_be_bytes([*body.first()?, *body.get(1)?]) as usize;
+    let mut offset = 2 + topic_len;
+    let topic = std::str::from_utf8(body.get(2..offset)?).ok()?.to_string();
+
+    let packet_id = match (header >> 1) & 0x03 {
+        0 => None,
+        _ => {
+            let id = body.get(offset..offset + 2)?;
+            offset += 2;
+            Some(u16::from_be_bytes([id[0], id[1]]))
+        }
+    };
+
+    Some((topic, packet_id, body.get(offset..)?))
+}
+
+/// Read a whole packet, returning its fixed header first byte and its body
+async fn read_packet(reader: &mut TcpReadHalf) -> Result<(u8, Vec<u8>), IoError> {
+    let mut byte = [0u8; 1];
+    reader.read_exact(&mut byte).await?;
+    let header = byte[0];
+
+    let mut remaining = 0;
+    let mut multiplier = 1;
+
+    loop {
+        reader.read_exact(&mut byte).await?;
+        remaining += (byte[0] & 0x7f) as usize * multiplier;
+
+        if byte[0] & 0x80 == 0 {
+            break;
+        }
+
+        multiplier *= 128;
+        if multiplier > 128 * 128 * 128 {
+            return Err(IoError::new(
+                IoErrorKind::InvalidData,
+                "Malformed MQTT remaining length",
+            ));
+        }
+    }
+
+    let mut body = vec![0u8; remaining];
+    reader.read_exact(&mut body).await?;
+
+    Ok((header, body))
+}
+
+/// Acknowledge incoming messages when required and route Kuzzle responses to
+/// the requests waiting for them, and any other message to the incoming
+/// streams, until the connection is closed
+async fn read_packets(
+    mut reader: TcpReadHalf,
+    writer: Weak<Mutex<TcpWriteHalf>>,
+    pending: PendingRequests,
+    subscribers: Subscribers,
+    state: ConnectionState,
+    generation: u64,
+    metrics: MetricsRecorder,
+) {
+    while let Ok((header, body)) = read_packet(&mut reader).await {
+        if header & 0xf0 != PUBLISH {
+            continue;
+        }
+        metrics.received(&body);
+
+        if let Some((topic, packet_id, payload)) = parse_publish(header, &body) {
+            if let (Some(id), Some(w)) = (packet_id, writer.upgrade()) {
+                let _ = w
+                    .lock()
+                    .await
+                    .write_all(&packet(PUBACK, &id.to_be_bytes()))
+                    .await;
+            }
+
+            let payload = String::from_utf8_lossy(payload).into_owned();
+            let unmatched = match topic.as_str() {
+                RESPONSE_TOPIC => pending.dispatch(payload),
+                _ => Some(payload),
+            };
+
+            if let Some(payload) = unmatched {
+                subscribers.publish(payload);
+            }
+        }
+    }
+
+    pending.close();
+    state.lost(generation, DisconnectReason::ConnectionLost);
+}
+
+/// Ping the broker every `interval` so it keeps the connection open.
+/// Stops as soon as the client is disconnected.
+async fn keep_alive(writer: Weak<Mutex<TcpWriteHalf>>, interval: Duration) {
+    loop {
+        runtime::sleep(interval).await;
+
+        let w = match writer.upgrade() {
+            Some(w) => w,
+            None => break,
+        };
+        let sent = w.lock().await.write_all(&packet(PINGREQ, &[])).await;
+
+        if sent.is_err() {
+            break;
+        }
+    }
+}
+
+#[async_trait]
+impl Protocol for Mqtt {
+    async fn connect(&self) -> Result<(), Box<dyn Error>> {
+        self.state.set(State::Connecting);
+
+        let (reader, writer) = match self.open_any().await {
+            Ok(halves) => halves,
+            Err(error) => {
+                self.state.set(State::Offline);
+                return Err(error);
+            }
+        };
+        let writer = Arc::new(Mutex::new(writer));
+        let pending = PendingRequests::new(self.options.max_in_flight);
+        let generation = self.state.connected();
+        self.metrics.connected();
+
+        if self.options.keep_alive > Duration::from_secs(0) {
+            runtime::spawn(keep_alive(Arc::downgrade(&writer), self.options.keep_alive));
+        }
+        runtime::spawn(read_packets(
+            reader,
+            Arc::downgrade(&writer),
+            pending.clone(),
+            self.subscribers.clone(),
+            self.state.clone(),
+            generation,
+            self.metrics.clone(),
+        ));
+
+        *self.link.lock().unwrap() = Some(Link { writer, pending });
+        Ok(())
+    }
+
+    async fn disconnect(&self) -> Result<(), Box<dyn Error>> {
+        let link = self.link.lock().unwrap().take();
+
+        match link {
+            Some(link) => {
+                link.pending.close();
+                self.state.disconnected();
+                link.writer
+                    .lock()
+                    .await
+                    .write_all(&packet(DISCONNECT, &[]))
+                    .await?;
+                Ok(())
+            }
+            None => Err(Box::new(not_connected())),
+        }
+    }
+
+    async fn disconnect_graceful(&self, timeout: Duration) -> Result<(), Box<dyn Error>> {
+        if let Some(link) = self.link() {
+            link.pending.drain();
+            runtime::timeout(timeout, link.pending.settled()).await;
+        }
+        self.disconnect().await
+    }
+
+    fn state(&self) -> State {
+        self.state.get()
+    }
+
+    fn on_connect(&mut self, callback: ConnectCallback) {
+        self.state.on_connect(callback);
+    }
+
+    fn on_disconnect(&mut self, callback: DisconnectCallback) {
+        self.state.on_disconnect(callback);
+    }
+
+    fn on_error(&mut self, callback: ErrorCallback) {
+        self.state.on_error(callback);
+    }
+
+    #[cfg(feature = "wire-trace")]
+    fn on_frame(&mut self, callback: FrameCallback) {
+        self.metrics.on_frame(callback);
+    }
+
+    fn incoming(&self) -> Incoming {
+        self.subscribers.subscribe()
+    }
+
+    fn metrics(&self) -> Metrics {
+        self.metrics.snapshot()
+    }
+
+    async fn send(&self, request: Value) -> Result<String, Box<dyn Error>> {
+        self.metrics
+            .track(self.send_request(request), self.options.request_timeout)
+            .await
+    }
+
+    async fn send_with_timeout(
+        &self,
+        request: Value,
+        timeout: Duration,
+    ) -> Result<String, Box<dyn Error>> {
+        self.metrics
+            .track(self.send_request(request), Some(timeout))
+            .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{request, Kuzzle};
+    use async_std::net::TcpListener;
+    use serde_json::json;
+
+    #[test]
+    fn should_encode_remaining_length() {
+        let encoded = packet(PUBLISH, &[0u8; 321]);
+
+        assert_eq!(&encoded[..3], &[0x30, 0xc1, 0x02]);
+        assert_eq!(encoded.len(), 3 + 321);
+        assert_eq!(packet(PINGREQ, &[]), vec![0xc0, 0x00]);
+    }
+
+    #[test]
+    fn should_encode_connect_packet() {
+        let encoded = connect_packet("foo", Duration::from_secs(60));
+
+        assert_eq!(
+            encoded,
+            vec![0x10, 15, 0, 4, b'M', b'Q', b'T', b'T', 4, 0x02, 0, 60, 0, 3, b'f', b'o', b'o']
+        );
+    }
+
+    #[test]
+    fn should_parse_publish_packet() {
+        let encoded = publish_packet("foo", b"bar", QoS::AtLeastOnce, 42);
+        let (topic, packet_id, payload) = parse_publish(encoded[0], &encoded[2..]).unwrap();
+
+        assert_eq!(encoded[0], 0x32);
+        assert_eq!(topic, "foo");
+        assert_eq!(packet_id, Some(42));
+        assert_eq!(payload, b"bar");
+    }
+
+    #[test]
+    fn should_not_parse_truncated_publish_packet() {
+        assert!(parse_publish(PUBLISH, &[0, 10, b'f']).is_none());
+    }
+
+    #[async_std::test]
+    async fn should_not_send_before_connect() {
+        let mqtt = Mqtt::new("localhost", None);
+        let res = mqtt.send(json!({"requestId": "foo"})).await;
+
+        assert!(res.is_err());
+    }
+
+    /// Fake broker answering each request with the request itself, with a
+    /// success status
+    async fn echo_broker() -> Result<u16, Box<dyn Error>> {
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let port = listener.local_addr()?.port();
+
+        async_std::task::spawn(async move {
+            let (socket, _) = listener.accept().await.unwrap();
+            let (mut reader, mut writer) = runtime::split_tcp(socket);
+
+            let (header, _) = read_packet(&mut reader).await.unwrap();
+            assert_eq!(header, CONNECT);
+            writer.write_all(&packet(CONNACK, &[0, 0])).await.unwrap();
+
+            let (header, body) = read_packet(&mut reader).await.unwrap();
+            assert_eq!(header, SUBSCRIBE);
+            writer
+                .write_all(&packet(SUBACK, &[body[0], body[1], 0]))
+                .await
+                .unwrap();
+
+            while let Ok((header, body)) = read_packet(&mut reader).await {
+                if let Some((topic, _, payload)) = parse_publish(header, &body) {
+                    assert_eq!(topic, REQUEST_TOPIC);
+                    let mut response: Value = serde_json::from_slice(payload).unwrap();
+                    response["status"] = json!(200);
+                    let response = response.to_string();
+
+                    writer
+                        .write_all(&publish_packet(
+                            RESPONSE_TOPIC,
+                            response.as_bytes(),
+                            QoS::AtMostOnce,
+                            0,
+                        ))
+                        .await
+                        .unwrap();
+                }
+            }
+        });
+
+        Ok(port)
+    }
+
+    #[async_std::test]
+    async fn should_send_request() -> Result<(), Box<dyn Error>> {
+        let port = echo_broker().await?;
+        let mqtt = Mqtt::new("127.0.0.1", Some(MqttOptions::new().port(port)));
+        mqtt.connect().await?;
+        assert_eq!(mqtt.state(), State::Connected);
+
+        let request = json!({"requestId": "foo", "controller": "server", "action": "now"});
+        let raw = mqtt.send(request.clone()).await?;
+        let response: Value = serde_json::from_str(&raw)?;
+        assert_eq!(response["requestId"], request["requestId"]);
+        assert_eq!(response["status"], 200);
+
+        mqtt.disconnect().await?;
+        assert_eq!(mqtt.state(), State::Offline);
+
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn should_reject_duplicate_request_ids() -> Result<(), Box<dyn Error>> {
+        let port = echo_broker().await?;
+        let kuzzle = Kuzzle::new(Mqtt::new("127.0.0.1", Some(MqttOptions::new().port(port))));
+        kuzzle.connect().await?;
+
+        let request = request!({"controller": "server", "action": "now"})?;
+        let request_id = request.request_id.clone();
+        let batch = kuzzle.query_batch(&[request.clone(), request]).await;
+        assert_eq!(batch.results.len(), 2);
+        assert_eq!(batch.failures(), vec![1]);
+        assert_eq!(
+            batch.results[1]
+                .as_ref()
+                .err()
+                .and_then(|err| err.downcast_ref::<ProtocolError>()),
+            Some(&ProtocolError::DuplicateRequestId(request_id))
+        );
+
+        Ok(())
+    }
+}
@@ -0,0 +1,312 @@
+use futures_channel::oneshot;
+use serde::Deserialize;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::error::Error;
+use std::io::Error as IoError;
+use std::io::ErrorKind as IoErrorKind;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use super::ProtocolError;
+use crate::runtime;
+
+const SETTLE_POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+type Waiters = HashMap<String, oneshot::Sender<String>>;
+
+/// The only part of a Kuzzle payload needed to route it
+#[derive(Deserialize)]
+struct Envelope {
+    #[serde(rename = "requestId")]
+    request_id: Option<String>,
+    room: Option<String>,
+}
+
+/// Extract the `requestId` of a Kuzzle request
+fn request_id(request: &Value) -> Option<String> {
+    request
+        .get("requestId")
+        .and_then(Value::as_str)
+        .map(String::from)
+}
+
+/// Extract the `requestId` of a raw Kuzzle response. Real-time notifications
+/// carry the `requestId` of the request that triggered them, possibly one of
+/// ours still waiting for its response: they are told apart by their `room`,
+/// which is the request one for responses only.
+fn response_id(payload: &str) -> Option<String> {
+    let envelope = serde_json::from_str::<Envelope>(payload).ok()?;
+
+    match envelope.room {
+        Some(room) if Some(&room) != envelope.request_id.as_ref() => None,
+        _ => envelope.request_id,
+    }
+}
+
+/// Requests awaiting their response, keyed by `requestId`, shared between a
+/// protocol and the task reading its connection.
+///
+/// Once closed (connection lost or disconnected), every pending request fails
+/// and new ones are refused. When a limit is set, requests beyond it are
+/// refused with `ProtocolError::TooManyRequests`.
+#[derive(Clone)]
+pub(crate) struct PendingRequests {
+    waiters: Arc<Mutex<Option<Waiters>>>,
+    draining: Arc<AtomicBool>,
+    limit: Option<usize>,
+}
+
+impl PendingRequests {
+    pub(crate) fn new(limit: Option<usize>) -> Self {
+        Self {
+            waiters: Arc::new(Mutex::new(Some(HashMap::new()))),
+            draining: Arc::new(AtomicBool::new(false)),
+            limit,
+        }
+    }
+
+    /// Register a request before sending it, so that its response can't be
+    /// missed
+    pub(crate) fn register(&self, request: &Value) -> Result<PendingRequest, Box<dyn Error>> {
+        let request_id = request_id(request)
+            .ok_or_else(|| IoError::new(IoErrorKind::InvalidInput, "Missing requestId"))?;
+        let (tx, rx) = oneshot::channel();
+
+        if self.draining.load(Ordering::SeqCst) {
+            return Err(Box::new(IoError::new(
+                IoErrorKind::NotConnected,
+                "Connection closing",
+            )));
+        }
+
+        match self.waiters.lock().unwrap().as_mut() {
+            Some(waiters) if matches!(self.limit, Some(limit) if waiters.len() >= limit) => {
+                return Err(Box::new(ProtocolError::TooManyRequests(waiters.len())))
+            }
+            Some(waiters) if waiters.contains_key(&request_id) => {
+                return Err(Box::new(ProtocolError::DuplicateRequestId(request_id)))
+            }
+            Some(waiters) => waiters.insert(request_id.clone(), tx),
+            None => {
+                return Err(Box::new(IoError::new(
+                    IoErrorKind::NotConnected,
+                    "Connection closed",
+                )))
+            }
+        };
+
+        Ok(PendingRequest {
+            requests: self.clone(),
+            request_id,
+            response: rx,
+        })
+    }
+
+    /// Resolve the request matching the payload `requestId`. Returns the
+    /// payload back if no request is waiting for it or if it is not a response.
+    pub(crate) fn dispatch(&self, payload: String) -> Option<String> {
+        let waiter = response_id(&payload).and_then(|id| {
+            self.waiters
+                .lock()
+                .unwrap()
+                .as_mut()
+                .and_then(|waiters| waiters.remove(&id))
+        });
+
+        match waiter {
+            Some(waiter) => waiter.send(payload).err(),
+            None => Some(payload),
+        }
+    }
+
+    /// Abort every pending request and refuse new ones
+    pub(crate) fn close(&self) {
+        self.waiters.lock().unwrap().take();
+    }
+
+    /// Refuse new requests, letting the pending ones complete
+    pub(crate) fn drain(&self) {
+        self.draining.store(true, Ordering::SeqCst);
+    }
+
+    /// Resolve once no request is waiting for its response anymore
+    pub(crate) async fn settled(&self) {
+        while !self.is_empty() {
+            runtime::sleep(SETTLE_POLL_INTERVAL).await;
+        }
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.waiters
+            .lock()
+            .unwrap()
+            .as_ref()
+            .map_or(true, |w| w.is_empty())
+    }
+}
+
+/// Slot of a request in the pending requests map, freed when dropped so that
+/// timed out or cancelled requests don't leak
+pub(crate) struct PendingRequest {
+    requests: PendingRequests,
+    request_id: String,
+    response: oneshot::Receiver<String>,
+}
+
+impl PendingRequest {
+    pub(crate) async fn response(mut self) -> Result<String, Box<dyn Error>> {
+        match (&mut self.response).await {
+            Ok(response) => Ok(response),
+            Err(_) => Err(Box::new(IoError::new(
+                IoErrorKind::UnexpectedEof,
+                "No response from server",
+            ))),
+        }
+    }
+}
+
+impl Drop for PendingRequest {
+    fn drop(&mut self) {
+        if let Some(waiters) = self.requests.waiters.lock().unwrap().as_mut() {
+            waiters.remove(&self.request_id);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[async_std::test]
+    async fn should_resolve_matching_request() -> Result<(), Box<dyn Error>> {
+        let requests = PendingRequests::new(None);
+        let pending = requests.register(&json!({"requestId": "foo"}))?;
+
+        let response = json!({"requestId": "foo", "status": 200}).to_string();
+        assert_eq!(requests.dispatch(response.clone()), None);
+        assert_eq!(pending.response().await?, response);
+        assert!(requests.is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn should_give_back_unmatched_payloads() {
+        let requests = PendingRequests::new(None);
+        let payload = json!({"requestId": "foo"}).to_string();
+
+        assert_eq!(requests.dispatch(payload.clone()), Some(payload));
+        assert_eq!(requests.dispatch("{}".into()), Some("{}".into()));
+    }
+
+    #[test]
+    fn should_not_mistake_notifications_for_responses() -> Result<(), Box<dyn Error>> {
+        let requests = PendingRequests::new(None);
+        let _pending = requests.register(&json!({"requestId": "foo"}))?;
+
+        let notification =
+            json!({"requestId": "foo", "room": "channel", "type": "document"}).to_string();
+        assert_eq!(requests.dispatch(notification.clone()), Some(notification));
+        assert!(!requests.is_empty());
+
+        let response = json!({"requestId": "foo", "room": "foo", "status": 200}).to_string();
+        assert_eq!(requests.dispatch(response), None);
+        assert!(requests.is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn should_free_slot_on_drop() -> Result<(), Box<dyn Error>> {
+        let requests = PendingRequests::new(None);
+        let pending = requests.register(&json!({"requestId": "foo"}))?;
+
+        assert!(!requests.is_empty());
+        drop(pending);
+        assert!(requests.is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn should_not_register_without_request_id() {
+        let requests = PendingRequests::new(None);
+        assert!(requests.register(&json!({})).is_err());
+    }
+
+    #[test]
+    fn should_refuse_requests_beyond_limit() -> Result<(), Box<dyn Error>> {
+        let requests = PendingRequests::new(Some(1));
+        let pending = requests.register(&json!({"requestId": "foo"}))?;
+
+        let err = requests
+            .register(&json!({"requestId": "bar"}))
+            .err()
+            .unwrap();
+        assert_eq!(
+            err.downcast_ref::<ProtocolError>(),
+            Some(&ProtocolError::TooManyRequests(1))
+        );
+
+        drop(pending);
+        assert!(requests.register(&json!({"requestId": "bar"})).is_ok());
+
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn should_refuse_duplicate_request_id() -> Result<(), Box<dyn Error>> {
+        let requests = PendingRequests::new(None);
+        let pending = requests.register(&json!({"requestId": "foo"}))?;
+
+        let err = requests
+            .register(&json!({"requestId": "foo"}))
+            .err()
+            .unwrap();
+        assert_eq!(
+            err.downcast_ref::<ProtocolError>(),
+            Some(&ProtocolError::DuplicateRequestId("foo".into()))
+        );
+
+        // The request in flight keeps its slot
+        let response = json!({"requestId": "foo"}).to_string();
+        assert_eq!(requests.dispatch(response.clone()), None);
+        assert_eq!(pending.response().await?, response);
+
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn should_settle_when_drained() -> Result<(), Box<dyn Error>> {
+        let requests = PendingRequests::new(None);
+        let pending = requests.register(&json!({"requestId": "foo"}))?;
+
+        requests.drain();
+        assert!(requests.register(&json!({"requestId": "bar"})).is_err());
+
+        let response = json!({"requestId": "foo"}).to_string();
+        requests.dispatch(response.clone());
+        assert_eq!(pending.response().await?, response);
+
+        requests.settled().await;
+        assert!(requests.is_empty());
+
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn should_abort_requests_when_closed() -> Result<(), Box<dyn Error>> {
+        let requests = PendingRequests::new(None);
+        let pending = requests.register(&json!({"requestId": "foo"}))?;
+
+        requests.close();
+
+        assert!(pending.response().await.is_err());
+        assert!(requests.register(&json!({"requestId": "bar"})).is_err());
+
+        Ok(())
+    }
+}
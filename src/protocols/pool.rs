@@ -0,0 +1,451 @@
+use async_trait::async_trait;
+use futures_util::future::join_all;
+use serde_json::Value;
+use std::error::Error;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use super::state::AggregatedHooks;
+use super::{ConnectCallback, DisconnectCallback, DisconnectReason, ErrorCallback};
+#[cfg(feature = "wire-trace")]
+use super::{Frame, FrameCallback};
+use super::{Incoming, Metrics, Protocol, State};
+
+pub struct PoolOptions {
+    pub size: usize,
+}
+
+impl Default for PoolOptions {
+    fn default() -> Self {
+        Self { size: 4 }
+    }
+}
+
+impl PoolOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Number of connections opened by the pool
+    pub fn size(mut self, size: usize) -> Self {
+        self.size = size;
+        self
+    }
+}
+
+/// Maintain several connections of the same protocol and spread the requests
+/// across them, so that a single connection doesn't become a bottleneck for
+/// high throughput workloads.
+///
+/// Requests are dispatched round-robin among the connected members, except
+/// `realtime` ones: Kuzzle binds rooms to the connection which subscribed to
+/// them, so they all go to the first connected member.
+///
+/// The connection hooks are invoked for the pool as a whole: when its first
+/// member connects, and when its last connected member disconnects.
+pub struct Pool<P> {
+    members: Vec<P>,
+    next: AtomicUsize,
+    hooks: AggregatedHooks,
+}
+
+impl<P: Protocol> Pool<P> {
+    /// Create a pool, calling `factory` once per connection
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use kuzzle::protocols::{Pool, PoolOptions, WebSocket};
+    ///
+    /// let pool = Pool::new(
+    ///     || WebSocket::new("localhost", None),
+    ///     Some(PoolOptions::new().size(8)),
+    /// );
+    /// assert_eq!(pool.size(), 8);
+    /// ```
+    pub fn new<F>(factory: F, options: Option<PoolOptions>) -> Pool<P>
+    where
+        F: FnMut() -> P,
+    {
+        let options = options.unwrap_or_default();
+        let hooks = AggregatedHooks::new(options.size);
+        let mut members: Vec<P> = std::iter::repeat_with(factory).take(options.size).collect();
+
+        for (index, member) in members.iter_mut().enumerate() {
+            let connects = hooks.clone();
+            member.on_connect(Box::new(move || connects.connected(index)));
+
+            let disconnects = hooks.clone();
+            member.on_disconnect(Box::new(move |reason: &DisconnectReason| {
+                disconnects.disconnected(index, reason)
+            }));
+        }
+
+        Pool {
+            members,
+            next: AtomicUsize::new(0),
+            hooks,
+        }
+    }
+
+    pub fn size(&self) -> usize {
+        self.members.len()
+    }
+
+    /// Pick the next connected member, or any member if none is connected
+    /// so that the request fails with the protocol own error
+    fn pick(&self) -> Option<&P> {
+        let len = self.members.len().max(1);
+        let start = self.next.fetch_add(1, Ordering::Relaxed) % len;
+
+        (start..start + len)
+            .filter_map(|index| self.members.get(index % len))
+            .find(|member| member.state() == State::Connected)
+            .or_else(|| self.members.get(start))
+    }
+
+    /// Pick the member a request is sent through: `realtime` requests always
+    /// go to the first connected member, so that unsubscribing reaches the
+    /// connection which subscribed
+    fn route(&self, request: &Value) -> Option<&P> {
+        match request["controller"].as_str() {
+            Some("realtime") => self
+                .members
+                .iter()
+                .find(|member| member.state() == State::Connected)
+                .or_else(|| self.members.first()),
+            _ => self.pick(),
+        }
+    }
+}
+
+fn not_configured() -> Box<dyn Error> {
+    Box::new(std::io::Error::new(
+        std::io::ErrorKind::InvalidInput,
+        "Empty connection pool",
+    ))
+}
+
+fn already_closed() -> Box<dyn Error> {
+    Box::new(std::io::Error::new(
+        std::io::ErrorKind::NotConnected,
+        "Connection pool already closed",
+    ))
+}
+
+#[async_trait]
+impl<P: Protocol> Protocol for Pool<P> {
+    /// Open every connection of the pool, failing as soon as one of them
+    /// can't be established. `disconnect` closes the ones already opened.
+    async fn connect(&self) -> Result<(), Box<dyn Error>> {
+        if self.members.is_empty() {
+            return Err(not_configured());
+        }
+
+        for member in &self.members {
+            member.connect().await?;
+        }
+
+        Ok(())
+    }
+
+    async fn disconnect(&self) -> Result<(), Box<dyn Error>> {
+        let mut disconnected = false;
+
+        for member in &self.members {
+            if member.state() != State::Offline {
+                disconnected |= member.disconnect().await.is_ok();
+            }
+        }
+
+        match disconnected {
+            true => Ok(()),
+            false => Err(already_closed()),
+        }
+    }
+
+    /// Disconnect every member at once
+    async fn disconnect_graceful(&self, timeout: Duration) -> Result<(), Box<dyn Error>> {
+        let disconnected = join_all(
+            self.members
+                .iter()
+                .filter(|member| member.state() != State::Offline)
+                .map(|member| async move { member.disconnect_graceful(timeout).await.is_ok() }),
+        )
+        .await;
+
+        match disconnected.contains(&true) {
+            true => Ok(()),
+            false => Err(already_closed()),
+        }
+    }
+
+    /// Connected as long as at least one member is connected
+    fn state(&self) -> State {
+        let states: Vec<State> = self.members.iter().map(|member| member.state()).collect();
+
+        [State::Connected, State::Reconnecting, State::Connecting]
+            .iter()
+            .copied()
+            .find(|state| states.contains(state))
+            .unwrap_or(State::Offline)
+    }
+
+    /// Invoked when the first member connects
+    fn on_connect(&mut self, callback: ConnectCallback) {
+        self.hooks.on_connect(callback);
+    }
+
+    /// Invoked when the last connected member disconnects
+    fn on_disconnect(&mut self, callback: DisconnectCallback) {
+        self.hooks.on_disconnect(callback);
+    }
+
+    fn on_error(&mut self, callback: ErrorCallback) {
+        let callback = Arc::new(callback);
+
+        for member in &mut self.members {
+            let callback = callback.clone();
+            member.on_error(Box::new(move |error: &(dyn Error + 'static)| {
+                callback(error)
+            }));
+        }
+    }
+
+    /// Invoked with the frames of every member
+    #[cfg(feature = "wire-trace")]
+    fn on_frame(&mut self, callback: FrameCallback) {
+        let callback = Arc::new(callback);
+
+        for member in &mut self.members {
+            let callback = callback.clone();
+            member.on_frame(Box::new(move |frame: &Frame| callback(frame)));
+        }
+    }
+
+    /// Messages received by any member
+    fn incoming(&self) -> Incoming {
+        Incoming::merge(
+            self.members
+                .iter()
+                .map(|member| member.incoming())
+                .collect(),
+        )
+    }
+
+    /// Sum of the members metrics
+    fn metrics(&self) -> Metrics {
+        let mut metrics = Metrics::default();
+        for member in &self.members {
+            metrics.merge(&member.metrics());
+        }
+        metrics
+    }
+
+    async fn send(&self, request: Value) -> Result<String, Box<dyn Error>> {
+        match self.route(&request) {
+            Some(member) => member.send(request).await,
+            None => Err(not_configured()),
+        }
+    }
+
+    async fn send_with_timeout(
+        &self,
+        request: Value,
+        timeout: Duration,
+    ) -> Result<String, Box<dyn Error>> {
+        match self.route(&request) {
+            Some(member) => member.send_with_timeout(request, timeout).await,
+            None => Err(not_configured()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocols::incoming::Subscribers;
+    use crate::protocols::WebSocket;
+    use serde_json::json;
+    use std::sync::Mutex;
+
+    /// Protocol answering every request with the index of the member
+    struct Member {
+        index: usize,
+        state: Mutex<State>,
+        sent: Arc<Mutex<Vec<usize>>>,
+        connected: Option<ConnectCallback>,
+        disconnected: Option<DisconnectCallback>,
+    }
+
+    #[async_trait]
+    impl Protocol for Member {
+        async fn connect(&self) -> Result<(), Box<dyn Error>> {
+            *self.state.lock().unwrap() = State::Connected;
+            if let Some(callback) = &self.connected {
+                callback();
+            }
+            Ok(())
+        }
+        async fn disconnect(&self) -> Result<(), Box<dyn Error>> {
+            *self.state.lock().unwrap() = State::Offline;
+            if let Some(callback) = &self.disconnected {
+                callback(&DisconnectReason::Requested);
+            }
+            Ok(())
+        }
+        async fn disconnect_graceful(&self, _: Duration) -> Result<(), Box<dyn Error>> {
+            self.disconnect().await
+        }
+        fn state(&self) -> State {
+            *self.state.lock().unwrap()
+        }
+        fn on_connect(&mut self, callback: ConnectCallback) {
+            self.connected = Some(callback);
+        }
+        fn on_disconnect(&mut self, callback: DisconnectCallback) {
+            self.disconnected = Some(callback);
+        }
+        fn on_error(&mut self, _: ErrorCallback) {}
+        fn incoming(&self) -> Incoming {
+            Subscribers::default().subscribe()
+        }
+        fn metrics(&self) -> Metrics {
+            Metrics {
+                requests: 1,
+                ..Metrics::default()
+            }
+        }
+        async fn send(&self, _: Value) -> Result<String, Box<dyn Error>> {
+            self.sent.lock().unwrap().push(self.index);
+            Ok(self.index.to_string())
+        }
+        async fn send_with_timeout(
+            &self,
+            request: Value,
+            _: Duration,
+        ) -> Result<String, Box<dyn Error>> {
+            self.send(request).await
+        }
+    }
+
+    fn pool(size: usize) -> (Pool<Member>, Arc<Mutex<Vec<usize>>>) {
+        let sent = Arc::new(Mutex::new(Vec::new()));
+        let mut index = 0;
+        let members = sent.clone();
+        let pool = Pool::new(
+            move || {
+                index += 1;
+                Member {
+                    index,
+                    state: Mutex::new(State::Offline),
+                    sent: members.clone(),
+                    connected: None,
+                    disconnected: None,
+                }
+            },
+            Some(PoolOptions::new().size(size)),
+        );
+
+        (pool, sent)
+    }
+
+    #[async_std::test]
+    async fn should_dispatch_round_robin() -> Result<(), Box<dyn Error>> {
+        let (pool, sent) = pool(3);
+
+        assert_eq!(pool.state(), State::Offline);
+        pool.connect().await?;
+        assert_eq!(pool.state(), State::Connected);
+
+        for _ in 0..6 {
+            pool.send(json!({})).await?;
+        }
+        assert_eq!(*sent.lock().unwrap(), vec![1, 2, 3, 1, 2, 3]);
+        assert_eq!(pool.metrics().requests, 3);
+
+        pool.disconnect_graceful(Duration::from_secs(1)).await?;
+        assert_eq!(pool.state(), State::Offline);
+        assert!(pool.disconnect().await.is_err());
+
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn should_skip_offline_members() -> Result<(), Box<dyn Error>> {
+        let (pool, sent) = pool(3);
+
+        pool.connect().await?;
+        pool.members[1].disconnect().await?;
+
+        for _ in 0..4 {
+            pool.send(json!({})).await?;
+        }
+        assert_eq!(*sent.lock().unwrap(), vec![1, 3, 3, 1]);
+
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn should_send_realtime_requests_through_the_same_member() -> Result<(), Box<dyn Error>> {
+        let (pool, sent) = pool(3);
+        let subscribe = json!({"controller": "realtime", "action": "subscribe"});
+        let unsubscribe = json!({"controller": "realtime", "action": "unsubscribe"});
+
+        pool.connect().await?;
+        pool.send(subscribe.clone()).await?;
+        pool.send(json!({"controller": "document", "action": "get"}))
+            .await?;
+        pool.send(unsubscribe).await?;
+        pool.members[0].disconnect().await?;
+        pool.send(subscribe).await?;
+
+        assert_eq!(*sent.lock().unwrap(), vec![1, 1, 1, 2]);
+
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn should_aggregate_connection_hooks() -> Result<(), Box<dyn Error>> {
+        let (mut pool, _) = pool(3);
+        let connects = Arc::new(AtomicUsize::new(0));
+        let disconnects = Arc::new(AtomicUsize::new(0));
+
+        let counter = connects.clone();
+        pool.on_connect(Box::new(move || {
+            counter.fetch_add(1, Ordering::SeqCst);
+        }));
+        let counter = disconnects.clone();
+        pool.on_disconnect(Box::new(move |_: &DisconnectReason| {
+            counter.fetch_add(1, Ordering::SeqCst);
+        }));
+
+        pool.connect().await?;
+        assert_eq!(connects.load(Ordering::SeqCst), 1);
+
+        pool.members[1].disconnect().await?;
+        pool.members[1].connect().await?;
+        assert_eq!(connects.load(Ordering::SeqCst), 1);
+        assert_eq!(disconnects.load(Ordering::SeqCst), 0);
+
+        pool.disconnect().await?;
+        assert_eq!(disconnects.load(Ordering::SeqCst), 1);
+
+        pool.connect().await?;
+        assert_eq!(connects.load(Ordering::SeqCst), 2);
+
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn should_not_use_empty_pool() {
+        let pool = Pool::new(
+            || WebSocket::new("localhost", None),
+            Some(PoolOptions::new().size(0)),
+        );
+
+        assert!(pool.connect().await.is_err());
+        assert!(pool.send(json!({})).await.is_err());
+    }
+}
@@ -0,0 +1,155 @@
+use rand::Rng;
+use std::time::Duration;
+
+/// How a protocol re-establishes a lost connection: exponential backoff
+/// between attempts, randomized so that clients losing the same node don't
+/// reconnect in lockstep, and an optional retry budget after which it gives up.
+///
+/// # Example
+///
+/// ```
+/// use kuzzle::protocols::ReconnectPolicy;
+/// use std::time::Duration;
+///
+/// let policy = ReconnectPolicy::new()
+///     .initial_delay(Duration::from_millis(500))
+///     .max_delay(Duration::from_secs(10))
+///     .jitter(0.3)
+///     .max_retries(20);
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct ReconnectPolicy {
+    pub initial_delay: Duration,
+    pub max_delay: Duration,
+    pub jitter: f64,
+    pub max_retries: Option<u32>,
+}
+
+impl Default for ReconnectPolicy {
+    fn default() -> Self {
+        Self {
+            initial_delay: Duration::from_secs(1),
+            max_delay: Duration::from_secs(30),
+            jitter: 0.2,
+            max_retries: None,
+        }
+    }
+}
+
+impl ReconnectPolicy {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Delay before the first attempt, doubled after each failed one
+    pub fn initial_delay(mut self, delay: Duration) -> Self {
+        self.initial_delay = delay;
+        self
+    }
+
+    pub fn max_delay(mut self, delay: Duration) -> Self {
+        self.max_delay = delay;
+        self
+    }
+
+    /// Randomization of the delays, as a fraction of them: with `0.2`, each
+    /// delay is picked between 80% and 120% of its nominal value
+    pub fn jitter(mut self, jitter: f64) -> Self {
+        self.jitter = jitter.clamp(0.0, 1.0);
+        self
+    }
+
+    /// Number of failed attempts after which the protocol gives up and goes
+    /// offline. Retries forever when unset.
+    pub fn max_retries(mut self, retries: u32) -> Self {
+        self.max_retries = Some(retries);
+        self
+    }
+
+    /// Delay to wait before the given attempt, starting from 0
+    pub fn delay(&self, attempt: u32) -> Duration {
+        backoff(self.initial_delay, self.max_delay, self.jitter, attempt)
+    }
+
+    /// Whether another attempt is allowed after `attempts` failed ones
+    #[cfg_attr(feature = "wasm", allow(dead_code))]
+    pub(crate) fn allows(&self, attempts: u32) -> bool {
+        match self.max_retries {
+            Some(max) => attempts < max,
+            None => true,
+        }
+    }
+}
+
+/// Exponential backoff: `initial_delay` doubled after each attempt, capped to
+/// `max_delay` and randomized by `jitter`
+pub(crate) fn backoff(
+    initial_delay: Duration,
+    max_delay: Duration,
+    jitter: f64,
+    attempt: u32,
+) -> Duration {
+    let nominal = 2u32
+        .checked_pow(attempt)
+        .and_then(|factor| initial_delay.checked_mul(factor))
+        .map_or(max_delay, |delay| delay.min(max_delay));
+
+    if jitter > 0.0 {
+        let offset = rand::thread_rng().gen_range(-jitter..=jitter);
+        Duration::try_from_secs_f64(nominal.as_secs_f64() * (1.0 + offset)).unwrap_or(max_delay)
+    } else {
+        nominal
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_back_off_exponentially() {
+        let policy = ReconnectPolicy::new()
+            .initial_delay(Duration::from_millis(100))
+            .max_delay(Duration::from_secs(1))
+            .jitter(0.0);
+
+        assert_eq!(policy.delay(0), Duration::from_millis(100));
+        assert_eq!(policy.delay(2), Duration::from_millis(400));
+        assert_eq!(policy.delay(4), Duration::from_secs(1));
+        assert_eq!(policy.delay(100), Duration::from_secs(1));
+    }
+
+    #[test]
+    fn should_randomize_delays() {
+        let policy = ReconnectPolicy::new()
+            .initial_delay(Duration::from_secs(1))
+            .jitter(0.5);
+
+        for _ in 0..100 {
+            let delay = policy.delay(0);
+            assert!(delay >= Duration::from_millis(500));
+            assert!(delay <= Duration::from_millis(1500));
+        }
+    }
+
+    #[test]
+    fn should_not_overflow_huge_delays() {
+        let policy = ReconnectPolicy::new()
+            .initial_delay(Duration::MAX)
+            .max_delay(Duration::MAX)
+            .jitter(0.5);
+
+        for _ in 0..100 {
+            assert!(policy.delay(1) >= Duration::MAX / 2);
+        }
+    }
+
+    #[test]
+    fn should_enforce_retry_budget() {
+        assert!(ReconnectPolicy::new().allows(u32::MAX));
+
+        let policy = ReconnectPolicy::new().max_retries(2);
+        assert!(policy.allows(1));
+        assert!(!policy.allows(2));
+    }
+}
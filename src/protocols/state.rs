@@ -0,0 +1,284 @@
+use std::error::Error;
+use std::sync::{Arc, Mutex, RwLock};
+
+/// Connection state of a protocol
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum State {
+    /// A connection attempt is in progress
+    Connecting,
+    /// Requests can be sent
+    Connected,
+    /// The connection was lost and is being re-established
+    Reconnecting,
+    /// Not connected, requests will fail
+    Offline,
+}
+
+/// Why a connection ended
+#[derive(Debug, Clone, PartialEq)]
+pub enum DisconnectReason {
+    /// `disconnect` was called
+    Requested,
+    /// The connection was closed by the server or dropped by the network
+    ConnectionLost,
+    /// The server stopped answering pings
+    HeartbeatTimeout,
+    /// The server closed the connection, e.g. when restarting or revoking the
+    /// session. `code` is the WebSocket close code (RFC 6455, section 7.4).
+    ClosedByServer { code: u16, reason: String },
+}
+
+pub type ConnectCallback = Box<dyn Fn() + Send + Sync>;
+pub type DisconnectCallback = Box<dyn Fn(&DisconnectReason) + Send + Sync>;
+pub type ErrorCallback = Box<dyn Fn(&(dyn Error + 'static)) + Send + Sync>;
+
+#[derive(Default)]
+struct Hooks {
+    on_connect: Vec<ConnectCallback>,
+    on_disconnect: Vec<DisconnectCallback>,
+    on_error: Vec<ErrorCallback>,
+}
+
+struct Inner {
+    state: Mutex<(State, u64)>,
+    hooks: RwLock<Hooks>,
+}
+
+/// State shared between a protocol and its background tasks, notifying the
+/// registered hooks of every connection lifecycle change.
+///
+/// Each successful connection starts a new generation, so that tasks bound to
+/// a previous connection can't alter the state of the current one.
+#[derive(Clone)]
+pub(crate) struct ConnectionState(Arc<Inner>);
+
+impl ConnectionState {
+    pub(crate) fn new() -> Self {
+        Self(Arc::new(Inner {
+            state: Mutex::new((State::Offline, 0)),
+            hooks: RwLock::new(Hooks::default()),
+        }))
+    }
+
+    pub(crate) fn get(&self) -> State {
+        self.0.state.lock().unwrap().0
+    }
+
+    pub(crate) fn set(&self, state: State) {
+        self.0.state.lock().unwrap().0 = state;
+    }
+
+    pub(crate) fn on_connect(&self, callback: ConnectCallback) {
+        self.0.hooks.write().unwrap().on_connect.push(callback);
+    }
+
+    pub(crate) fn on_disconnect(&self, callback: DisconnectCallback) {
+        self.0.hooks.write().unwrap().on_disconnect.push(callback);
+    }
+
+    pub(crate) fn on_error(&self, callback: ErrorCallback) {
+        self.0.hooks.write().unwrap().on_error.push(callback);
+    }
+
+    /// Mark a new connection as established, returning its generation
+    pub(crate) fn connected(&self) -> u64 {
+        let generation = {
+            let mut state = self.0.state.lock().unwrap();
+            state.0 = State::Connected;
+            state.1 += 1;
+            state.1
+        };
+
+        for callback in &self.0.hooks.read().unwrap().on_connect {
+            callback();
+        }
+
+        generation
+    }
+
+    /// Mark the current connection as closed on purpose
+    pub(crate) fn disconnected(&self) {
+        self.set(State::Offline);
+        self.notify_disconnect(&DisconnectReason::Requested);
+    }
+
+    /// Mark the connection of the given generation as lost. Returns `false`
+    /// if it was already known to be closed, or if a newer connection has been
+    /// established since.
+    pub(crate) fn lost(&self, generation: u64, reason: DisconnectReason) -> bool {
+        {
+            let mut state = self.0.state.lock().unwrap();
+
+            if state.1 != generation || state.0 == State::Offline {
+                return false;
+            }
+
+            state.0 = State::Offline;
+        }
+
+        self.notify_disconnect(&reason);
+        true
+    }
+
+    /// Report an error to the registered hooks
+    pub(crate) fn error(&self, error: &(dyn Error + 'static)) {
+        for callback in &self.0.hooks.read().unwrap().on_error {
+            callback(error);
+        }
+    }
+
+    fn notify_disconnect(&self, reason: &DisconnectReason) {
+        for callback in &self.0.hooks.read().unwrap().on_disconnect {
+            callback(reason);
+        }
+    }
+}
+
+#[derive(Default)]
+struct Members {
+    connected: Vec<bool>,
+    on_connect: Option<Arc<ConnectCallback>>,
+    on_disconnect: Option<Arc<DisconnectCallback>>,
+}
+
+/// Connection hooks of a protocol made of several connections, invoked for
+/// the protocol as a whole: when its first member connects, and when its last
+/// connected member disconnects.
+#[derive(Clone)]
+pub(crate) struct AggregatedHooks(Arc<Mutex<Members>>);
+
+impl AggregatedHooks {
+    pub(crate) fn new(members: usize) -> Self {
+        Self(Arc::new(Mutex::new(Members {
+            connected: vec![false; members],
+            ..Members::default()
+        })))
+    }
+
+    pub(crate) fn on_connect(&self, callback: ConnectCallback) {
+        self.0.lock().unwrap().on_connect = Some(Arc::new(callback));
+    }
+
+    pub(crate) fn on_disconnect(&self, callback: DisconnectCallback) {
+        self.0.lock().unwrap().on_disconnect = Some(Arc::new(callback));
+    }
+
+    /// Report the member at `index` as connected
+    pub(crate) fn connected(&self, index: usize) {
+        let callback = {
+            let mut members = self.0.lock().unwrap();
+            let first = !members.connected.contains(&true);
+            members.connected[index] = true;
+            members.on_connect.clone().filter(|_| first)
+        };
+
+        if let Some(callback) = callback {
+            callback();
+        }
+    }
+
+    /// Report the member at `index` as disconnected
+    pub(crate) fn disconnected(&self, index: usize, reason: &DisconnectReason) {
+        let callback = {
+            let mut members = self.0.lock().unwrap();
+            let was_connected = std::mem::replace(&mut members.connected[index], false);
+            let last = was_connected && !members.connected.contains(&true);
+            members.on_disconnect.clone().filter(|_| last)
+        };
+
+        if let Some(callback) = callback {
+            callback(reason);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    #[test]
+    fn should_start_offline() {
+        assert_eq!(ConnectionState::new().get(), State::Offline);
+    }
+
+    #[test]
+    fn should_ignore_previous_connections() {
+        let state = ConnectionState::new();
+        let first = state.connected();
+        let second = state.connected();
+
+        assert!(!state.lost(first, DisconnectReason::ConnectionLost));
+        assert_eq!(state.get(), State::Connected);
+
+        assert!(state.lost(second, DisconnectReason::ConnectionLost));
+        assert_eq!(state.get(), State::Offline);
+    }
+
+    #[test]
+    fn should_notify_hooks() {
+        let state = ConnectionState::new();
+        let connections = Arc::new(AtomicUsize::new(0));
+        let reasons = Arc::new(Mutex::new(Vec::new()));
+        let errors = Arc::new(Mutex::new(Vec::new()));
+
+        let counter = connections.clone();
+        state.on_connect(Box::new(move || {
+            counter.fetch_add(1, Ordering::SeqCst);
+        }));
+        let received = reasons.clone();
+        state.on_disconnect(Box::new(move |reason: &DisconnectReason| {
+            received.lock().unwrap().push(reason.clone());
+        }));
+        let received = errors.clone();
+        state.on_error(Box::new(move |error: &(dyn Error + 'static)| {
+            received.lock().unwrap().push(error.to_string());
+        }));
+
+        let generation = state.connected();
+        state.lost(generation, DisconnectReason::HeartbeatTimeout);
+        // Already offline: must not be notified twice
+        state.lost(generation, DisconnectReason::ConnectionLost);
+        state.connected();
+        state.disconnected();
+        state.error(&std::fmt::Error);
+
+        assert_eq!(connections.load(Ordering::SeqCst), 2);
+        assert_eq!(
+            *reasons.lock().unwrap(),
+            vec![
+                DisconnectReason::HeartbeatTimeout,
+                DisconnectReason::Requested
+            ]
+        );
+        assert_eq!(errors.lock().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn should_aggregate_member_hooks() {
+        let hooks = AggregatedHooks::new(2);
+        let connections = Arc::new(AtomicUsize::new(0));
+        let reasons = Arc::new(Mutex::new(Vec::new()));
+
+        let counter = connections.clone();
+        hooks.on_connect(Box::new(move || {
+            counter.fetch_add(1, Ordering::SeqCst);
+        }));
+        let received = reasons.clone();
+        hooks.on_disconnect(Box::new(move |reason: &DisconnectReason| {
+            received.lock().unwrap().push(reason.clone());
+        }));
+
+        hooks.connected(0);
+        hooks.connected(1);
+        hooks.disconnected(0, &DisconnectReason::ConnectionLost);
+        hooks.connected(0);
+        // Never connected: must not be taken for the last member
+        hooks.disconnected(1, &DisconnectReason::Requested);
+        hooks.disconnected(1, &DisconnectReason::Requested);
+        hooks.disconnected(0, &DisconnectReason::Requested);
+
+        assert_eq!(connections.load(Ordering::SeqCst), 1);
+        assert_eq!(*reasons.lock().unwrap(), vec![DisconnectReason::Requested]);
+    }
+}
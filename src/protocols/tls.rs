@@ -0,0 +1,153 @@
+use crate::runtime::{Certificate, Identity, TlsConnector};
+use std::error::Error;
+
+const PEM_CERTIFICATE_END: &str = "-----END CERTIFICATE-----";
+
+/// TLS settings used by the protocols able to establish secured connections
+#[derive(Default, Clone)]
+pub struct TlsOptions {
+    pub root_certificates: Vec<Vec<u8>>,
+    pub client_certificate: Option<Vec<u8>>,
+    pub client_key: Option<Vec<u8>>,
+    pub danger_accept_invalid_certs: bool,
+    pub server_name: Option<String>,
+}
+
+impl TlsOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Trust every certificate of a PEM encoded CA bundle, on top of the
+    /// system trust store
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use kuzzle::protocols::TlsOptions;
+    ///
+    /// let bundle = std::fs::read("/etc/kuzzle/ca.pem").unwrap();
+    /// let tls = TlsOptions::new().root_certificates(&bundle);
+    /// ```
+    pub fn root_certificates(mut self, bundle: &[u8]) -> Self {
+        self.root_certificates.extend(split_pem_bundle(bundle));
+        self
+    }
+
+    /// Authenticate the client using a PEM encoded certificate chain and its
+    /// PKCS#8 private key (mTLS)
+    pub fn client_identity(mut self, certificate: &[u8], key: &[u8]) -> Self {
+        self.client_certificate = Some(certificate.to_vec());
+        self.client_key = Some(key.to_vec());
+        self
+    }
+
+    /// Accept any server certificate, including self-signed or expired ones.
+    ///
+    /// **Never enable this in production**: it makes the connection vulnerable
+    /// to man-in-the-middle attacks. It is only meant for local development stacks.
+    pub fn danger_accept_invalid_certs(mut self, accept: bool) -> Self {
+        self.danger_accept_invalid_certs = accept;
+        self
+    }
+
+    /// Present `name` for SNI and validate the server certificate against it,
+    /// instead of the host actually connected to. Useful to reach Kuzzle
+    /// through an IP address, a service mesh or an SSH tunnel.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use kuzzle::protocols::{TlsOptions, WebSocket, WebSocketOptions};
+    ///
+    /// let tls = TlsOptions::new().server_name("kuzzle.example.com");
+    /// let websocket = WebSocket::new("127.0.0.1", Some(WebSocketOptions::new().tls(tls)));
+    /// ```
+    pub fn server_name(mut self, name: &str) -> Self {
+        self.server_name = Some(name.into());
+        self
+    }
+
+    /// Name to present to a server reached through `host`
+    pub(crate) fn server_name_for<'a>(&'a self, host: &'a str) -> &'a str {
+        self.server_name.as_deref().unwrap_or(host)
+    }
+
+    /// Build the TLS connector matching these options
+    #[cfg(not(feature = "tokio"))]
+    pub(crate) fn connector(&self) -> Result<TlsConnector, Box<dyn Error>> {
+        let mut connector =
+            TlsConnector::new().danger_accept_invalid_certs(self.danger_accept_invalid_certs);
+
+        for pem in &self.root_certificates {
+            connector = connector.add_root_certificate(Certificate::from_pem(pem)?);
+        }
+
+        if let (Some(certificate), Some(key)) = (&self.client_certificate, &self.client_key) {
+            connector = connector.identity(Identity::from_pkcs8(certificate, key)?);
+        }
+
+        Ok(connector)
+    }
+
+    /// Build the TLS connector matching these options
+    #[cfg(feature = "tokio")]
+    pub(crate) fn connector(&self) -> Result<TlsConnector, Box<dyn Error>> {
+        let mut builder = tokio_native_tls::native_tls::TlsConnector::builder();
+        builder.danger_accept_invalid_certs(self.danger_accept_invalid_certs);
+
+        for pem in &self.root_certificates {
+            builder.add_root_certificate(Certificate::from_pem(pem)?);
+        }
+
+        if let (Some(certificate), Some(key)) = (&self.client_certificate, &self.client_key) {
+            builder.identity(Identity::from_pkcs8(certificate, key)?);
+        }
+
+        Ok(TlsConnector::from(builder.build()?))
+    }
+}
+
+/// Split a PEM bundle into one PEM block per certificate
+fn split_pem_bundle(bundle: &[u8]) -> Vec<Vec<u8>> {
+    String::from_utf8_lossy(bundle)
+        .split_inclusive(PEM_CERTIFICATE_END)
+        .filter(|block| block.contains(PEM_CERTIFICATE_END))
+        .map(|block| block.trim().as_bytes().to_vec())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_split_pem_bundle() {
+        let bundle = "-----BEGIN CERTIFICATE-----\nfoo\n-----END CERTIFICATE-----\n\
+                      -----BEGIN CERTIFICATE-----\nbar\n-----END CERTIFICATE-----\n";
+
+        let tls = TlsOptions::new().root_certificates(bundle.as_bytes());
+
+        assert_eq!(tls.root_certificates.len(), 2);
+        assert!(String::from_utf8_lossy(&tls.root_certificates[1]).contains("bar"));
+    }
+
+    #[test]
+    fn should_ignore_trailing_garbage() {
+        let tls = TlsOptions::new().root_certificates(b"not a certificate");
+        assert!(tls.root_certificates.is_empty());
+    }
+
+    #[test]
+    fn should_not_build_connector_with_invalid_certificate() {
+        let bundle = "-----BEGIN CERTIFICATE-----\nfoo\n-----END CERTIFICATE-----";
+        let tls = TlsOptions::new().root_certificates(bundle.as_bytes());
+
+        assert!(tls.connector().is_err());
+    }
+
+    #[test]
+    fn should_build_default_connector() {
+        assert!(TlsOptions::new().connector().is_ok());
+    }
+}
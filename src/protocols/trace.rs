@@ -0,0 +1,34 @@
+use crate::runtime::Instant;
+
+/// Whether a frame was written to, or read from, the connection
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Direction {
+    Outbound,
+    Inbound,
+}
+
+/// A payload as it went over the wire, e.g. to capture traces when
+/// investigating an incompatibility with a given Kuzzle version
+#[derive(Debug)]
+pub struct Frame<'a> {
+    pub direction: Direction,
+    /// Raw payload: JSON text, MessagePack data, or a whole HTTP or MQTT message
+    /// depending on the protocol
+    pub payload: &'a [u8],
+    /// When the frame was written or read
+    pub at: Instant,
+}
+
+/// Invoked with every frame written or read by a protocol
+///
+/// # Example
+///
+/// ```
+/// use kuzzle::protocols::{Frame, Protocol, WebSocket};
+///
+/// let mut protocol = WebSocket::new("localhost", None);
+/// protocol.on_frame(Box::new(|frame: &Frame| {
+///     eprintln!("{:?} {}", frame.direction, String::from_utf8_lossy(frame.payload));
+/// }));
+/// ```
+pub type FrameCallback = Box<dyn Fn(&Frame) + Send + Sync>;
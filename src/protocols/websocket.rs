@@ -1,21 +1,61 @@
-use async_std::io::Error as IoError;
-use async_std::io::ErrorKind as IoErrorKind;
 use async_trait::async_trait;
-use async_tungstenite::async_std::connect_async;
-use async_tungstenite::async_std::ConnectStream;
+use async_tungstenite::tungstenite::client::IntoClientRequest;
 use async_tungstenite::tungstenite::error::Error as WsErrors;
-use async_tungstenite::tungstenite::protocol::Message;
+use async_tungstenite::tungstenite::handshake::client::Request;
+use async_tungstenite::tungstenite::http::header::{HeaderName, HeaderValue, COOKIE, SET_COOKIE};
+use async_tungstenite::tungstenite::protocol::{Message, WebSocketConfig};
 use async_tungstenite::WebSocketStream;
+use futures_channel::oneshot;
+use futures_util::future::{select, Either};
+use futures_util::lock::Mutex;
 use futures_util::sink::SinkExt;
-use futures_util::stream::StreamExt;
+use futures_util::stream::{SplitSink, SplitStream, StreamExt};
+use serde_json::Value;
+use socket2::{SockRef, TcpKeepalive};
 use std::error::Error;
+use std::sync::{Arc, Mutex as StdMutex, Weak};
+use std::time::{Duration, Instant};
 use url::Url;
 
-use super::Protocol;
+use super::encoding::decode_binary;
+use super::hosts::{socket_host, url_host};
+use super::incoming::Subscribers;
+use super::metrics::MetricsRecorder;
+use super::pending::PendingRequests;
+use super::state::ConnectionState;
+#[cfg(feature = "wire-trace")]
+use super::FrameCallback;
+use super::{ConnectCallback, DisconnectCallback, DisconnectReason, ErrorCallback};
+use super::{CookieJar, ProxyOptions, ReconnectPolicy, State, TlsOptions};
+use super::{Encoding, HostSelection, Hosts, Incoming, Metrics, Protocol, ProtocolError};
+use crate::runtime::client_async_tls_with_connector_and_config;
+use crate::runtime::{self, ConnectStream, TcpStream};
+
+/// Close code reported when the close frame carries none (RFC 6455)
+const NO_STATUS_CODE: u16 = 1005;
+
+type WsSink = SplitSink<WebSocketStream<ConnectStream>, Message>;
+type WsStream = SplitStream<WebSocketStream<ConnectStream>>;
 
 pub struct WebSocketOptions {
     pub port: u16,
     pub ssl: bool,
+    pub ping_interval: Option<Duration>,
+    pub tls: Option<TlsOptions>,
+    pub proxy: Option<ProxyOptions>,
+    pub request_timeout: Option<Duration>,
+    pub headers: Vec<(String, String)>,
+    pub encoding: Encoding,
+    pub host_selection: HostSelection,
+    pub connect_timeout: Option<Duration>,
+    pub path: String,
+    pub max_in_flight: Option<usize>,
+    pub reconnect: Option<ReconnectPolicy>,
+    pub max_message_size: Option<usize>,
+    pub max_frame_size: Option<usize>,
+    pub tcp_nodelay: bool,
+    pub tcp_keepalive: Option<Duration>,
+    pub cookies: Option<CookieJar>,
 }
 
 impl Default for WebSocketOptions {
@@ -23,6 +63,22 @@ impl Default for WebSocketOptions {
         Self {
             port: 7512,
             ssl: false,
+            ping_interval: None,
+            tls: None,
+            proxy: None,
+            request_timeout: None,
+            headers: Vec::new(),
+            encoding: Encoding::Json,
+            host_selection: HostSelection::InOrder,
+            connect_timeout: None,
+            path: String::new(),
+            max_in_flight: None,
+            reconnect: None,
+            max_message_size: None,
+            max_frame_size: None,
+            tcp_nodelay: false,
+            tcp_keepalive: None,
+            cookies: None,
         }
     }
 }
@@ -41,12 +97,209 @@ impl WebSocketOptions {
         self.ssl = ssl;
         self
     }
+
+    /// Send a ping frame every `interval` once connected. If no pong came back
+    /// before the next ping is due, the connection is considered lost.
+    pub fn ping_interval(mut self, interval: Duration) -> Self {
+        self.ping_interval = Some(interval);
+        self
+    }
+
+    /// Use custom TLS settings (trusted CAs, client certificate). Implies `ssl(true)`.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use kuzzle::protocols::{TlsOptions, WebSocketOptions};
+    ///
+    /// let ca = std::fs::read("/etc/kuzzle/ca.pem").unwrap();
+    /// let options = WebSocketOptions::new().tls(TlsOptions::new().root_certificates(&ca));
+    /// ```
+    pub fn tls(mut self, tls: TlsOptions) -> Self {
+        self.ssl = true;
+        self.tls = Some(tls);
+        self
+    }
+
+    /// Accept self-signed or otherwise invalid server certificates. Implies `ssl(true)`.
+    /// See [`TlsOptions::danger_accept_invalid_certs`].
+    pub fn danger_accept_invalid_certs(mut self, accept: bool) -> Self {
+        let tls = self.tls.take().unwrap_or_default();
+        self.tls(tls.danger_accept_invalid_certs(accept))
+    }
+
+    /// Reach Kuzzle through an HTTP (`CONNECT`) or SOCKS5 proxy
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use kuzzle::protocols::{ProxyOptions, WebSocketOptions};
+    ///
+    /// let proxy = ProxyOptions::new("http://proxy.corp:3128").unwrap();
+    /// let options = WebSocketOptions::new().proxy(proxy);
+    /// ```
+    pub fn proxy(mut self, proxy: ProxyOptions) -> Self {
+        self.proxy = Some(proxy);
+        self
+    }
+
+    /// Default time to wait for a response before failing with
+    /// `ProtocolError::Timeout`. Requests wait forever when unset.
+    pub fn request_timeout(mut self, timeout: Duration) -> Self {
+        self.request_timeout = Some(timeout);
+        self
+    }
+
+    /// Add an HTTP header to the WebSocket upgrade request, e.g. to authenticate
+    /// against a reverse proxy. Can be called several times for the same name.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use kuzzle::protocols::WebSocketOptions;
+    ///
+    /// let options = WebSocketOptions::new()
+    ///     .header("Authorization", "Bearer my-gateway-token")
+    ///     .header("Cookie", "session=42");
+    /// ```
+    pub fn header(mut self, name: &str, value: &str) -> Self {
+        self.headers.push((name.into(), value.into()));
+        self
+    }
+
+    /// Encoding of the requests: binary encodings are sent as binary frames.
+    /// Binary responses are decoded transparently whatever this setting.
+    pub fn encoding(mut self, encoding: Encoding) -> Self {
+        self.encoding = encoding;
+        self
+    }
+
+    /// Order in which the hosts are tried when several of them are given
+    pub fn host_selection(mut self, selection: HostSelection) -> Self {
+        self.host_selection = selection;
+        self
+    }
+
+    /// Maximum time to establish a connection to a host, handshakes included,
+    /// before failing with `ProtocolError::ConnectTimeout` and trying the next
+    /// host. Relies on the OS TCP timeout when unset.
+    pub fn connect_timeout(mut self, timeout: Duration) -> Self {
+        self.connect_timeout = Some(timeout);
+        self
+    }
+
+    /// Path Kuzzle is exposed under, for deployments behind a reverse proxy
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use kuzzle::protocols::{WebSocket, WebSocketOptions};
+    ///
+    /// let options = WebSocketOptions::new().port(443).ssl(true).path("kuzzle/");
+    /// let websocket = WebSocket::new("example.com", Some(options));
+    /// assert_eq!("wss://example.com:443/kuzzle/", &websocket.get_url());
+    /// ```
+    pub fn path(mut self, path: &str) -> Self {
+        self.path = match path {
+            "" => String::new(),
+            path if path.starts_with('/') => path.into(),
+            path => format!("/{}", path),
+        };
+        self
+    }
+
+    /// Maximum number of requests awaiting a response at once. Requests
+    /// beyond it fail with `ProtocolError::TooManyRequests`.
+    pub fn max_in_flight(mut self, limit: usize) -> Self {
+        self.max_in_flight = Some(limit);
+        self
+    }
+
+    /// Re-establish lost connections following the given policy. Lost
+    /// connections are not re-established when unset.
+    pub fn reconnect(mut self, policy: ReconnectPolicy) -> Self {
+        self.reconnect = Some(policy);
+        self
+    }
+
+    /// Maximum size of an incoming message, e.g. to receive large `mGet`
+    /// responses. Defaults to 64 MiB.
+    pub fn max_message_size(mut self, size: usize) -> Self {
+        self.max_message_size = Some(size);
+        self
+    }
+
+    /// Maximum size of a single incoming frame. Defaults to 16 MiB.
+    pub fn max_frame_size(mut self, size: usize) -> Self {
+        self.max_frame_size = Some(size);
+        self
+    }
+
+    /// Disable Nagle's algorithm, so that small frames are sent right away
+    /// instead of being buffered
+    pub fn tcp_nodelay(mut self, nodelay: bool) -> Self {
+        self.tcp_nodelay = nodelay;
+        self
+    }
+
+    /// Enable TCP keep-alive, probing the connection after it has been idle
+    /// for `idle`, so that dead peers are detected on long-lived connections
+    pub fn tcp_keepalive(mut self, idle: Duration) -> Self {
+        self.tcp_keepalive = Some(idle);
+        self
+    }
+
+    /// Keep the cookies set on the handshake responses in `jar`, and send
+    /// them back on every new connection, e.g. to stick to the same node
+    /// behind a load balancer
+    pub fn cookies(mut self, jar: CookieJar) -> Self {
+        self.cookies = Some(jar);
+        self
+    }
+
+    fn websocket_config(&self) -> WebSocketConfig {
+        let default = WebSocketConfig::default();
+
+        WebSocketConfig {
+            max_message_size: self.max_message_size.or(default.max_message_size),
+            max_frame_size: self.max_frame_size.or(default.max_frame_size),
+            ..default
+        }
+    }
 }
 
+/// Clones share the same connection
+#[derive(Clone)]
 pub struct WebSocket {
-    host: String,
+    shared: Arc<Shared>,
+}
+
+/// Everything the background tasks need to serve the connection and
+/// re-establish it when lost
+struct Shared {
+    hosts: Hosts,
     options: WebSocketOptions,
-    stream: Option<WebSocketStream<ConnectStream>>,
+    current: StdMutex<Option<usize>>,
+    link: StdMutex<Option<Link>>,
+    state: ConnectionState,
+    subscribers: Subscribers,
+    metrics: MetricsRecorder,
+}
+
+/// Writing half of the current connection and the requests sent over it
+#[derive(Clone)]
+struct Link {
+    sink: Arc<Mutex<WsSink>>,
+    pending: PendingRequests,
+}
+
+/// Reading half of an established connection
+struct Connection {
+    stream: WsStream,
+    pending: PendingRequests,
+    last_pong: Arc<StdMutex<Instant>>,
+    heartbeat: Option<oneshot::Receiver<()>>,
+    generation: u64,
 }
 
 impl WebSocket {
@@ -68,16 +321,26 @@ impl WebSocket {
     ///     .ssl(true);
     ///
     /// let customized_ws = WebSocket::new("localhost", Some(options));
+    ///
+    /// // Several nodes can be given: they are tried in turn on connection
+    /// let cluster_ws = WebSocket::new(&["node1", "node2", "node3"], None);
     /// ```
-    pub fn new(host: &str, options: Option<WebSocketOptions>) -> WebSocket {
+    pub fn new<H: Into<Hosts>>(hosts: H, options: Option<WebSocketOptions>) -> WebSocket {
         WebSocket {
-            host: host.into(),
-            options: options.unwrap_or_default(),
-            stream: None,
+            shared: Arc::new(Shared {
+                hosts: hosts.into(),
+                options: options.unwrap_or_default(),
+                current: StdMutex::new(None),
+                link: StdMutex::new(None),
+                state: ConnectionState::new(),
+                subscribers: Subscribers::default(),
+                metrics: MetricsRecorder::default(),
+            }),
         }
     }
 
-    /// Create and return a valid WebSocket URL using provided host and WebSocketOptions
+    /// Create and return a valid WebSocket URL using provided host and WebSocketOptions.
+    /// With several hosts, the URL is the one of the host last connected to.
     ///
     /// # Example
     ///
@@ -93,48 +356,471 @@ impl WebSocket {
     /// assert_eq!("wss://localhost:7512", &websocket_ssl.get_url());
     /// ```
     pub fn get_url(&self) -> String {
-        match &self.options.ssl {
-            true => format!("wss://{}:{}", self.host, self.options.port),
-            false => format!("ws://{}:{}", self.host, self.options.port),
+        let current = self.shared.current.lock().unwrap().unwrap_or(0);
+        self.shared
+            .url(self.shared.hosts.get(current).unwrap_or_default())
+    }
+
+    async fn send_request(&self, request: Value) -> Result<String, Box<dyn Error>> {
+        let link = self.shared.link().ok_or(WsErrors::ConnectionClosed)?;
+        let pending = link.pending.register(&request)?;
+        let message = if self.shared.options.encoding.is_binary() {
+            let payload = self.shared.options.encoding.encode(&request)?;
+            self.shared.metrics.sent(&payload);
+            Message::Binary(payload)
+        } else {
+            let text = request.to_string();
+            self.shared.metrics.sent(text.as_bytes());
+            Message::Text(text)
+        };
+
+        link.sink.lock().await.send(message).await?;
+        pending.response().await
+    }
+}
+
+impl Shared {
+    fn link(&self) -> Option<Link> {
+        self.link.lock().unwrap().clone()
+    }
+
+    fn url(&self, host: &str) -> String {
+        let scheme = match &self.options.ssl {
+            true => "wss",
+            false => "ws",
+        };
+
+        format!(
+            "{}://{}:{}{}",
+            scheme,
+            url_host(host),
+            self.options.port,
+            self.options.path
+        )
+    }
+
+    /// Connect to the first available host, reporting every failed attempt
+    async fn open_any(&self) -> Result<WebSocketStream<ConnectStream>, Box<dyn Error>> {
+        let current = *self.current.lock().unwrap();
+        let attempts: Vec<usize> = self
+            .hosts
+            .attempts(self.options.host_selection, current)
+            .collect();
+
+        for (attempt, &index) in attempts.iter().enumerate() {
+            let host = self.hosts.get(index).unwrap_or_default();
+
+            match self.open_with_timeout(host).await {
+                Ok(ws_stream) => {
+                    *self.current.lock().unwrap() = Some(index);
+                    return Ok(ws_stream);
+                }
+                Err(error) => {
+                    self.metrics.connection_failed();
+                    self.state.error(&*error);
+                    if attempt + 1 == attempts.len() {
+                        return Err(error);
+                    }
+                }
+            }
+        }
+
+        Err(Box::new(ProtocolError::NoHost))
+    }
+
+    async fn open_with_timeout(
+        &self,
+        host: &str,
+    ) -> Result<WebSocketStream<ConnectStream>, Box<dyn Error>> {
+        match self.options.connect_timeout {
+            Some(timeout) => match runtime::timeout(timeout, self.open(host)).await {
+                Some(result) => result,
+                None => Err(Box::new(ProtocolError::ConnectTimeout(timeout))),
+            },
+            None => self.open(host).await,
+        }
+    }
+
+    async fn open(&self, host: &str) -> Result<WebSocketStream<ConnectStream>, Box<dyn Error>> {
+        let request = self.handshake_request(host)?;
+        let connector = match &self.options.tls {
+            Some(tls) => Some(tls.connector()?),
+            None => None,
+        };
+        let stream = match &self.options.proxy {
+            Some(proxy) => proxy.connect(host, self.options.port).await?,
+            None => TcpStream::connect((socket_host(host), self.options.port)).await?,
+        };
+        self.tune(&stream)?;
+
+        let config = Some(self.options.websocket_config());
+        let (ws_stream, response) =
+            client_async_tls_with_connector_and_config(request, stream, connector, config).await?;
+
+        if let Some(jar) = &self.options.cookies {
+            for cookie in response.headers().get_all(SET_COOKIE) {
+                if let Ok(cookie) = cookie.to_str() {
+                    jar.store(cookie);
+                }
+            }
+        }
+
+        Ok(ws_stream)
+    }
+
+    /// Apply the socket-level options to a freshly opened TCP stream
+    fn tune(&self, stream: &TcpStream) -> Result<(), Box<dyn Error>> {
+        let socket = SockRef::from(stream);
+        socket.set_nodelay(self.options.tcp_nodelay)?;
+
+        if let Some(idle) = self.options.tcp_keepalive {
+            socket.set_tcp_keepalive(&TcpKeepalive::new().with_time(idle))?;
+        }
+
+        Ok(())
+    }
+
+    /// Build the upgrade request, including the custom headers
+    fn handshake_request(&self, host: &str) -> Result<Request, Box<dyn Error>> {
+        // The TLS server name is taken from the URL, the TCP connection being
+        // opened to `host` anyway
+        let name = match &self.options.tls {
+            Some(tls) => tls.server_name_for(host),
+            None => host,
+        };
+        let mut request = Url::parse(&self.url(name))?.into_client_request()?;
+
+        for (name, value) in &self.options.headers {
+            request.headers_mut().append(
+                HeaderName::from_bytes(name.as_bytes())?,
+                HeaderValue::from_str(value)?,
+            );
+        }
+
+        // Merged with the cookies given as headers, a single `Cookie` header
+        // being allowed
+        if let Some(jar) = self.options.cookies.as_ref().and_then(CookieJar::header) {
+            let mut cookies: Vec<&str> = request
+                .headers()
+                .get_all(COOKIE)
+                .iter()
+                .filter_map(|value| value.to_str().ok())
+                .collect();
+            cookies.push(&jar);
+            let cookies = HeaderValue::from_str(&cookies.join("; "))?;
+            request.headers_mut().insert(COOKIE, cookies);
+        }
+
+        Ok(request)
+    }
+
+    /// Make an opened stream the current connection and start its heartbeat
+    fn install(&self, ws_stream: WebSocketStream<ConnectStream>) -> Connection {
+        let (sink, stream) = ws_stream.split();
+        let sink = Arc::new(Mutex::new(sink));
+        let pending = PendingRequests::new(self.options.max_in_flight);
+        let last_pong = Arc::new(StdMutex::new(Instant::now()));
+
+        let heartbeat = self.options.ping_interval.map(|interval| {
+            let (timed_out, on_timeout) = oneshot::channel();
+            runtime::spawn(heartbeat(
+                Arc::downgrade(&sink),
+                last_pong.clone(),
+                interval,
+                timed_out,
+            ));
+            on_timeout
+        });
+
+        *self.link.lock().unwrap() = Some(Link {
+            sink,
+            pending: pending.clone(),
+        });
+        let generation = self.state.connected();
+        self.metrics.connected();
+
+        Connection {
+            stream,
+            pending,
+            last_pong,
+            heartbeat,
+            generation,
+        }
+    }
+
+    /// Try to re-establish a lost connection following the reconnection
+    /// policy. Gives up when the retry budget is exhausted, or as soon as the
+    /// protocol is disconnected on purpose.
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip_all))]
+    async fn reconnect(&self, policy: &ReconnectPolicy) -> Option<Connection> {
+        let mut attempts = 0;
+
+        while policy.allows(attempts) {
+            runtime::sleep(policy.delay(attempts)).await;
+
+            if self.state.get() != State::Reconnecting {
+                return None;
+            }
+
+            #[cfg(feature = "tracing")]
+            tracing::info!(attempt = attempts + 1, "reconnecting");
+            let opened = self.open_any().await.ok();
+            match opened {
+                Some(ws_stream) if self.state.get() == State::Reconnecting => {
+                    #[cfg(feature = "tracing")]
+                    tracing::info!(attempts = attempts + 1, "reconnected");
+                    return Some(self.install(ws_stream));
+                }
+                Some(_) => return None,
+                None => attempts += 1,
+            }
+        }
+
+        #[cfg(feature = "tracing")]
+        tracing::warn!(attempts, "gave up reconnecting");
+        self.state.set(State::Offline);
+        self.state.error(&ProtocolError::ReconnectFailed(attempts));
+        None
+    }
+}
+
+/// Serve a connection until it is closed on purpose, re-establishing it
+/// whenever it is lost if a reconnection policy is set.
+async fn drive(shared: Arc<Shared>, mut connection: Connection) {
+    loop {
+        let generation = connection.generation;
+        let pending = connection.pending.clone();
+        let reason = read_frames(&shared, connection).await;
+
+        pending.close();
+        if !shared.state.lost(generation, reason) {
+            return;
+        }
+
+        let policy = match &shared.options.reconnect {
+            Some(policy) => policy,
+            None => return,
+        };
+
+        shared.link.lock().unwrap().take();
+        shared.state.set(State::Reconnecting);
+
+        connection = match shared.reconnect(policy).await {
+            Some(connection) => connection,
+            None => return,
+        };
+    }
+}
+
+/// What woke the reading loop up
+enum ReadEvent {
+    Frame(Option<Result<Message, WsErrors>>),
+    HeartbeatTimeout,
+    HeartbeatStopped,
+}
+
+/// Continuously read the connection, independently of the requests being
+/// sent: route every response to the request waiting for it and anything else,
+/// such as notifications, to the incoming streams. Keep track of the last
+/// pong received. Returns once the connection is closed or stopped answering
+/// pings.
+async fn read_frames(shared: &Shared, connection: Connection) -> DisconnectReason {
+    let Connection {
+        mut stream,
+        pending,
+        last_pong,
+        mut heartbeat,
+        ..
+    } = connection;
+    let mut closed = None;
+
+    loop {
+        let event = match heartbeat.as_mut() {
+            Some(timed_out) => match select(stream.next(), timed_out).await {
+                Either::Left((frame, _)) => ReadEvent::Frame(frame),
+                Either::Right((Ok(()), _)) => ReadEvent::HeartbeatTimeout,
+                Either::Right((Err(_), _)) => ReadEvent::HeartbeatStopped,
+            },
+            None => ReadEvent::Frame(stream.next().await),
+        };
+
+        let message = match event {
+            ReadEvent::Frame(Some(Ok(message))) => message,
+            ReadEvent::Frame(Some(Err(error))) => {
+                shared.state.error(&error);
+                return DisconnectReason::ConnectionLost;
+            }
+            ReadEvent::Frame(None) => return closed.unwrap_or(DisconnectReason::ConnectionLost),
+            ReadEvent::HeartbeatTimeout => return DisconnectReason::HeartbeatTimeout,
+            ReadEvent::HeartbeatStopped => {
+                heartbeat = None;
+                continue;
+            }
+        };
+
+        match message {
+            Message::Pong(_) => *last_pong.lock().unwrap() = Instant::now(),
+            Message::Text(text) => {
+                shared.metrics.received(text.as_bytes());
+                if let Some(text) = pending.dispatch(text) {
+                    shared.subscribers.publish(text);
+                }
+            }
+            Message::Binary(payload) => {
+                shared.metrics.received(&payload);
+                if let Some(text) = decode_binary(payload)
+                    .ok()
+                    .and_then(|t| pending.dispatch(t))
+                {
+                    shared.subscribers.publish(text);
+                }
+            }
+            // Keep reading until the closing handshake completes
+            Message::Close(frame) => {
+                closed = Some(match frame {
+                    Some(frame) => DisconnectReason::ClosedByServer {
+                        code: frame.code.into(),
+                        reason: frame.reason.into_owned(),
+                    },
+                    None => DisconnectReason::ClosedByServer {
+                        code: NO_STATUS_CODE,
+                        reason: String::new(),
+                    },
+                });
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Ping the server every `interval`. When a pong is missing, the reading loop
+/// is told the connection is lost and the sink is closed. Stops as soon as
+/// the connection is closed.
+async fn heartbeat(
+    sink: Weak<Mutex<WsSink>>,
+    last_pong: Arc<StdMutex<Instant>>,
+    interval: Duration,
+    timed_out: oneshot::Sender<()>,
+) {
+    loop {
+        let ping_sent_at = Instant::now();
+
+        let s = match sink.upgrade() {
+            Some(s) => s,
+            None => break,
+        };
+        let sent = s.lock().await.send(Message::Ping(Vec::new())).await;
+        drop(s);
+
+        if sent.is_err() {
+            break;
+        }
+
+        runtime::sleep(interval).await;
+
+        let last = *last_pong.lock().unwrap();
+        if last < ping_sent_at {
+            let _ = timed_out.send(());
+            if let Some(s) = sink.upgrade() {
+                let _ = s.lock().await.close().await;
+            }
+            break;
         }
     }
 }
 
 #[async_trait]
 impl Protocol for WebSocket {
-    async fn connect(&mut self) -> Result<(), Box<dyn Error>> {
-        let url = Url::parse(&self.get_url())?;
-        let (ws_stream, _) = connect_async(url).await?;
+    async fn connect(&self) -> Result<(), Box<dyn Error>> {
+        self.shared.state.set(State::Connecting);
 
-        self.stream = Some(ws_stream);
+        let ws_stream = match self.shared.open_any().await {
+            Ok(ws_stream) => ws_stream,
+            Err(error) => {
+                self.shared.state.set(State::Offline);
+                return Err(error);
+            }
+        };
+        let connection = self.shared.install(ws_stream);
+
+        runtime::spawn(drive(self.shared.clone(), connection));
         Ok(())
     }
 
-    async fn disconnect(&mut self) -> Result<(), Box<dyn Error>> {
-        match self.stream.as_mut() {
-            Some(s) => {
-                s.close(None).await?;
-                self.stream = None;
+    async fn disconnect(&self) -> Result<(), Box<dyn Error>> {
+        let link = self.shared.link.lock().unwrap().take();
+
+        match link {
+            Some(link) => {
+                link.pending.close();
+                self.shared.state.disconnected();
+                link.sink.lock().await.close().await?;
+                Ok(())
+            }
+            None if self.shared.state.get() == State::Reconnecting => {
+                self.shared.state.disconnected();
                 Ok(())
             }
             None => Err(Box::new(WsErrors::AlreadyClosed)),
         }
     }
 
-    async fn send(&mut self, request: String) -> Result<String, Box<dyn Error>> {
-        match self.stream.as_mut() {
-            Some(s) => {
-                s.send(Message::Text(request)).await?;
-                let res = s.next().await.ok_or_else(|| {
-                    Box::new(IoError::new(
-                        IoErrorKind::UnexpectedEof,
-                        "No response from server",
-                    ))
-                })??;
-                Ok(res.into_text()?)
-            }
-            None => Err(Box::new(WsErrors::ConnectionClosed)),
+    async fn disconnect_graceful(&self, timeout: Duration) -> Result<(), Box<dyn Error>> {
+        if let Some(link) = self.shared.link() {
+            link.pending.drain();
+            runtime::timeout(timeout, link.pending.settled()).await;
         }
+        self.disconnect().await
+    }
+
+    fn state(&self) -> State {
+        self.shared.state.get()
+    }
+
+    fn on_connect(&mut self, callback: ConnectCallback) {
+        self.shared.state.on_connect(callback);
+    }
+
+    fn on_disconnect(&mut self, callback: DisconnectCallback) {
+        self.shared.state.on_disconnect(callback);
+    }
+
+    fn on_error(&mut self, callback: ErrorCallback) {
+        self.shared.state.on_error(callback);
+    }
+
+    #[cfg(feature = "wire-trace")]
+    fn on_frame(&mut self, callback: FrameCallback) {
+        self.shared.metrics.on_frame(callback);
+    }
+
+    fn incoming(&self) -> Incoming {
+        self.shared.subscribers.subscribe()
+    }
+
+    fn metrics(&self) -> Metrics {
+        self.shared.metrics.snapshot()
+    }
+
+    async fn send(&self, request: Value) -> Result<String, Box<dyn Error>> {
+        self.shared
+            .metrics
+            .track(
+                self.send_request(request),
+                self.shared.options.request_timeout,
+            )
+            .await
+    }
+
+    async fn send_with_timeout(
+        &self,
+        request: Value,
+        timeout: Duration,
+    ) -> Result<String, Box<dyn Error>> {
+        self.shared
+            .metrics
+            .track(self.send_request(request), Some(timeout))
+            .await
     }
 }
 
@@ -156,23 +842,285 @@ mod tests {
         assert_eq!(ws.get_url(), "wss://localhost:7512");
     }
 
+    #[test]
+    fn should_forge_ws_url_with_path() {
+        let ws = WebSocket::new("localhost", Some(WebSocketOptions::new().path("/kuzzle")));
+        assert_eq!(ws.get_url(), "ws://localhost:7512/kuzzle");
+
+        let ws = WebSocket::new("localhost", Some(WebSocketOptions::new().path("kuzzle/")));
+        assert_eq!(ws.get_url(), "ws://localhost:7512/kuzzle/");
+    }
+
+    #[test]
+    fn should_forge_ws_url_with_ipv6_host() {
+        let ws = WebSocket::new("::1", None);
+        assert_eq!(ws.get_url(), "ws://[::1]:7512");
+
+        let ws = WebSocket::new("[::1]", None);
+        assert_eq!(ws.get_url(), "ws://[::1]:7512");
+    }
+
+    #[test]
+    fn should_not_build_handshake_with_invalid_host() {
+        let ws = WebSocket::new("fe80::zz", None);
+        assert!(ws.shared.handshake_request("fe80::zz").is_err());
+    }
+
     #[async_std::test]
     async fn should_not_connect_with_bad_url() {
-        let mut ws = WebSocket::new("localhost42", None);
+        let ws = WebSocket::new("localhost42", None);
         let result = ws.connect().await;
         assert!(result.is_err());
+        assert_eq!(ws.state(), State::Offline);
+    }
+
+    #[async_std::test]
+    async fn should_not_connect_after_timeout() -> Result<(), Box<dyn Error>> {
+        // Accepts TCP connections but never answers the WebSocket handshake
+        let listener = async_std::net::TcpListener::bind("127.0.0.1:0").await?;
+        let port = listener.local_addr()?.port();
+        let timeout = Duration::from_millis(100);
+
+        let ws = WebSocket::new(
+            "127.0.0.1",
+            Some(WebSocketOptions::new().port(port).connect_timeout(timeout)),
+        );
+        let err = ws.connect().await.err().unwrap();
+
+        assert_eq!(
+            err.downcast_ref::<ProtocolError>(),
+            Some(&ProtocolError::ConnectTimeout(timeout))
+        );
+        assert_eq!(ws.state(), State::Offline);
+
+        Ok(())
     }
 
     #[async_std::test]
     async fn should_disconnect() -> Result<(), Box<dyn Error>> {
         let (_, port) = MockServer::default().start().await?;
 
+        let ws = WebSocket::new("localhost", Some(WebSocketOptions::new().port(port)));
+        assert_eq!(ws.state(), State::Offline);
+        ws.connect().await?;
+
+        assert!(ws.shared.link().is_some());
+        assert_eq!(ws.state(), State::Connected);
+
+        ws.disconnect().await?;
+        assert_eq!(ws.state(), State::Offline);
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn should_disconnect_gracefully() -> Result<(), Box<dyn Error>> {
+        let (_, port) = MockServer::default()
+            .responses(vec![json!({"requestId": "foo"})])
+            .start()
+            .await?;
+
+        let ws = WebSocket::new("localhost", Some(WebSocketOptions::new().port(port)));
+        ws.connect().await?;
+        ws.send(json!({"requestId": "foo"})).await?;
+
+        ws.disconnect_graceful(Duration::from_secs(1)).await?;
+        assert_eq!(ws.state(), State::Offline);
+        assert!(ws.send(json!({"requestId": "bar"})).await.is_err());
+
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn should_notify_lifecycle_hooks() -> Result<(), Box<dyn Error>> {
+        let (_, port) = MockServer::default().start().await?;
+        let events = Arc::new(StdMutex::new(Vec::new()));
+
         let mut ws = WebSocket::new("localhost", Some(WebSocketOptions::new().port(port)));
+        let received = events.clone();
+        ws.on_connect(Box::new(move || {
+            received.lock().unwrap().push("connect".to_string())
+        }));
+        let received = events.clone();
+        ws.on_disconnect(Box::new(move |reason: &DisconnectReason| {
+            received.lock().unwrap().push(format!("{:?}", reason))
+        }));
+
+        ws.connect().await?;
+        ws.disconnect().await?;
+
+        assert_eq!(*events.lock().unwrap(), vec!["connect", "Requested"]);
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn should_report_close_code() -> Result<(), Box<dyn Error>> {
+        use async_tungstenite::tungstenite::protocol::frame::coding::CloseCode;
+        use async_tungstenite::tungstenite::protocol::CloseFrame;
+
+        let listener = async_std::net::TcpListener::bind("127.0.0.1:0").await?;
+        let port = listener.local_addr()?.port();
+
+        async_std::task::spawn(async move {
+            let (socket, _) = listener.accept().await.unwrap();
+            let mut ws = async_tungstenite::accept_async(socket).await.unwrap();
+            ws.close(Some(CloseFrame {
+                code: CloseCode::Away,
+                reason: "restarting".into(),
+            }))
+            .await
+            .unwrap();
+            while ws.next().await.is_some() {}
+        });
+
+        let reasons = Arc::new(StdMutex::new(Vec::new()));
+        let mut ws = WebSocket::new("127.0.0.1", Some(WebSocketOptions::new().port(port)));
+        let received = reasons.clone();
+        ws.on_disconnect(Box::new(move |reason: &DisconnectReason| {
+            received.lock().unwrap().push(reason.clone())
+        }));
+        ws.connect().await?;
+
+        while ws.state() == State::Connected {
+            async_std::task::sleep(Duration::from_millis(10)).await;
+        }
+
+        assert_eq!(
+            *reasons.lock().unwrap(),
+            vec![DisconnectReason::ClosedByServer {
+                code: 1001,
+                reason: "restarting".to_string(),
+            }]
+        );
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn should_notify_connection_errors() {
+        let errors = Arc::new(StdMutex::new(0));
+
+        let mut ws = WebSocket::new("localhost42", None);
+        let received = errors.clone();
+        ws.on_error(Box::new(move |_: &(dyn Error + 'static)| {
+            *received.lock().unwrap() += 1
+        }));
+
+        assert!(ws.connect().await.is_err());
+        assert_eq!(*errors.lock().unwrap(), 1);
+    }
+
+    #[async_std::test]
+    async fn should_fail_over_to_next_host() -> Result<(), Box<dyn Error>> {
+        let (_, port) = MockServer::default().start().await?;
+        let errors = Arc::new(StdMutex::new(0));
+
+        let mut ws = WebSocket::new(
+            &["localhost42", "localhost"],
+            Some(WebSocketOptions::new().port(port)),
+        );
+        let received = errors.clone();
+        ws.on_error(Box::new(move |_: &(dyn Error + 'static)| {
+            *received.lock().unwrap() += 1
+        }));
+
+        ws.connect().await?;
+
+        assert_eq!(ws.get_url(), format!("ws://localhost:{}", port));
+        assert_eq!(*errors.lock().unwrap(), 1);
+
+        ws.disconnect().await?;
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn should_not_connect_without_host() {
+        let ws = WebSocket::new(Vec::<String>::new(), None);
+        let err = ws.connect().await.err().unwrap();
+
+        assert_eq!(
+            err.downcast_ref::<ProtocolError>(),
+            Some(&ProtocolError::NoHost)
+        );
+        assert_eq!(ws.state(), State::Offline);
+    }
+
+    #[async_std::test]
+    async fn should_reconnect_then_give_up() -> Result<(), Box<dyn Error>> {
+        // Accepts two connections and closes them right away, then goes away
+        let listener = async_std::net::TcpListener::bind("127.0.0.1:0").await?;
+        let port = listener.local_addr()?.port();
+        async_std::task::spawn(async move {
+            for _ in 0..2 {
+                let (socket, _) = listener.accept().await.unwrap();
+                drop(async_tungstenite::accept_async(socket).await.unwrap());
+            }
+        });
+
+        let events = Arc::new(StdMutex::new(Vec::new()));
+        let policy = ReconnectPolicy::new()
+            .initial_delay(Duration::from_millis(10))
+            .max_retries(2);
+        let mut ws = WebSocket::new(
+            "127.0.0.1",
+            Some(WebSocketOptions::new().port(port).reconnect(policy)),
+        );
+        let received = events.clone();
+        ws.on_connect(Box::new(move || {
+            received.lock().unwrap().push("connect".to_string())
+        }));
+        let received = events.clone();
+        ws.on_error(Box::new(move |error: &(dyn Error + 'static)| {
+            if let Some(error) = error.downcast_ref::<ProtocolError>() {
+                received.lock().unwrap().push(error.to_string());
+            }
+        }));
+
+        ws.connect().await?;
+
+        let gave_up = ProtocolError::ReconnectFailed(2).to_string();
+        for _ in 0..100 {
+            if events.lock().unwrap().contains(&gave_up) {
+                break;
+            }
+            runtime::sleep(Duration::from_millis(50)).await;
+        }
+
+        assert_eq!(
+            *events.lock().unwrap(),
+            vec!["connect", "connect", gave_up.as_str()]
+        );
+        assert_eq!(ws.state(), State::Offline);
+        assert_eq!(ws.metrics().connections, 2);
+
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn should_stop_reconnecting_once_disconnected() -> Result<(), Box<dyn Error>> {
+        let listener = async_std::net::TcpListener::bind("127.0.0.1:0").await?;
+        let port = listener.local_addr()?.port();
+        async_std::task::spawn(async move {
+            let (socket, _) = listener.accept().await.unwrap();
+            drop(async_tungstenite::accept_async(socket).await.unwrap());
+        });
+
+        let policy = ReconnectPolicy::new().initial_delay(Duration::from_secs(60));
+        let ws = WebSocket::new(
+            "127.0.0.1",
+            Some(WebSocketOptions::new().port(port).reconnect(policy)),
+        );
         ws.connect().await?;
 
-        assert!(ws.stream.is_some());
+        for _ in 0..100 {
+            if ws.state() == State::Reconnecting {
+                break;
+            }
+            runtime::sleep(Duration::from_millis(10)).await;
+        }
+        assert_eq!(ws.state(), State::Reconnecting);
 
         ws.disconnect().await?;
+        assert_eq!(ws.state(), State::Offline);
+
         Ok(())
     }
 
@@ -180,10 +1128,10 @@ mod tests {
     async fn should_not_disconnect_twice() -> Result<(), Box<dyn Error>> {
         let (_, port) = surimi::MockServer::default().start().await?;
 
-        let mut ws = WebSocket::new("localhost", Some(WebSocketOptions::new().port(port)));
+        let ws = WebSocket::new("localhost", Some(WebSocketOptions::new().port(port)));
         ws.connect().await?;
 
-        assert!(ws.stream.is_some());
+        assert!(ws.shared.link().is_some());
 
         ws.disconnect().await?;
         ws.disconnect().await.err().unwrap();
@@ -194,15 +1142,27 @@ mod tests {
     #[async_std::test]
     async fn should_send_request() -> Result<(), Box<dyn Error>> {
         let (_, port) = surimi::MockServer::default()
-            .responses(vec![json!({"hello": "world"})])
+            .responses(vec![json!({"requestId": "foo", "hello": "world"})])
             .start()
             .await?;
 
-        let mut ws = WebSocket::new("localhost", Some(WebSocketOptions::new().port(port)));
+        let ws = WebSocket::new("localhost", Some(WebSocketOptions::new().port(port)));
         ws.connect().await?;
 
-        let raw = ws.send("Some request".into()).await?;
-        assert_eq!(raw, json!({"hello": "world"}).to_string());
+        let raw = ws.send(json!({"requestId": "foo"})).await?;
+        assert_eq!(
+            raw,
+            json!({"requestId": "foo", "hello": "world"}).to_string()
+        );
+
+        let metrics = ws.metrics();
+        assert_eq!(metrics.requests, 1);
+        assert_eq!(metrics.connections, 1);
+        assert_eq!(
+            metrics.bytes_sent as usize,
+            json!({"requestId": "foo"}).to_string().len()
+        );
+        assert!(metrics.bytes_received as usize >= raw.len());
 
         ws.disconnect().await?;
         Ok(())
@@ -212,25 +1172,224 @@ mod tests {
     async fn should_able_to_send_multiple_request() -> Result<(), Box<dyn Error>> {
         let (_, port) = surimi::MockServer::default()
             .responses(vec![
-                json!({"hello": "world"}),
-                json!({"hello": "world"}),
-                json!({"hello": "world"}),
+                json!({"requestId": "0", "hello": "world"}),
+                json!({"requestId": "1", "hello": "world"}),
+                json!({"requestId": "2", "hello": "world"}),
             ])
             .start()
             .await?;
 
-        let mut ws = WebSocket::new("localhost", Some(WebSocketOptions::new().port(port)));
+        let ws = WebSocket::new("localhost", Some(WebSocketOptions::new().port(port)));
         ws.connect().await?;
 
-        for _ in 0..2 {
-            let raw = &ws.send("Trigger some server responses".into()).await?;
-            assert_eq!(raw.to_string(), json!({"hello": "world"}).to_string());
+        for i in 0..2 {
+            let raw = &ws.send(json!({ "requestId": i.to_string() })).await?;
+            assert_eq!(
+                raw.to_string(),
+                json!({"requestId": i.to_string(), "hello": "world"}).to_string()
+            );
         }
 
         ws.disconnect().await?;
         Ok(())
     }
 
+    #[test]
+    fn should_set_ping_interval() {
+        let options = WebSocketOptions::new().ping_interval(Duration::from_secs(5));
+        assert_eq!(options.ping_interval, Some(Duration::from_secs(5)));
+        assert_eq!(WebSocketOptions::default().ping_interval, None);
+    }
+
+    #[test]
+    fn should_enable_ssl_with_tls_options() {
+        let ws = WebSocket::new(
+            "localhost",
+            Some(WebSocketOptions::new().tls(TlsOptions::new())),
+        );
+        assert_eq!(ws.get_url(), "wss://localhost:7512");
+    }
+
+    #[test]
+    fn should_enable_ssl_when_accepting_invalid_certs() {
+        let ws = WebSocket::new(
+            "localhost",
+            Some(WebSocketOptions::new().danger_accept_invalid_certs(true)),
+        );
+        assert_eq!(ws.get_url(), "wss://localhost:7512");
+    }
+
+    #[test]
+    fn should_keep_tls_options_when_accepting_invalid_certs() {
+        let options = WebSocketOptions::new()
+            .tls(TlsOptions::new().client_identity(b"cert", b"key"))
+            .danger_accept_invalid_certs(true);
+
+        let tls = options.tls.unwrap();
+        assert!(tls.danger_accept_invalid_certs);
+        assert_eq!(tls.client_key, Some(b"key".to_vec()));
+    }
+
+    #[test]
+    fn should_configure_message_size_limits() {
+        let config = WebSocketOptions::new()
+            .max_message_size(256 << 20)
+            .websocket_config();
+        let default = WebSocketConfig::default();
+
+        assert_eq!(config.max_message_size, Some(256 << 20));
+        assert_eq!(config.max_frame_size, default.max_frame_size);
+    }
+
+    #[async_std::test]
+    async fn should_reject_messages_above_limit() -> Result<(), Box<dyn Error>> {
+        let (_, port) = MockServer::default()
+            .responses(vec![
+                json!({"requestId": "foo", "result": "x".repeat(1024)}),
+            ])
+            .start()
+            .await?;
+
+        let ws = WebSocket::new(
+            "localhost",
+            Some(WebSocketOptions::new().port(port).max_message_size(512)),
+        );
+        ws.connect().await?;
+
+        assert!(ws.send(json!({"requestId": "foo"})).await.is_err());
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn should_tune_tcp_socket() -> Result<(), Box<dyn Error>> {
+        let listener = async_std::net::TcpListener::bind("127.0.0.1:0").await?;
+        let stream = TcpStream::connect(listener.local_addr()?).await?;
+        let ws = WebSocket::new(
+            "localhost",
+            Some(
+                WebSocketOptions::new()
+                    .tcp_nodelay(true)
+                    .tcp_keepalive(Duration::from_secs(60)),
+            ),
+        );
+
+        ws.shared.tune(&stream)?;
+
+        let socket = SockRef::from(&stream);
+        assert!(socket.nodelay()?);
+        assert!(socket.keepalive()?);
+        Ok(())
+    }
+
+    #[test]
+    fn should_send_cookies_on_handshake() -> Result<(), Box<dyn Error>> {
+        let jar = CookieJar::new();
+        jar.store("SERVERID=node2; Path=/");
+
+        let ws = WebSocket::new("localhost", Some(WebSocketOptions::new().cookies(jar)));
+        let request = ws.shared.handshake_request("localhost")?;
+
+        assert_eq!(request.headers()["Cookie"], "SERVERID=node2");
+        Ok(())
+    }
+
+    #[test]
+    fn should_merge_cookies_with_cookie_headers() -> Result<(), Box<dyn Error>> {
+        let jar = CookieJar::new();
+        jar.store("SERVERID=node2; Path=/");
+
+        let options = WebSocketOptions::new()
+            .header("Cookie", "session=42")
+            .cookies(jar);
+        let ws = WebSocket::new("localhost", Some(options));
+        let request = ws.shared.handshake_request("localhost")?;
+
+        let cookies: Vec<_> = request.headers().get_all("Cookie").iter().collect();
+        assert_eq!(cookies, vec!["session=42; SERVERID=node2"]);
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn should_store_cookies_set_on_handshake() -> Result<(), Box<dyn Error>> {
+        use async_tungstenite::tungstenite::handshake::server::{Request, Response};
+
+        let listener = async_std::net::TcpListener::bind("127.0.0.1:0").await?;
+        let port = listener.local_addr()?.port();
+
+        async_std::task::spawn(async move {
+            let (socket, _) = listener.accept().await.unwrap();
+            let _ws = async_tungstenite::accept_hdr_async(
+                socket,
+                |_: &Request, mut response: Response| {
+                    response
+                        .headers_mut()
+                        .append(SET_COOKIE, HeaderValue::from_static("SERVERID=node1"));
+                    Ok(response)
+                },
+            )
+            .await
+            .unwrap();
+            async_std::task::sleep(Duration::from_secs(1)).await;
+        });
+
+        let jar = CookieJar::new();
+        let ws = WebSocket::new(
+            "127.0.0.1",
+            Some(WebSocketOptions::new().port(port).cookies(jar.clone())),
+        );
+        ws.connect().await?;
+
+        assert_eq!(jar.header(), Some("SERVERID=node1".to_string()));
+        Ok(())
+    }
+
+    #[test]
+    fn should_present_server_name_on_handshake() -> Result<(), Box<dyn Error>> {
+        let tls = TlsOptions::new().server_name("kuzzle.example.com");
+        let ws = WebSocket::new("127.0.0.1", Some(WebSocketOptions::new().tls(tls)));
+        let request = ws.shared.handshake_request("127.0.0.1")?;
+
+        assert_eq!(request.uri().host(), Some("kuzzle.example.com"));
+        Ok(())
+    }
+
+    #[test]
+    fn should_add_headers_to_handshake() -> Result<(), Box<dyn Error>> {
+        let options = WebSocketOptions::new()
+            .header("Authorization", "Bearer foo")
+            .header("Cookie", "a=1")
+            .header("Cookie", "b=2");
+        let ws = WebSocket::new("localhost", Some(options));
+
+        let request = ws.shared.handshake_request("localhost")?;
+        let headers = request.headers();
+
+        assert_eq!(request.uri(), "ws://localhost:7512/");
+        assert_eq!(headers["Authorization"], "Bearer foo");
+        assert_eq!(headers.get_all("Cookie").iter().count(), 2);
+
+        Ok(())
+    }
+
+    #[test]
+    fn should_not_build_handshake_with_invalid_header() {
+        let options = WebSocketOptions::new().header("Bad Header", "foo");
+        let ws = WebSocket::new("localhost", Some(options));
+
+        assert!(ws.shared.handshake_request("localhost").is_err());
+    }
+
+    #[async_std::test]
+    async fn should_not_send_with_timeout_before_connect() {
+        let ws = WebSocket::new(
+            "localhost",
+            Some(WebSocketOptions::new().request_timeout(Duration::from_millis(10))),
+        );
+        let res = ws.send(json!({"requestId": "foo"})).await;
+
+        assert!(res.is_err());
+    }
+
     #[async_std::test]
     async fn should_not_send_before_connect() -> Result<(), Box<dyn Error>> {
         let (_, port) = surimi::MockServer::default()
@@ -238,21 +1397,92 @@ mod tests {
             .start()
             .await?;
 
-        let mut ws = WebSocket::new("localhost", Some(WebSocketOptions::new().port(port)));
-        let res = ws.send("Some request".into()).await;
+        let ws = WebSocket::new("localhost", Some(WebSocketOptions::new().port(port)));
+        let res = ws.send(json!({"requestId": "foo"})).await;
 
         assert!(res.is_err());
         Ok(())
     }
 
+    #[async_std::test]
+    async fn should_not_send_without_request_id() -> Result<(), Box<dyn Error>> {
+        let (_, port) = surimi::MockServer::default().start().await?;
+
+        let ws = WebSocket::new("localhost", Some(WebSocketOptions::new().port(port)));
+        ws.connect().await?;
+
+        assert!(ws.send(json!({"hello": "world"})).await.is_err());
+        assert!(ws.shared.link().unwrap().pending.is_empty());
+
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn should_route_responses_by_request_id() -> Result<(), Box<dyn Error>> {
+        let (_, port) = surimi::MockServer::default()
+            .responses(vec![
+                json!({"requestId": "unknown", "hello": "world"}),
+                json!({"requestId": "bar", "hello": "world"}),
+            ])
+            .start()
+            .await?;
+
+        let ws = WebSocket::new("localhost", Some(WebSocketOptions::new().port(port)));
+        ws.connect().await?;
+
+        let timeout = Duration::from_millis(100);
+        let err = ws
+            .send_with_timeout(json!({"requestId": "foo"}), timeout)
+            .await
+            .err()
+            .unwrap();
+        assert_eq!(
+            err.downcast_ref::<ProtocolError>(),
+            Some(&ProtocolError::Timeout(timeout))
+        );
+        assert!(ws.shared.link().unwrap().pending.is_empty());
+
+        let raw = ws.send(json!({"requestId": "bar"})).await?;
+        assert_eq!(
+            raw,
+            json!({"requestId": "bar", "hello": "world"}).to_string()
+        );
+
+        ws.disconnect().await?;
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn should_stream_unsolicited_messages() -> Result<(), Box<dyn Error>> {
+        let notification = json!({"requestId": "unknown", "type": "document"});
+        let (_, port) = surimi::MockServer::default()
+            .responses(vec![notification.clone()])
+            .start()
+            .await?;
+
+        let ws = WebSocket::new("localhost", Some(WebSocketOptions::new().port(port)));
+        let mut incoming = ws.incoming();
+        ws.connect().await?;
+
+        let timeout = Duration::from_millis(100);
+        assert!(ws
+            .send_with_timeout(json!({"requestId": "foo"}), timeout)
+            .await
+            .is_err());
+        assert_eq!(incoming.next().await, Some(notification.to_string()));
+
+        ws.disconnect().await?;
+        Ok(())
+    }
+
     #[async_std::test]
     async fn should_send_but_no_response() -> Result<(), Box<dyn Error>> {
         let (_, port) = surimi::MockServer::default().start().await?;
 
-        let mut ws = WebSocket::new("localhost", Some(WebSocketOptions::new().port(port)));
+        let ws = WebSocket::new("localhost", Some(WebSocketOptions::new().port(port)));
         ws.connect().await?;
 
-        let res = ws.send("Some request".into()).await;
+        let res = ws.send(json!({"requestId": "foo"})).await;
         assert!(res.is_err());
 
         Ok(())
@@ -0,0 +1,419 @@
+use futures_channel::oneshot;
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+
+use crate::queue_store::QueueStore;
+use crate::types::Request;
+
+pub type DiscardedCallback = Box<dyn Fn(&[Request]) + Send + Sync>;
+/// Tells whether a request issued while reconnecting is queued (`true`) or
+/// discarded (`false`)
+pub type QueueFilter = Box<dyn Fn(&Request) -> bool + Send + Sync>;
+
+/// A request waiting in the queue, told whether it should be sent (`true`)
+/// or discarded (`false`)
+struct Queued {
+    request: Request,
+    release: oneshot::Sender<bool>,
+}
+
+#[derive(Default)]
+struct Inner {
+    requests: Mutex<VecDeque<Queued>>,
+    on_discarded: RwLock<Vec<DiscardedCallback>>,
+    filter: RwLock<Option<QueueFilter>>,
+    deduplicate: AtomicBool,
+    store: RwLock<Option<Arc<dyn QueueStore>>>,
+}
+
+/// Requests issued while the connection is being re-established, held until
+/// they can be sent. The queue is played automatically once reconnected.
+///
+/// Clones share the same queue, so that it can be controlled from another
+/// task than the one waiting for the queued queries.
+///
+/// # Example
+///
+/// ```
+/// use kuzzle::protocols::InMemory;
+/// use kuzzle::types::Request;
+/// use kuzzle::Kuzzle;
+///
+/// let kuzzle = Kuzzle::new(InMemory::new());
+/// let queue = kuzzle.queue();
+///
+/// queue.on_discarded(Box::new(|requests: &[Request]| {
+///     eprintln!("{} requests were never sent", requests.len());
+/// }));
+/// queue.flush();
+/// ```
+#[derive(Clone, Default)]
+pub struct OfflineQueue(Arc<Inner>);
+
+impl OfflineQueue {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Number of requests waiting to be sent
+    pub fn len(&self) -> usize {
+        self.0
+            .requests
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|queued| !queued.release.is_canceled())
+            .count()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Requests waiting to be sent, oldest first
+    pub fn requests(&self) -> Vec<Request> {
+        self.0
+            .requests
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|queued| !queued.release.is_canceled())
+            .map(|queued| queued.request.clone())
+            .collect()
+    }
+
+    /// Send every queued request, oldest first
+    pub fn play(&self) {
+        let requests: Vec<Queued> = self.0.requests.lock().unwrap().drain(..).collect();
+
+        for queued in requests {
+            self.unpersist(&queued.request);
+            let _ = queued.release.send(true);
+        }
+    }
+
+    /// Drop every queued request: their queries fail, and the `on_discarded`
+    /// callbacks are given the dropped requests
+    pub fn flush(&self) {
+        let requests: Vec<Request> = self
+            .0
+            .requests
+            .lock()
+            .unwrap()
+            .drain(..)
+            .filter_map(|queued| {
+                self.unpersist(&queued.request);
+                match queued.release.send(false) {
+                    Ok(()) => Some(queued.request),
+                    Err(_) => None,
+                }
+            })
+            .collect();
+
+        if requests.is_empty() {
+            return;
+        }
+
+        for callback in self.0.on_discarded.read().unwrap().iter() {
+            callback(&requests);
+        }
+    }
+
+    /// Register a callback invoked with the requests dropped by `flush`
+    pub fn on_discarded(&self, callback: DiscardedCallback) {
+        self.0.on_discarded.write().unwrap().push(callback);
+    }
+
+    /// Only queue the requests accepted by the given filter, the other ones
+    /// being discarded right away, or queue every request with `None`, which
+    /// is the default
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use kuzzle::protocols::InMemory;
+    /// use kuzzle::types::Request;
+    /// use kuzzle::Kuzzle;
+    ///
+    /// let kuzzle = Kuzzle::new(InMemory::new());
+    ///
+    /// // Searches are pointless once reconnected, only keep writes
+    /// kuzzle.queue().set_filter(Some(Box::new(|request: &Request| {
+    ///     request.controller == "document" && request.action != "search"
+    /// })));
+    /// ```
+    pub fn set_filter(&self, filter: Option<QueueFilter>) {
+        *self.0.filter.write().unwrap() = filter;
+    }
+
+    /// Whether queuing a request identical to an already queued one, with the
+    /// same controller, action, index, collection and body, discards the
+    /// older one. Disabled by default.
+    pub fn set_deduplicate(&self, enabled: bool) {
+        self.0.deduplicate.store(enabled, Ordering::SeqCst);
+    }
+
+    /// Persist the queued requests in the given store, or only keep them in
+    /// memory with `None`, which is the default
+    pub(crate) fn set_store(&self, store: Option<Arc<dyn QueueStore>>) {
+        *self.0.store.write().unwrap() = store;
+    }
+
+    /// Save a request in the store, if any. Its token is not written, the
+    /// current one being sent once it is played.
+    fn persist(&self, request: &Request) {
+        if let Some(store) = &*self.0.store.read().unwrap() {
+            let mut request = request.clone();
+            request.jwt = None;
+            let _ = store.save(&request);
+        }
+    }
+
+    fn unpersist(&self, request: &Request) {
+        if let Some(store) = &*self.0.store.read().unwrap() {
+            let _ = store.remove(&request.request_id);
+        }
+    }
+
+    /// Queue a request, resolving with `true` once it should be sent, or
+    /// `false` if it was discarded
+    pub(crate) fn push(&self, request: Request) -> oneshot::Receiver<bool> {
+        self.enqueue(request, true)
+    }
+
+    /// Queue a request loaded from the store, which already holds it
+    pub(crate) fn restore(&self, request: Request) -> oneshot::Receiver<bool> {
+        self.enqueue(request, false)
+    }
+
+    fn enqueue(&self, request: Request, persist: bool) -> oneshot::Receiver<bool> {
+        let (release, released) = oneshot::channel();
+
+        let accepted = match &*self.0.filter.read().unwrap() {
+            Some(filter) => filter(&request),
+            None => true,
+        };
+        if !accepted {
+            let _ = release.send(false);
+            for callback in self.0.on_discarded.read().unwrap().iter() {
+                callback(&[request.clone()]);
+            }
+            return released;
+        }
+
+        let mut requests = self.0.requests.lock().unwrap();
+        let duplicates: Vec<Request> = match self.0.deduplicate.load(Ordering::SeqCst) {
+            true => {
+                let (duplicates, kept) = requests
+                    .drain(..)
+                    .partition(|queued| same_query(&queued.request, &request));
+                *requests = kept;
+
+                duplicates
+                    .into_iter()
+                    .filter_map(|queued: Queued| {
+                        self.unpersist(&queued.request);
+                        match queued.release.send(false) {
+                            Ok(()) => Some(queued.request),
+                            Err(_) => None,
+                        }
+                    })
+                    .collect()
+            }
+            false => Vec::new(),
+        };
+        if persist {
+            self.persist(&request);
+        }
+        requests.push_back(Queued { request, release });
+        drop(requests);
+
+        if !duplicates.is_empty() {
+            for callback in self.0.on_discarded.read().unwrap().iter() {
+                callback(&duplicates);
+            }
+        }
+
+        released
+    }
+}
+
+/// Whether two requests perform the same query: everything but their
+/// `requestId`, token and volatile data must match
+fn same_query(a: &Request, b: &Request) -> bool {
+    a.controller == b.controller
+        && a.action == b.action
+        && a.index == b.index
+        && a.collection == b.collection
+        && a.id == b.id
+        && a.body == b.body
+        && a.refresh == b.refresh
+        && a.kind == b.kind
+        && a.from == b.from
+        && a.size == b.size
+        && a.scroll == b.scroll
+        && a.scroll_id == b.scroll_id
+        && a.source == b.source
+        && a.fetch_source == b.fetch_source
+        && a.source_includes == b.source_includes
+        && a.source_excludes == b.source_excludes
+        && a.retry_on_conflict == b.retry_on_conflict
+        && a.headers == b.headers
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::request;
+    use std::error::Error;
+
+    #[async_std::test]
+    async fn should_release_requests_when_played() -> Result<(), Box<dyn Error>> {
+        let queue = OfflineQueue::new();
+        let released = queue.push(request!({"controller": "server", "action": "now"})?);
+        assert_eq!(queue.len(), 1);
+
+        queue.play();
+
+        assert!(queue.is_empty());
+        assert_eq!(released.await, Ok(true));
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn should_notify_discarded_requests() -> Result<(), Box<dyn Error>> {
+        let queue = OfflineQueue::new();
+        let discarded = Arc::new(Mutex::new(Vec::new()));
+        let notified = discarded.clone();
+        queue.on_discarded(Box::new(move |requests: &[Request]| {
+            notified
+                .lock()
+                .unwrap()
+                .extend(requests.iter().map(|request| request.action.clone()));
+        }));
+
+        let released = queue.push(request!({"controller": "server", "action": "now"})?);
+        drop(queue.push(request!({"controller": "server", "action": "info"})?));
+        queue.flush();
+
+        assert_eq!(released.await, Ok(false));
+        assert_eq!(*discarded.lock().unwrap(), vec!["now".to_string()]);
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn should_discard_filtered_requests() -> Result<(), Box<dyn Error>> {
+        let queue = OfflineQueue::new();
+        queue.set_filter(Some(Box::new(|request: &Request| {
+            request.action != "search"
+        })));
+
+        let search = queue.push(request!({"controller": "document", "action": "search"})?);
+        let create = queue.push(request!({"controller": "document", "action": "create"})?);
+
+        assert_eq!(search.await, Ok(false));
+        assert_eq!(queue.requests().len(), 1);
+
+        queue.play();
+        assert_eq!(create.await, Ok(true));
+        Ok(())
+    }
+
+    #[derive(Default)]
+    struct MemoryStore(Mutex<Vec<Request>>);
+
+    impl QueueStore for MemoryStore {
+        fn save(&self, request: &Request) -> Result<(), Box<dyn Error>> {
+            self.0.lock().unwrap().push(request.clone());
+            Ok(())
+        }
+
+        fn remove(&self, request_id: &str) -> Result<(), Box<dyn Error>> {
+            self.0
+                .lock()
+                .unwrap()
+                .retain(|request| request.request_id != request_id);
+            Ok(())
+        }
+
+        fn load(&self) -> Result<Vec<Request>, Box<dyn Error>> {
+            Ok(Vec::new())
+        }
+    }
+
+    #[async_std::test]
+    async fn should_persist_queued_requests() -> Result<(), Box<dyn Error>> {
+        let queue = OfflineQueue::new();
+        let store = Arc::new(MemoryStore::default());
+        queue.set_store(Some(store.clone()));
+
+        let released = queue.push(request!({
+            "controller": "document",
+            "action": "create",
+            "jwt": "eyJhbGciOi"
+        })?);
+        assert_eq!(store.0.lock().unwrap().len(), 1);
+        assert_eq!(store.0.lock().unwrap()[0].jwt, None);
+
+        queue.play();
+        assert_eq!(released.await, Ok(true));
+        assert!(store.0.lock().unwrap().is_empty());
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn should_deduplicate_requests() -> Result<(), Box<dyn Error>> {
+        let queue = OfflineQueue::new();
+        queue.set_deduplicate(true);
+
+        let update = |position: u64| {
+            request!({
+                "controller": "document",
+                "action": "update",
+                "index": "fleet",
+                "collection": "trucks",
+                "body": {"position": position}
+            })
+        };
+
+        let first = queue.push(update(1)?);
+        let other = queue.push(update(2)?);
+        let latest = queue.push(update(1)?);
+
+        assert_eq!(first.await, Ok(false));
+        assert_eq!(queue.len(), 2);
+        assert_eq!(queue.requests()[1].body, update(1)?.body);
+
+        queue.play();
+        assert_eq!(other.await, Ok(true));
+        assert_eq!(latest.await, Ok(true));
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn should_not_deduplicate_other_documents() -> Result<(), Box<dyn Error>> {
+        let queue = OfflineQueue::new();
+        queue.set_deduplicate(true);
+
+        let update = |id: &str| {
+            request!({
+                "controller": "document",
+                "action": "update",
+                "index": "fleet",
+                "collection": "trucks",
+                "_id": id,
+                "body": {"position": 1}
+            })
+        };
+
+        let first = queue.push(update("truck-1")?);
+        let second = queue.push(update("truck-2")?);
+        assert_eq!(queue.len(), 2);
+
+        queue.play();
+        assert_eq!(first.await, Ok(true));
+        assert_eq!(second.await, Ok(true));
+        Ok(())
+    }
+}
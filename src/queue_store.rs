@@ -0,0 +1,153 @@
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+#[cfg(not(feature = "wasm"))]
+use std::fs::{self, File, OpenOptions};
+#[cfg(not(feature = "wasm"))]
+use std::io::{BufRead, BufReader, Write};
+#[cfg(not(feature = "wasm"))]
+use std::path::{Path, PathBuf};
+#[cfg(not(feature = "wasm"))]
+use std::sync::Mutex;
+
+use crate::types::Request;
+
+/// Persistent backing store of the offline queue, so that queued requests
+/// survive a process restart, e.g. on devices with intermittent
+/// connectivity. Failing to persist a request doesn't prevent it from being
+/// queued.
+pub trait QueueStore: Send + Sync {
+    /// Persist a request entering the queue
+    fn save(&self, request: &Request) -> Result<(), Box<dyn Error>>;
+
+    /// Forget a request which left the queue, sent or discarded
+    fn remove(&self, request_id: &str) -> Result<(), Box<dyn Error>>;
+
+    /// Requests still persisted, oldest first
+    fn load(&self) -> Result<Vec<Request>, Box<dyn Error>>;
+}
+
+/// Change made to the queue, as appended to a `FileStore`
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum Entry {
+    Save(Request),
+    Remove(String),
+}
+
+/// Queue store appending the changes made to the queue to a file, one JSON
+/// entry per line. The file is compacted when loaded.
+///
+/// # Example
+///
+/// ```no_run
+/// use kuzzle::protocols::WebSocket;
+/// use kuzzle::queue_store::FileStore;
+/// use kuzzle::Kuzzle;
+///
+/// # async_std::task::block_on(async {
+/// let kuzzle = Kuzzle::new(WebSocket::new("localhost", None));
+/// let store = FileStore::open("/var/lib/sensor/queue.jsonl").unwrap();
+///
+/// // Queries queued before the last shutdown are sent again once connected
+/// kuzzle.set_queue_store(store).await.unwrap();
+/// kuzzle.connect().await.unwrap();
+/// # })
+/// ```
+#[cfg(not(feature = "wasm"))]
+pub struct FileStore {
+    path: PathBuf,
+    file: Mutex<File>,
+}
+
+#[cfg(not(feature = "wasm"))]
+impl FileStore {
+    /// Open the given file, creating it if needed
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, Box<dyn Error>> {
+        let path = path.as_ref().to_path_buf();
+        let file = OpenOptions::new().create(true).append(true).open(&path)?;
+
+        Ok(Self {
+            path,
+            file: Mutex::new(file),
+        })
+    }
+
+    fn append(&self, entry: &Entry) -> Result<(), Box<dyn Error>> {
+        let mut line = serde_json::to_string(entry)?;
+        line.push('\n');
+
+        let mut file = self.file.lock().unwrap();
+        file.write_all(line.as_bytes())?;
+        file.flush()?;
+        Ok(())
+    }
+}
+
+#[cfg(not(feature = "wasm"))]
+impl QueueStore for FileStore {
+    fn save(&self, request: &Request) -> Result<(), Box<dyn Error>> {
+        self.append(&Entry::Save(request.clone()))
+    }
+
+    fn remove(&self, request_id: &str) -> Result<(), Box<dyn Error>> {
+        self.append(&Entry::Remove(request_id.to_string()))
+    }
+
+    fn load(&self) -> Result<Vec<Request>, Box<dyn Error>> {
+        let mut file = self.file.lock().unwrap();
+        let mut requests: Vec<Request> = Vec::new();
+
+        for line in BufReader::new(File::open(&self.path)?).lines() {
+            let line = line?;
+            // A line cut short by a crash is ignored
+            match serde_json::from_str(&line) {
+                Ok(Entry::Save(request)) => requests.push(request),
+                Ok(Entry::Remove(id)) => requests.retain(|request| request.request_id != id),
+                Err(_) => (),
+            }
+        }
+
+        // Compact the file, so that it doesn't grow forever
+        let compacted = self.path.with_extension("compacting");
+        let mut content = String::new();
+        for request in &requests {
+            content.push_str(&serde_json::to_string(&Entry::Save(request.clone()))?);
+            content.push('\n');
+        }
+        fs::write(&compacted, content)?;
+        fs::rename(&compacted, &self.path)?;
+        *file = OpenOptions::new().append(true).open(&self.path)?;
+
+        Ok(requests)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::request;
+
+    #[test]
+    fn should_load_the_requests_left() -> Result<(), Box<dyn Error>> {
+        let path = std::env::temp_dir().join(format!("kuzzle-queue-{}.jsonl", std::process::id()));
+        let _ = fs::remove_file(&path);
+
+        let store = FileStore::open(&path)?;
+        let create = request!({"controller": "document", "action": "create"})?;
+        let update = request!({"controller": "document", "action": "update"})?;
+        store.save(&create)?;
+        store.save(&update)?;
+        store.remove(&create.request_id)?;
+        drop(store);
+
+        let store = FileStore::open(&path)?;
+        let requests = store.load()?;
+        assert_eq!(requests.len(), 1);
+        assert_eq!(requests[0].request_id, update.request_id);
+
+        // Compacted to the requests left
+        assert_eq!(fs::read_to_string(&path)?.lines().count(), 1);
+        fs::remove_file(&path)?;
+        Ok(())
+    }
+}
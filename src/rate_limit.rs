@@ -0,0 +1,124 @@
+use std::sync::Mutex;
+use std::time::Duration;
+
+use crate::runtime::Instant;
+
+/// Client-side limit on the rate of the queries, so that a runaway loop
+/// doesn't trip the Kuzzle rate limits. Queries beyond the limit wait for
+/// their turn, or fail right away with `fail_fast`.
+///
+/// # Example
+///
+/// ```
+/// use kuzzle::protocols::InMemory;
+/// use kuzzle::rate_limit::RateLimit;
+/// use kuzzle::Kuzzle;
+///
+/// let kuzzle = Kuzzle::new(InMemory::new());
+///
+/// // 20 queries per second on average, up to 50 at once
+/// kuzzle.set_rate_limit(Some(RateLimit::new(20.0).burst(50)));
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct RateLimit {
+    /// Queries allowed per second
+    pub rate: f64,
+    /// Queries allowed at once, after a period of inactivity
+    pub burst: u32,
+    pub fail_fast: bool,
+}
+
+impl RateLimit {
+    /// Allow `rate` queries per second, in bursts of as many queries
+    pub fn new(rate: f64) -> Self {
+        Self {
+            rate,
+            burst: (rate.ceil() as u32).max(1),
+            fail_fast: false,
+        }
+    }
+
+    pub fn burst(mut self, burst: u32) -> Self {
+        self.burst = burst.max(1);
+        self
+    }
+
+    /// Whether queries beyond the limit fail instead of waiting
+    pub fn fail_fast(mut self, enabled: bool) -> Self {
+        self.fail_fast = enabled;
+        self
+    }
+}
+
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+/// Token bucket enforcing a rate limit, starting full
+pub(crate) struct TokenBucket {
+    limit: RateLimit,
+    bucket: Mutex<Bucket>,
+}
+
+impl TokenBucket {
+    pub(crate) fn new(limit: RateLimit) -> Self {
+        Self {
+            bucket: Mutex::new(Bucket {
+                tokens: limit.burst as f64,
+                updated: Instant::now(),
+            }),
+            limit,
+        }
+    }
+
+    /// Take a token, telling how long to wait before sending the query, or
+    /// `None` if it must fail instead
+    pub(crate) fn reserve(&self) -> Option<Duration> {
+        let mut bucket = self.bucket.lock().unwrap();
+        let now = Instant::now();
+        let refilled = (now - bucket.updated).as_secs_f64() * self.limit.rate;
+
+        bucket.tokens = (bucket.tokens + refilled).min(self.limit.burst as f64);
+        bucket.updated = now;
+
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            return Some(Duration::from_secs(0));
+        }
+        if self.limit.fail_fast || self.limit.rate <= 0.0 {
+            return None;
+        }
+
+        // Borrow the token: the queries waiting take their turn in order
+        let wait = (1.0 - bucket.tokens) / self.limit.rate;
+        bucket.tokens -= 1.0;
+        Some(Duration::from_secs_f64(wait))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_fail_fast_beyond_burst() {
+        let bucket = TokenBucket::new(RateLimit::new(1.0).burst(2).fail_fast(true));
+
+        assert_eq!(bucket.reserve(), Some(Duration::from_secs(0)));
+        assert_eq!(bucket.reserve(), Some(Duration::from_secs(0)));
+        assert_eq!(bucket.reserve(), None);
+    }
+
+    #[test]
+    fn should_delay_queries_beyond_burst() {
+        let bucket = TokenBucket::new(RateLimit::new(10.0).burst(1));
+
+        assert_eq!(bucket.reserve(), Some(Duration::from_secs(0)));
+
+        let first = bucket.reserve().unwrap();
+        let second = bucket.reserve().unwrap();
+        assert!(first > Duration::from_millis(50) && first <= Duration::from_millis(100));
+        assert!(second > first + Duration::from_millis(50));
+    }
+}
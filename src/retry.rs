@@ -0,0 +1,189 @@
+use std::error::Error;
+use std::time::Duration;
+
+use crate::protocols::reconnect::backoff;
+use crate::protocols::ProtocolError;
+use crate::types::{KuzzleError, Request, Response};
+
+/// Action prefixes of the requests that can be sent twice without side
+/// effects
+const IDEMPOTENT_ACTIONS: [&str; 10] = [
+    "check", "count", "exists", "get", "info", "list", "mGet", "now", "scroll", "search",
+];
+
+/// Transient failures a query can be retried on
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RetryOn {
+    /// No response was received in time
+    Timeout,
+    /// The request could not be sent, e.g. while the connection is down
+    ConnectionError,
+    /// Kuzzle answered with a 503 status, being overloaded or starting
+    ServiceUnavailable,
+    /// Kuzzle answered with a 429 status, its rate limit being reached
+    TooManyRequests,
+    /// Kuzzle answered with a 504 status, one of its services being too slow
+    GatewayTimeout,
+}
+
+/// How queries failing transiently are retried: number of attempts,
+/// exponential backoff between them, and failures worth retrying.
+///
+/// Only requests that can safely be sent twice, such as searches, are
+/// retried unless `retry_writes` is enabled: a write whose response was lost
+/// may have been applied.
+///
+/// # Example
+///
+/// ```
+/// use kuzzle::protocols::InMemory;
+/// use kuzzle::retry::{RetryOn, RetryPolicy};
+/// use kuzzle::Kuzzle;
+/// use std::time::Duration;
+///
+/// let kuzzle = Kuzzle::new(InMemory::new());
+/// kuzzle.set_retry_policy(Some(
+///     RetryPolicy::new()
+///         .max_attempts(5)
+///         .initial_delay(Duration::from_millis(200))
+///         .retry_on(vec![RetryOn::Timeout, RetryOn::ServiceUnavailable]),
+/// ));
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct RetryPolicy {
+    pub max_attempts: u32,
+    pub initial_delay: Duration,
+    pub max_delay: Duration,
+    pub jitter: f64,
+    pub retry_on: Vec<RetryOn>,
+    pub retry_writes: bool,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 3,
+            initial_delay: Duration::from_millis(100),
+            max_delay: Duration::from_secs(5),
+            jitter: 0.2,
+            retry_on: vec![RetryOn::Timeout, RetryOn::ServiceUnavailable],
+            retry_writes: false,
+        }
+    }
+}
+
+impl RetryPolicy {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Maximum number of times a query is sent, first attempt included
+    pub fn max_attempts(mut self, attempts: u32) -> Self {
+        self.max_attempts = attempts.max(1);
+        self
+    }
+
+    /// Delay before the first retry, doubled after each failed one
+    pub fn initial_delay(mut self, delay: Duration) -> Self {
+        self.initial_delay = delay;
+        self
+    }
+
+    pub fn max_delay(mut self, delay: Duration) -> Self {
+        self.max_delay = delay;
+        self
+    }
+
+    /// Randomization of the delays, as a fraction of them
+    pub fn jitter(mut self, jitter: f64) -> Self {
+        self.jitter = jitter.clamp(0.0, 1.0);
+        self
+    }
+
+    /// Failures worth retrying
+    pub fn retry_on(mut self, failures: Vec<RetryOn>) -> Self {
+        self.retry_on = failures;
+        self
+    }
+
+    /// Whether requests that may not be safely sent twice, such as document
+    /// creations, are retried too
+    pub fn retry_writes(mut self, enabled: bool) -> Self {
+        self.retry_writes = enabled;
+        self
+    }
+
+    /// Delay to wait before the given retry, starting from 0
+    pub fn delay(&self, retry: u32) -> Duration {
+        backoff(self.initial_delay, self.max_delay, self.jitter, retry)
+    }
+
+    /// Whether a request may be retried at all
+    pub(crate) fn covers(&self, request: &Request) -> bool {
+        self.retry_writes
+            || IDEMPOTENT_ACTIONS
+                .iter()
+                .any(|prefix| request.action.starts_with(prefix))
+    }
+
+    /// Whether the outcome of an attempt is worth retrying
+    pub(crate) fn retries(&self, result: &Result<Response, Box<dyn Error>>) -> bool {
+        let failure = match result {
+            Ok(response) => match &response.error {
+                Some(KuzzleError::ServiceUnavailable(_)) => RetryOn::ServiceUnavailable,
+                Some(KuzzleError::TooManyRequests(_)) => RetryOn::TooManyRequests,
+                Some(KuzzleError::GatewayTimeout(_)) => RetryOn::GatewayTimeout,
+                _ => return false,
+            },
+            Err(error) => match error.downcast_ref::<ProtocolError>() {
+                Some(ProtocolError::Timeout(_)) => RetryOn::Timeout,
+                _ => RetryOn::ConnectionError,
+            },
+        };
+
+        self.retry_on.contains(&failure)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::request;
+    use serde_json::json;
+
+    fn response(status: u16) -> Result<Response, Box<dyn Error>> {
+        Ok(serde_json::from_value(json!({
+            "requestId": "foo",
+            "action": "search",
+            "controller": "document",
+            "status": status,
+            "error": {"status": status}
+        }))?)
+    }
+
+    #[test]
+    fn should_retry_transient_failures() {
+        let policy = RetryPolicy::new();
+        let timeout: Box<dyn Error> = Box::new(ProtocolError::Timeout(Duration::from_secs(1)));
+
+        assert!(policy.retries(&Err(timeout)));
+        assert!(policy.retries(&response(503)));
+        assert!(!policy.retries(&response(429)));
+        assert!(!policy.retries(&response(404)));
+        assert!(policy
+            .retry_on(vec![RetryOn::TooManyRequests])
+            .retries(&response(429)));
+    }
+
+    #[test]
+    fn should_only_retry_writes_when_allowed() -> Result<(), Box<dyn Error>> {
+        let search = request!({"controller": "document", "action": "search"})?;
+        let create = request!({"controller": "document", "action": "create"})?;
+
+        assert!(RetryPolicy::new().covers(&search));
+        assert!(!RetryPolicy::new().covers(&create));
+        assert!(RetryPolicy::new().retry_writes(true).covers(&create));
+
+        Ok(())
+    }
+}
@@ -0,0 +1,159 @@
+//! Thin layer over the async runtime selected through the crate features, so
+//! that protocols don't depend on a specific executor.
+//!
+//! `async-std` is used by default; enabling the `tokio` feature switches every
+//! protocol to tokio-based networking, timers and tasks. The `wasm` feature
+//! relies on the browser event loop instead, and provides no networking.
+
+#[cfg(not(any(feature = "async-std-runtime", feature = "tokio", feature = "wasm")))]
+compile_error!("one of the `async-std-runtime`, `tokio` or `wasm` features must be enabled");
+
+#[cfg(all(feature = "blocking", feature = "wasm"))]
+compile_error!("the `blocking` feature is not available with the `wasm` one");
+
+#[cfg(not(any(feature = "tokio", feature = "wasm")))]
+mod imp {
+    pub(crate) use async_native_tls::{Certificate, Identity, TlsConnector};
+    pub(crate) use async_std::io::{ReadExt as AsyncReadExt, WriteExt as AsyncWriteExt};
+    pub(crate) use async_std::net::TcpStream;
+    pub(crate) use async_tungstenite::async_std::client_async_tls_with_connector_and_config;
+    pub(crate) use async_tungstenite::async_std::ConnectStream;
+
+    pub(crate) use std::time::Instant;
+
+    use std::future::Future;
+    use std::time::Duration;
+
+    pub(crate) type TcpReadHalf = TcpStream;
+    pub(crate) type TcpWriteHalf = TcpStream;
+
+    /// Split a TCP stream so that it can be read and written concurrently
+    pub(crate) fn split_tcp(stream: TcpStream) -> (TcpReadHalf, TcpWriteHalf) {
+        (stream.clone(), stream)
+    }
+
+    pub(crate) fn spawn<F>(future: F)
+    where
+        F: Future<Output = ()> + Send + 'static,
+    {
+        async_std::task::spawn(future);
+    }
+
+    pub(crate) async fn sleep(duration: Duration) {
+        async_std::task::sleep(duration).await
+    }
+
+    /// Await `future` for at most `duration`, resolving with `None` if it elapsed
+    pub(crate) async fn timeout<F: Future>(duration: Duration, future: F) -> Option<F::Output> {
+        async_std::future::timeout(duration, future).await.ok()
+    }
+
+    /// Executor running futures to completion from synchronous code, the
+    /// spawned tasks running on the global async-std executor meanwhile
+    #[cfg(feature = "blocking")]
+    pub(crate) struct Blocker;
+
+    #[cfg(feature = "blocking")]
+    impl Blocker {
+        pub(crate) fn new() -> std::io::Result<Self> {
+            Ok(Blocker)
+        }
+
+        pub(crate) fn block_on<F: Future>(&self, future: F) -> F::Output {
+            async_std::task::block_on(future)
+        }
+    }
+}
+
+#[cfg(all(feature = "tokio", not(feature = "wasm")))]
+mod imp {
+    pub(crate) use async_tungstenite::tokio::client_async_tls_with_connector_and_config;
+    pub(crate) use async_tungstenite::tokio::ConnectStream;
+    pub(crate) use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    pub(crate) use tokio::net::TcpStream;
+    pub(crate) use tokio_native_tls::native_tls::{Certificate, Identity};
+    pub(crate) use tokio_native_tls::TlsConnector;
+
+    pub(crate) use tokio::net::tcp::{
+        OwnedReadHalf as TcpReadHalf, OwnedWriteHalf as TcpWriteHalf,
+    };
+
+    pub(crate) use std::time::Instant;
+
+    use std::future::Future;
+    use std::time::Duration;
+
+    /// Split a TCP stream so that it can be read and written concurrently
+    pub(crate) fn split_tcp(stream: TcpStream) -> (TcpReadHalf, TcpWriteHalf) {
+        stream.into_split()
+    }
+
+    pub(crate) fn spawn<F>(future: F)
+    where
+        F: Future<Output = ()> + Send + 'static,
+    {
+        tokio::spawn(future);
+    }
+
+    pub(crate) async fn sleep(duration: Duration) {
+        tokio::time::sleep(duration).await
+    }
+
+    /// Await `future` for at most `duration`, resolving with `None` if it elapsed
+    pub(crate) async fn timeout<F: Future>(duration: Duration, future: F) -> Option<F::Output> {
+        tokio::time::timeout(duration, future).await.ok()
+    }
+
+    /// Executor running futures to completion from synchronous code, owning
+    /// the runtime the spawned tasks keep running on between calls
+    #[cfg(feature = "blocking")]
+    pub(crate) struct Blocker(tokio::runtime::Runtime);
+
+    #[cfg(feature = "blocking")]
+    impl Blocker {
+        pub(crate) fn new() -> std::io::Result<Self> {
+            tokio::runtime::Builder::new_multi_thread()
+                .enable_all()
+                .build()
+                .map(Blocker)
+        }
+
+        pub(crate) fn block_on<F: Future>(&self, future: F) -> F::Output {
+            self.0.block_on(future)
+        }
+    }
+}
+
+#[cfg(feature = "wasm")]
+mod imp {
+    pub(crate) use instant::Instant;
+
+    use futures_util::future::{select, Either};
+    use send_wrapper::SendWrapper;
+    use std::future::Future;
+    use std::time::Duration;
+
+    pub(crate) fn spawn<F>(future: F)
+    where
+        F: Future<Output = ()> + Send + 'static,
+    {
+        wasm_bindgen_futures::spawn_local(future);
+    }
+
+    pub(crate) async fn sleep(duration: Duration) {
+        // Browser timers are bound to the single thread running the module
+        SendWrapper::new(gloo_timers::future::sleep(duration)).await
+    }
+
+    /// Await `future` for at most `duration`, resolving with `None` if it elapsed
+    pub(crate) async fn timeout<F: Future>(duration: Duration, future: F) -> Option<F::Output> {
+        futures_util::pin_mut!(future);
+
+        match select(future, Box::pin(sleep(duration))).await {
+            Either::Left((output, _)) => Some(output),
+            Either::Right(_) => None,
+        }
+    }
+}
+
+pub(crate) use self::imp::*;
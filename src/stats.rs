@@ -0,0 +1,141 @@
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use crate::protocols::ProtocolError;
+use crate::types::{KuzzleError, Response};
+
+/// Snapshot of the activity of a client since its creation, e.g. to export
+/// it to a monitoring system
+///
+/// # Example
+///
+/// ```
+/// use kuzzle::protocols::InMemory;
+/// use kuzzle::Kuzzle;
+///
+/// let kuzzle = Kuzzle::new(InMemory::new());
+/// let stats = kuzzle.stats();
+///
+/// println!("kuzzle_queries_total {}", stats.queries);
+/// for (class, count) in &stats.failures {
+///     println!("kuzzle_failures_total{{class=\"{}\"}} {}", class, count);
+/// }
+/// ```
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Stats {
+    /// Queries sent, the ones still queued excluded
+    pub queries: u64,
+    /// Failed queries by error class: `timeout` or `connection` when no
+    /// response was received, or the Kuzzle error kind, e.g. `not_found`
+    pub failures: BTreeMap<&'static str, u64>,
+    /// Average time taken by the queries to complete, retries included
+    pub average_latency: Option<Duration>,
+    /// Queries waiting in the offline queue
+    pub queue_depth: usize,
+    /// Connections re-established after being lost
+    pub reconnections: u64,
+}
+
+#[derive(Default)]
+struct Counters {
+    queries: AtomicU64,
+    failures: Mutex<BTreeMap<&'static str, u64>>,
+    latency_total: Mutex<Duration>,
+    reconnections: AtomicU64,
+}
+
+/// Collect the stats of a client, shared with its protocol hooks
+#[derive(Clone, Default)]
+pub(crate) struct StatsRecorder(Arc<Counters>);
+
+impl StatsRecorder {
+    /// Account for a sent query, given its outcome and how long it took
+    pub(crate) fn query(&self, result: &Result<Response, Box<dyn Error>>, latency: Duration) {
+        self.0.queries.fetch_add(1, Ordering::Relaxed);
+        *self.0.latency_total.lock().unwrap() += latency;
+
+        let class = match result {
+            Ok(response) => match &response.error {
+                Some(error) => error_class(error),
+                None => return,
+            },
+            Err(error) => match error.downcast_ref::<ProtocolError>() {
+                Some(ProtocolError::Timeout(_)) => "timeout",
+                _ => "connection",
+            },
+        };
+        *self.0.failures.lock().unwrap().entry(class).or_default() += 1;
+    }
+
+    pub(crate) fn reconnected(&self) {
+        self.0.reconnections.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn snapshot(&self, queue_depth: usize) -> Stats {
+        let queries = self.0.queries.load(Ordering::Relaxed);
+
+        Stats {
+            queries,
+            failures: self.0.failures.lock().unwrap().clone(),
+            average_latency: match queries {
+                0 => None,
+                count => Some(*self.0.latency_total.lock().unwrap() / count as u32),
+            },
+            queue_depth,
+            reconnections: self.0.reconnections.load(Ordering::Relaxed),
+        }
+    }
+}
+
+fn error_class(error: &KuzzleError) -> &'static str {
+    match error {
+        KuzzleError::PartialError(_) => "partial_error",
+        KuzzleError::BadRequest(_) => "bad_request",
+        KuzzleError::Unauthorized(_) => "unauthorized",
+        KuzzleError::Forbidden(_) => "forbidden",
+        KuzzleError::NotFound(_) => "not_found",
+        KuzzleError::PreconditionFailed(_) => "precondition_failed",
+        KuzzleError::SizeLimit(_) => "size_limit",
+        KuzzleError::TooManyRequests(_) => "too_many_requests",
+        KuzzleError::Internal(_) => "internal",
+        KuzzleError::ServiceUnavailable(_) => "service_unavailable",
+        KuzzleError::GatewayTimeout(_) => "gateway_timeout",
+        KuzzleError::Other(_) => "other",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn should_count_failures_by_class() -> Result<(), Box<dyn Error>> {
+        let recorder = StatsRecorder::default();
+        let not_found: Response = serde_json::from_value(json!({
+            "requestId": "foo",
+            "action": "get",
+            "controller": "document",
+            "status": 404,
+            "error": {"status": 404}
+        }))?;
+        let timeout: Box<dyn Error> = Box::new(ProtocolError::Timeout(Duration::from_secs(1)));
+
+        recorder.query(&Ok(not_found), Duration::from_millis(10));
+        recorder.query(&Err(timeout), Duration::from_millis(30));
+        recorder.reconnected();
+
+        let stats = recorder.snapshot(2);
+        assert_eq!(stats.queries, 2);
+        assert_eq!(stats.failures["not_found"], 1);
+        assert_eq!(stats.failures["timeout"], 1);
+        assert_eq!(stats.average_latency, Some(Duration::from_millis(20)));
+        assert_eq!(stats.queue_depth, 2);
+        assert_eq!(stats.reconnections, 1);
+
+        Ok(())
+    }
+}
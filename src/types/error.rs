@@ -0,0 +1,184 @@
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::error::Error;
+use std::fmt;
+
+/// Content of an error payload sent by Kuzzle
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct ErrorDetails {
+    /// Unique identifier of the error, e.g. `services.storage.not_found`
+    #[serde(default)]
+    pub id: String,
+    #[serde(default)]
+    pub code: u64,
+    #[serde(default)]
+    pub message: String,
+    #[serde(default)]
+    pub status: u16,
+    /// Any other field, such as the failures of a partial error
+    #[serde(flatten)]
+    pub props: Map<String, Value>,
+}
+
+/// Error returned by Kuzzle, classified by its HTTP status
+///
+/// # Example
+///
+/// ```
+/// use kuzzle::types::{KuzzleError, Response};
+/// use serde_json::json;
+///
+/// let response: Response = serde_json::from_value(json!({
+///     "requestId": "foo",
+///     "status": 404,
+///     "action": "get",
+///     "controller": "document",
+///     "error": {
+///         "id": "services.storage.not_found",
+///         "message": "Document \"bar\" not found in \"baz\":\"qux\".",
+///         "status": 404
+///     }
+/// }))
+/// .unwrap();
+///
+/// match response.error {
+///     Some(KuzzleError::NotFound(details)) => println!("{}", details.message),
+///     Some(error) => panic!("Unexpected error: {}", error),
+///     None => (),
+/// }
+/// ```
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(from = "ErrorDetails", into = "ErrorDetails")]
+pub enum KuzzleError {
+    /// Some actions of a multi-actions request failed (206)
+    PartialError(ErrorDetails),
+    /// Invalid request (400)
+    BadRequest(ErrorDetails),
+    /// Missing or invalid authentication (401)
+    Unauthorized(ErrorDetails),
+    /// Insufficient rights (403)
+    Forbidden(ErrorDetails),
+    /// Resource not found (404)
+    NotFound(ErrorDetails),
+    /// Version conflict or unmet precondition (412)
+    PreconditionFailed(ErrorDetails),
+    /// Request too large (413)
+    SizeLimit(ErrorDetails),
+    /// Rate limit reached (429)
+    TooManyRequests(ErrorDetails),
+    /// Unexpected server failure (500)
+    Internal(ErrorDetails),
+    /// Kuzzle or one of its services is overloaded or unavailable (503)
+    ServiceUnavailable(ErrorDetails),
+    /// A service took too long to answer (504)
+    GatewayTimeout(ErrorDetails),
+    /// Any other status
+    Other(ErrorDetails),
+}
+
+impl KuzzleError {
+    pub fn details(&self) -> &ErrorDetails {
+        match self {
+            KuzzleError::PartialError(details)
+            | KuzzleError::BadRequest(details)
+            | KuzzleError::Unauthorized(details)
+            | KuzzleError::Forbidden(details)
+            | KuzzleError::NotFound(details)
+            | KuzzleError::PreconditionFailed(details)
+            | KuzzleError::SizeLimit(details)
+            | KuzzleError::TooManyRequests(details)
+            | KuzzleError::Internal(details)
+            | KuzzleError::ServiceUnavailable(details)
+            | KuzzleError::GatewayTimeout(details)
+            | KuzzleError::Other(details) => details,
+        }
+    }
+
+    /// Unique identifier of the error, e.g. `services.storage.not_found`
+    pub fn id(&self) -> &str {
+        &self.details().id
+    }
+
+    pub fn status(&self) -> u16 {
+        self.details().status
+    }
+}
+
+impl From<ErrorDetails> for KuzzleError {
+    fn from(details: ErrorDetails) -> Self {
+        match details.status {
+            206 => KuzzleError::PartialError(details),
+            400 => KuzzleError::BadRequest(details),
+            401 => KuzzleError::Unauthorized(details),
+            403 => KuzzleError::Forbidden(details),
+            404 => KuzzleError::NotFound(details),
+            412 => KuzzleError::PreconditionFailed(details),
+            413 => KuzzleError::SizeLimit(details),
+            429 => KuzzleError::TooManyRequests(details),
+            500 => KuzzleError::Internal(details),
+            503 => KuzzleError::ServiceUnavailable(details),
+            504 => KuzzleError::GatewayTimeout(details),
+            _ => KuzzleError::Other(details),
+        }
+    }
+}
+
+impl From<KuzzleError> for ErrorDetails {
+    fn from(error: KuzzleError) -> Self {
+        error.details().clone()
+    }
+}
+
+impl fmt::Display for KuzzleError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let details = self.details();
+
+        match details.id.is_empty() {
+            true => write!(f, "{}", details.message),
+            false => write!(f, "{} ({})", details.message, details.id),
+        }
+    }
+}
+
+impl Error for KuzzleError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn should_classify_by_status() -> Result<(), Box<dyn Error>> {
+        let error: KuzzleError = serde_json::from_value(json!({
+            "id": "security.token.expired",
+            "code": 117506049,
+            "message": "Token expired",
+            "status": 401
+        }))?;
+
+        assert!(matches!(error, KuzzleError::Unauthorized(_)));
+        assert_eq!(error.id(), "security.token.expired");
+        assert_eq!(error.to_string(), "Token expired (security.token.expired)");
+
+        Ok(())
+    }
+
+    #[test]
+    fn should_keep_extra_fields() -> Result<(), Box<dyn Error>> {
+        let payload = json!({
+            "id": "api.process.incomplete_multiple_request",
+            "code": 33882113,
+            "message": "Some actions failed",
+            "status": 206,
+            "errors": [{"_id": "foo"}],
+            "count": 1
+        });
+        let error: KuzzleError = serde_json::from_value(payload.clone())?;
+
+        assert!(matches!(error, KuzzleError::PartialError(_)));
+        assert_eq!(error.details().props["count"], 1);
+        assert_eq!(serde_json::to_value(&error)?, payload);
+
+        Ok(())
+    }
+}
@@ -1,5 +1,7 @@
+pub mod error;
 pub mod request;
 pub mod response;
 
+pub use self::error::{ErrorDetails, KuzzleError};
 pub use self::request::Request;
 pub use self::response::Response;
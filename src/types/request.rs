@@ -1,5 +1,6 @@
 use serde::{Deserialize, Serialize};
 use serde_json::value::Value;
+use std::collections::BTreeMap;
 use uuid::Uuid;
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -10,8 +11,62 @@ pub struct Request {
     pub controller: String,
     pub index: Option<String>,
     pub collection: Option<String>,
+    /// Identifier of the document targeted, if any
+    #[serde(rename = "_id", default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
     pub jwt: Option<String>,
     pub body: Option<Value>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub volatile: Option<Value>,
+    /// Set to `wait_for` to get the response once the changes are searchable
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub refresh: Option<String>,
+    /// Kind of collections listed, e.g. `stored`
+    #[serde(rename = "type", default, skip_serializing_if = "Option::is_none")]
+    pub kind: Option<String>,
+    /// Offset of the first search result
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub from: Option<u64>,
+    /// Maximum number of search results
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub size: Option<u64>,
+    /// How long a search cursor is kept alive, e.g. `1m`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub scroll: Option<String>,
+    /// Search cursor to fetch the next page from
+    #[serde(rename = "scrollId", default, skip_serializing_if = "Option::is_none")]
+    pub scroll_id: Option<String>,
+    /// Whether the content of the documents changed is part of the response
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source: Option<bool>,
+    /// Whether the content of the documents read is part of the response
+    #[serde(rename = "_source", default, skip_serializing_if = "Option::is_none")]
+    pub fetch_source: Option<bool>,
+    /// Only fields of the documents read which are part of the response
+    #[serde(
+        rename = "_source_includes",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub source_includes: Option<Vec<String>>,
+    /// Fields of the documents read left out of the response
+    #[serde(
+        rename = "_source_excludes",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub source_excludes: Option<Vec<String>>,
+    /// Times an update is retried when the document is modified meanwhile
+    #[serde(
+        rename = "retryOnConflict",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub retry_on_conflict: Option<u32>,
+    /// Sent as HTTP headers by the HTTP protocol, and along with the other
+    /// fields by the other protocols
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub headers: Option<BTreeMap<String, String>>,
 }
 
 fn default_uuid_string() -> String {
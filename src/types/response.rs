@@ -1,6 +1,8 @@
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
 
+use super::{ErrorDetails, KuzzleError};
+
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct Response {
     #[serde(rename = "requestId")]
@@ -11,15 +13,33 @@ pub struct Response {
     pub controller: String,
     pub index: Option<String>,
     pub collection: Option<String>,
-    pub error: Option<Value>,
+    pub error: Option<KuzzleError>,
     pub result: Option<Value>,
     pub volatile: Option<Value>,
 }
 
+impl Response {
+    /// Turn a response whose status is not a success into its error, one
+    /// being forged from the status if Kuzzle sent none
+    pub fn error_for_status(self) -> Result<Response, KuzzleError> {
+        if (200..300).contains(&self.status) {
+            return Ok(self);
+        }
+
+        let status = self.status;
+        Err(self.error.unwrap_or_else(|| {
+            KuzzleError::from(ErrorDetails {
+                message: format!("Unexpected status {}", status),
+                status,
+                ..ErrorDetails::default()
+            })
+        }))
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
-    use serde_json::json;
 
     #[test]
     fn test_response_serde() {
@@ -34,7 +54,8 @@ mod tests {
                 "collection":"quux",
                 "error": {
                     "message":"error message",
-                    "code":1
+                    "code":1,
+                    "status":404
                 }
             }"#,
         );
@@ -49,10 +70,31 @@ mod tests {
         assert_eq!(response.index, Some(String::from("qux")));
         assert_eq!(response.collection, Some(String::from("quux")));
         assert_eq!(
-            response.error,
-            Some(json!({"message":"error message", "code":1 }))
+            response.error.as_ref().map(|error| error.to_string()),
+            Some(String::from("error message"))
         );
+        assert!(matches!(response.error, Some(KuzzleError::NotFound(_))));
         assert_eq!(response.result, None);
         assert_eq!(response.volatile, None);
     }
+
+    #[test]
+    fn should_turn_failures_into_errors() {
+        let response = |status: u16| -> Response {
+            serde_json::from_value(serde_json::json!({
+                "requestId": "0",
+                "status": status,
+                "action": "bar",
+                "controller": "baz"
+            }))
+            .unwrap()
+        };
+
+        assert!(response(200).error_for_status().is_ok());
+        assert!(response(206).error_for_status().is_ok());
+        assert!(matches!(
+            response(503).error_for_status(),
+            Err(KuzzleError::ServiceUnavailable(details)) if details.status == 503
+        ));
+    }
 }